@@ -0,0 +1,442 @@
+use crate::config::{same_file, EditorState, FileData, VisualSelection};
+use crate::undo::UndoTree;
+use crate::{load_undo_file, tab, update_modified, EditorConfig};
+use gap_buffer::GapBuffer;
+use std::collections::HashMap;
+use std::io;
+use std::mem;
+
+/// everything that belongs to one file rather than to the editor. the buffer being edited keeps
+/// all of this in EditorConfig, where the rest of the editor works on it, and switching swaps it
+/// with one of the buffers kept here
+pub struct Buffer {
+    pub number: usize,
+    pub file_data: FileData,
+    pub gap_buffer: GapBuffer<GapBuffer<char>>,
+    pub undo_tree: UndoTree,
+    pub modified: bool,
+    pub changedtick: u64,
+    pub saved_seq: usize,
+    pub marks: HashMap<char, (usize, usize)>,
+    pub last_visual: Option<VisualSelection>,
+    /// the first line and column on screen when the buffer was left
+    pub view: (usize, usize),
+}
+
+impl Buffer {
+    /// reads the file of `file_data` into a buffer, with the undo history saved for it. the
+    /// number is given when the buffer is added to the list
+    pub fn load(mut file_data: FileData, editor_state: &mut EditorState) -> io::Result<Buffer> {
+        let file_contents = file_data.read_contents()?;
+        let undo_tree = load_undo_file(&file_data, &file_contents, editor_state);
+        let gap_buffer = GapBuffer::build_nested(&file_contents);
+        Ok(Buffer {
+            number: 0,
+            file_data,
+            changedtick: gap_buffer.changedtick(),
+            gap_buffer,
+            saved_seq: undo_tree.seq_cur(),
+            undo_tree,
+            modified: false,
+            marks: HashMap::new(),
+            last_visual: None,
+            view: (0, 0),
+        })
+    }
+
+    /// the name :ls and the messages show for the buffer
+    fn name(&self) -> &str {
+        display_name(&self.file_data)
+    }
+}
+
+/// the name of the file, or what is shown instead for a buffer without one
+pub fn display_name(file_data: &FileData) -> &str {
+    file_data.file_name.as_deref().unwrap_or("[No Name]")
+}
+
+/// the buffers that aren't being edited, and which one is
+pub struct BufferList {
+    /// the number of the buffer being edited
+    pub current: usize,
+    /// the buffer edited before it, for :b #
+    pub alternate: Option<usize>,
+    /// every other buffer, in the order of their numbers
+    hidden: Vec<Buffer>,
+    next: usize,
+}
+
+impl BufferList {
+    /// the list of a freshly started editor, where the buffer being edited is number 1
+    pub fn new() -> BufferList {
+        BufferList {
+            current: 1,
+            alternate: None,
+            hidden: Vec::new(),
+            next: 2,
+        }
+    }
+    /// adds `buffer` under the next number, which is returned
+    pub fn add(&mut self, mut buffer: Buffer) -> usize {
+        buffer.number = self.next;
+        self.next += 1;
+        self.hidden.push(buffer);
+        self.next - 1
+    }
+    /// the numbers of every buffer, the one being edited among them
+    fn numbers(&self) -> Vec<usize> {
+        let mut numbers: Vec<usize> = self.hidden.iter().map(|buffer| buffer.number).collect();
+        numbers.push(self.current);
+        numbers.sort_unstable();
+        numbers
+    }
+    /// buffer `number` when it isn't the one being edited
+    pub fn get(&self, number: usize) -> Option<&Buffer> {
+        self.hidden.iter().find(|buffer| buffer.number == number)
+    }
+    /// a buffer that isn't being edited but still has changes that weren't written
+    pub fn first_modified(&self) -> Option<&Buffer> {
+        self.hidden.iter().find(|buffer| buffer.modified)
+    }
+}
+
+/// swaps the buffer being edited with `buffer`
+fn swap(editor_config: &mut EditorConfig, buffer: &mut Buffer) {
+    let editor_state = &mut editor_config.editor_state;
+    let viewport = &mut editor_config.viewport;
+    let mut view = (viewport.top, viewport.left);
+    mem::swap(&mut editor_config.buffers.current, &mut buffer.number);
+    mem::swap(&mut editor_config.file_data, &mut buffer.file_data);
+    mem::swap(&mut editor_config.gap_buffer, &mut buffer.gap_buffer);
+    mem::swap(&mut editor_config.undo_tree, &mut buffer.undo_tree);
+    mem::swap(&mut editor_config.modified, &mut buffer.modified);
+    mem::swap(&mut editor_config.changedtick, &mut buffer.changedtick);
+    mem::swap(&mut editor_config.saved_seq, &mut buffer.saved_seq);
+    mem::swap(&mut editor_state.marks, &mut buffer.marks);
+    mem::swap(&mut editor_state.last_visual, &mut buffer.last_visual);
+    mem::swap(&mut view, &mut buffer.view);
+    (viewport.top, viewport.left) = view;
+}
+
+/// makes buffer `number` the one being edited, keeping the one that was, changes and all.
+/// false when `number` isn't in the list or is already being edited
+pub fn enter(editor_config: &mut EditorConfig, number: usize) -> bool {
+    let buffers = &mut editor_config.buffers;
+    if number == buffers.current {
+        return false;
+    }
+    let Some(index) = buffers
+        .hidden
+        .iter()
+        .position(|buffer| buffer.number == number)
+    else {
+        return false;
+    };
+    let mut buffer = buffers.hidden.remove(index);
+    swap(editor_config, &mut buffer);
+    let buffers = &mut editor_config.buffers;
+    let index = buffers
+        .hidden
+        .partition_point(|hidden| hidden.number < buffer.number);
+    buffers.hidden.insert(index, buffer);
+    true
+}
+
+/// switches to buffer `number` in the window being edited, the buffer left behind becomes the
+/// alternate one
+fn switch_to(editor_config: &mut EditorConfig, number: usize) {
+    let current = editor_config.buffers.current;
+    if enter(editor_config, number) {
+        editor_config.buffers.alternate = Some(current);
+        file_info(editor_config);
+    }
+}
+
+/// tells where in which file the cursor is, like vim does after switching to it
+fn file_info(editor_config: &mut EditorConfig) {
+    let content_buffer = &editor_config.gap_buffer;
+    let (line, col) = content_buffer.cursor_position();
+    let lines = content_buffer.get_filled_items();
+    let modified = match editor_config.modified {
+        true => " [Modified]",
+        false => "",
+    };
+    editor_config.editor_state.message = Some(format!(
+        "\"{}\"{modified} line {line} of {lines} --{}%-- col {col}",
+        display_name(&editor_config.file_data),
+        line * 100 / lines.max(1)
+    ));
+}
+
+/// the buffer `name` stands for in :b and :bd, a number, # for the alternate buffer or a part of
+/// the name of one file. no name at all is the buffer being edited
+fn find(editor_config: &EditorConfig, name: Option<&str>) -> Result<usize, String> {
+    let buffers = &editor_config.buffers;
+    let name = match name {
+        None => return Ok(buffers.current),
+        Some("%") => return Ok(buffers.current),
+        Some("#") => {
+            return buffers
+                .alternate
+                .ok_or(String::from("E23: No alternate file"))
+        }
+        Some(name) => name,
+    };
+    if let Ok(number) = name.parse::<usize>() {
+        return match buffers.numbers().contains(&number) {
+            true => Ok(number),
+            false => Err(format!("E86: Buffer {number} does not exist")),
+        };
+    }
+    let names = buffers
+        .hidden
+        .iter()
+        .map(|buffer| (buffer.number, buffer.file_data.file_name.as_deref()))
+        .chain([(
+            buffers.current,
+            editor_config.file_data.file_name.as_deref(),
+        )]);
+    let mut matching = Vec::new();
+    for (number, file_name) in names {
+        match file_name {
+            // a full match wins over any number of partial ones
+            Some(file_name) if same_file(file_name, name) => return Ok(number),
+            Some(file_name) if file_name.contains(name) => matching.push(number),
+            _ => (),
+        }
+    }
+    match matching[..] {
+        [number] => Ok(number),
+        [] => Err(format!("E94: No matching buffer for {name}")),
+        _ => Err(format!("E93: More than one match for {name}")),
+    }
+}
+
+/// :e file, edits the file in a buffer of its own, or in the one already open for it. :e on its
+/// own or with the name of the file being edited reads it again, throwing away the changes
+/// with ! only
+pub fn edit(editor_config: &mut EditorConfig, file_name: Option<String>, bang: bool) {
+    let file_name = match file_name.as_deref() {
+        Some("#") => match find(editor_config, Some("#")) {
+            Ok(number) => return switch_to(editor_config, number),
+            Err(e) => {
+                editor_config.editor_state.message = Some(e);
+                return;
+            }
+        },
+        _ => file_name.or(editor_config.file_data.file_name.clone()),
+    };
+    let Some(file_name) = file_name else {
+        editor_config.editor_state.message = Some(String::from("E32: No file name"));
+        return;
+    };
+    let open = editor_config
+        .buffers
+        .hidden
+        .iter()
+        .find(|buffer| buffer.file_data.is_named(&file_name));
+    if let Some(buffer) = open {
+        return switch_to(editor_config, buffer.number);
+    }
+    update_modified(editor_config);
+    let own_file = editor_config.file_data.is_named(&file_name);
+    // reading the file again keeps the name it was opened with, whatever path it was given by
+    let file_name = match (own_file, &editor_config.file_data.file_name) {
+        (true, Some(own_name)) => own_name.clone(),
+        _ => file_name,
+    };
+    // the empty buffer jbvim starts with when no file is given is taken over by the first file
+    let unused = editor_config.file_data.file_name.is_none()
+        && !editor_config.modified
+        && !tab::shows_elsewhere(editor_config, editor_config.buffers.current)
+        && editor_config.gap_buffer.get_lines() == [""];
+    if own_file && editor_config.modified && !bang {
+        editor_config.editor_state.message = Some(String::from(
+            "E37: No write since last change (add ! to override)",
+        ));
+        return;
+    }
+
+    let editor_state = &mut editor_config.editor_state;
+    let file_data = match FileData::open(file_name) {
+        Ok(file_data) => file_data,
+        Err(e) => {
+            editor_state.message = Some(e);
+            return;
+        }
+    };
+    let new_file = file_data.is_new_file();
+    let mut buffer = match Buffer::load(file_data, editor_state) {
+        Ok(buffer) => buffer,
+        Err(e) => {
+            editor_state.message = Some(format!("E484: Can't open file: {e}"));
+            return;
+        }
+    };
+    if own_file || unused {
+        // the new buffer takes the place and number of the one being edited
+        swap(editor_config, &mut buffer);
+        editor_config.buffers.current = buffer.number;
+    } else {
+        let number = editor_config.buffers.add(buffer);
+        switch_to(editor_config, number);
+    }
+    editor_config.editor_state.message = None;
+    if let (true, Some(file_name)) = (new_file, &editor_config.file_data.file_name) {
+        editor_config.editor_state.message = Some(format!("\"{file_name}\" [New File]"));
+    }
+}
+
+/// :b N, :b name and :b #, switches to another buffer
+pub fn buffer(editor_config: &mut EditorConfig, name: Option<&str>) {
+    match find(editor_config, name) {
+        Ok(number) => switch_to(editor_config, number),
+        Err(e) => editor_config.editor_state.message = Some(e),
+    }
+}
+
+/// :bn and :bp, switches to the buffer numbered after the one being edited, or before it with
+/// `back`. the last one is followed by the first
+pub fn cycle(editor_config: &mut EditorConfig, back: bool) {
+    let numbers = editor_config.buffers.numbers();
+    let current = editor_config.buffers.current;
+    let index = numbers
+        .iter()
+        .position(|number| *number == current)
+        .unwrap_or(0);
+    let index = match back {
+        true => (index + numbers.len() - 1) % numbers.len(),
+        false => (index + 1) % numbers.len(),
+    };
+    switch_to(editor_config, numbers[index]);
+}
+
+/// :bd, removes a buffer from the list. one with changes that weren't written needs a !. when
+/// the buffer being edited goes, the alternate one is edited instead, or an empty buffer when
+/// there is nothing else
+pub fn delete(editor_config: &mut EditorConfig, name: Option<&str>, bang: bool) {
+    let number = match find(editor_config, name) {
+        Ok(number) => number,
+        Err(e) => {
+            editor_config.editor_state.message = Some(e);
+            return;
+        }
+    };
+    update_modified(editor_config);
+    let buffers = &mut editor_config.buffers;
+    let modified = match buffers.hidden.iter().find(|buffer| buffer.number == number) {
+        Some(buffer) => buffer.modified,
+        None => editor_config.modified,
+    };
+    if modified && !bang {
+        editor_config.editor_state.message = Some(format!(
+            "E89: No write since last change for buffer {number} (add ! to override)"
+        ));
+        return;
+    }
+    if buffers.alternate == Some(number) {
+        buffers.alternate = None;
+    }
+    // the other windows on the buffer go with it
+    tab::close_showing(editor_config, number);
+    let buffers = &mut editor_config.buffers;
+    if number != buffers.current {
+        buffers.hidden.retain(|buffer| buffer.number != number);
+        return;
+    }
+
+    let numbers = buffers.numbers();
+    let next = buffers.alternate.or_else(|| {
+        let after = numbers.iter().find(|other| **other > number);
+        after
+            .or(numbers.iter().rev().find(|other| **other < number))
+            .copied()
+    });
+    match next {
+        Some(next) => {
+            switch_to(editor_config, next);
+            let buffers = &mut editor_config.buffers;
+            buffers.hidden.retain(|buffer| buffer.number != number);
+            buffers.alternate = None;
+        }
+        None => {
+            let editor_state = &mut editor_config.editor_state;
+            let mut buffer = match Buffer::load(FileData::scratch(), editor_state) {
+                Ok(buffer) => buffer,
+                Err(e) => {
+                    editor_state.message = Some(format!("E484: Can't open file: {e}"));
+                    return;
+                }
+            };
+            buffer.number = editor_config.buffers.next;
+            editor_config.buffers.next += 1;
+            swap(editor_config, &mut buffer);
+        }
+    }
+}
+
+/// one line of :ls. the flags are % for the buffer being edited and # for the alternate one, a
+/// for the buffer on screen and h for one that is hidden, and + when there are changes that
+/// weren't written
+fn list_line(number: usize, flags: [char; 3], name: &str, line: usize) -> String {
+    let [current, shown, modified] = flags;
+    let entry = format!("{number:>3} {current}{shown} {modified} \"{name}\"");
+    let padding = 40usize.saturating_sub(entry.chars().count()).max(1);
+    format!("{entry}{}line {line}", " ".repeat(padding))
+}
+
+/// :ls, lists every buffer
+pub fn list(editor_config: &mut EditorConfig) {
+    let buffers = &editor_config.buffers;
+    let windows = &editor_config.windows;
+    let modified = |modified| if modified { '+' } else { ' ' };
+    let current = list_line(
+        buffers.current,
+        ['%', 'a', modified(editor_config.modified)],
+        display_name(&editor_config.file_data),
+        editor_config.gap_buffer.cursor_position().0,
+    );
+    let mut lines: Vec<(usize, String)> = buffers
+        .hidden
+        .iter()
+        .map(|buffer| {
+            let alternate = match buffers.alternate == Some(buffer.number) {
+                true => '#',
+                false => ' ',
+            };
+            let shown = match windows.shows_elsewhere(buffer.number) {
+                true => 'a',
+                false => 'h',
+            };
+            let line = list_line(
+                buffer.number,
+                [alternate, shown, modified(buffer.modified)],
+                buffer.name(),
+                buffer.gap_buffer.cursor_position().0,
+            );
+            (buffer.number, line)
+        })
+        .collect();
+    lines.push((buffers.current, current));
+    lines.sort_by_key(|(number, _)| *number);
+    editor_config.editor_state.output = lines.into_iter().map(|(_, line)| line).collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ls_lines() {
+        assert_eq!(
+            list_line(1, ['%', 'a', ' '], "a.txt", 3),
+            "  1 %a   \"a.txt\"                        line 3"
+        );
+        assert_eq!(
+            list_line(12, ['#', 'h', '+'], "src/b.rs", 1),
+            " 12 #h + \"src/b.rs\"                     line 1"
+        );
+        let long = "a".repeat(40);
+        assert!(list_line(2, [' ', 'h', ' '], &long, 7).ends_with("\" line 7"));
+    }
+}
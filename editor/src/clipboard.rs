@@ -0,0 +1,226 @@
+use crate::ops::RangeKind;
+use crate::register::Register;
+use std::env;
+use std::ffi::OsStr;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use terminol::clipboard::Selection;
+
+/// the tools clipcmd=auto looks for on PATH, in this order
+const TOOLS: [&str; 3] = ["wl-copy", "xclip", "xsel"];
+
+/// the terminal selection behind "+ or "*
+fn selection(name: char) -> Selection {
+    match name {
+        '*' => Selection::Primary,
+        _ => Selection::Clipboard,
+    }
+}
+
+/// the program and its arguments that copy to or, when `copy` isn't set, paste from `selection`
+fn command_line(
+    tool: &str,
+    selection: Selection,
+    copy: bool,
+) -> Option<(&'static str, Vec<&'static str>)> {
+    let primary = selection == Selection::Primary;
+    let command_line = match (tool, copy) {
+        ("wl-copy", true) => ("wl-copy", if primary { vec!["--primary"] } else { vec![] }),
+        ("wl-copy", false) => {
+            let mut args = vec!["--no-newline"];
+            if primary {
+                args.push("--primary");
+            }
+            ("wl-paste", args)
+        }
+        ("xclip", _) => {
+            let target = if primary { "primary" } else { "clipboard" };
+            let mut args = vec!["-selection", target];
+            if !copy {
+                args.push("-o");
+            }
+            ("xclip", args)
+        }
+        ("xsel", _) => {
+            let target = if primary { "--primary" } else { "--clipboard" };
+            let direction = if copy { "--input" } else { "--output" };
+            ("xsel", vec![target, direction])
+        }
+        _ => return None,
+    };
+    Some(command_line)
+}
+
+/// where `program` is on `path`, which is laid out like $PATH
+fn find_program(program: &str, path: &OsStr) -> Option<PathBuf> {
+    env::split_paths(path)
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.is_file())
+}
+
+/// the full path and arguments of the command that copies or pastes for the clipcmd option
+/// `setting`. auto takes the first tool that is installed, none never runs anything
+fn resolve(
+    setting: &str,
+    selection: Selection,
+    copy: bool,
+    path: &OsStr,
+) -> Option<(PathBuf, Vec<&'static str>)> {
+    let tools: Vec<&str> = match setting {
+        "auto" => TOOLS.to_vec(),
+        tool => vec![tool],
+    };
+    tools.into_iter().find_map(|tool| {
+        let (program, args) = command_line(tool, selection, copy)?;
+        Some((find_program(program, path)?, args))
+    })
+}
+
+/// whether clipcmd can be set to `setting`
+pub fn is_valid_setting(setting: &str) -> bool {
+    matches!(setting, "auto" | "none") || TOOLS.contains(&setting)
+}
+
+/// the text of a register as it goes on the clipboard, where whole lines end with a newline
+fn clipboard_text(register: &Register) -> String {
+    let mut text = register.text.join("\n");
+    if register.kind == RangeKind::Line {
+        text.push('\n');
+    }
+    text
+}
+
+/// sends a yank or delete to "+ or "* out to the system. the terminal is told through OSC 52
+/// when `osc52` is set, and the clipcmd tool is given it too when there is one. nothing stops
+/// both from working, which is what makes it work both locally and over ssh
+pub fn copy(
+    name: char,
+    register: &Register,
+    setting: &str,
+    osc52: bool,
+    path: &OsStr,
+) -> Result<(), String> {
+    let text = clipboard_text(register);
+    if osc52 {
+        terminol::clipboard::set(selection(name), &text);
+    }
+    let (program, args) = match resolve(setting, selection(name), true, path) {
+        Some(command) => command,
+        None => return Ok(()),
+    };
+    let failed = |e: &dyn std::fmt::Display| format!("{}: {e}", program.display());
+    let mut child = Command::new(&program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| failed(&e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes()).map_err(|e| failed(&e))?;
+    }
+    let status = child.wait().map_err(|e| failed(&e))?;
+    if !status.success() {
+        return Err(failed(&status));
+    }
+    Ok(())
+}
+
+/// what "+ or "* holds, read back through the clipcmd tool. None when there is no tool to ask,
+/// so the caller falls back on the copy kept from the last yank. text the tool gives back that
+/// is what was last copied keeps its shape, anything else is whole lines when it ends with a
+/// newline and chars otherwise
+pub fn paste(
+    name: char,
+    last_copied: Option<Register>,
+    setting: &str,
+    path: &OsStr,
+) -> Option<Result<Register, String>> {
+    let (program, args) = resolve(setting, selection(name), false, path)?;
+    let output = Command::new(&program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output();
+    let text = match output {
+        Ok(output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        }
+        Ok(output) => return Some(Err(format!("{}: {}", program.display(), output.status))),
+        Err(e) => return Some(Err(format!("{}: {e}", program.display()))),
+    };
+    if let Some(register) = last_copied.filter(|register| clipboard_text(register) == text) {
+        return Some(Ok(register));
+    }
+    let register = match text.strip_suffix('\n') {
+        Some(lines) => Register {
+            text: lines.split('\n').map(String::from).collect(),
+            kind: RangeKind::Line,
+        },
+        None => Register {
+            text: text.split('\n').map(String::from).collect(),
+            kind: RangeKind::Char,
+        },
+    };
+    Some(Ok(register))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// a directory holding an xclip that keeps what it is given in a file next to it
+    fn stub_xclip() -> PathBuf {
+        let dir = env::temp_dir().join(format!("jbvim-clipboard-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap_or_else(|e| panic!("couldn't make {dir:?}: {e}"));
+        let script = dir.join("xclip");
+        fs::write(
+            &script,
+            "#!/bin/sh\n\
+             clip=\"$(dirname \"$0\")/clip-$2\"\n\
+             case \"$*\" in *-o*) cat \"$clip\" ;; *) cat > \"$clip\" ;; esac\n",
+        )
+        .unwrap_or_else(|e| panic!("couldn't write the stub: {e}"));
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755))
+            .unwrap_or_else(|e| panic!("couldn't make the stub runnable: {e}"));
+        dir
+    }
+
+    #[test]
+    fn copy_and_paste_through_a_stub_tool() {
+        let dir = stub_xclip();
+        let path = dir.as_os_str();
+        let lines = Register {
+            text: vec![String::from("one"), String::from("two")],
+            kind: RangeKind::Line,
+        };
+        copy('+', &lines, "auto", false, path).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("clip-clipboard")).unwrap(),
+            "one\ntwo\n"
+        );
+        let pasted = paste('+', None, "xclip", path).unwrap().unwrap();
+        assert_eq!(pasted, lines);
+
+        // the primary selection is kept apart, and a block comes back as a block
+        let block = Register {
+            text: vec![String::from("ab"), String::from("cd")],
+            kind: RangeKind::Block,
+        };
+        copy('*', &block, "xclip", false, path).unwrap();
+        let pasted = paste('*', Some(block.clone()), "auto", path)
+            .unwrap()
+            .unwrap();
+        assert_eq!(pasted, block);
+        fs::write(dir.join("clip-primary"), "from elsewhere").unwrap();
+        let pasted = paste('*', Some(block), "auto", path).unwrap().unwrap();
+        assert_eq!(pasted.kind, RangeKind::Char);
+
+        assert!(paste('+', None, "none", path).is_none());
+        assert!(paste('+', None, "wl-copy", path).is_none());
+        fs::remove_dir_all(&dir).unwrap_or_else(|e| panic!("couldn't clean up {dir:?}: {e}"));
+    }
+}
@@ -1,4 +1,11 @@
-use std::fs;
+use crate::clipboard;
+use crate::motion::Find;
+use crate::ops::{Operator, RangeKind, TextRange};
+use crate::substitute::{LastSubstitute, Substitution};
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::path::{Component, PathBuf};
+use std::{env, fs};
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum EditorMode {
@@ -6,6 +13,13 @@ pub enum EditorMode {
     Insert,
     Visual,
     Command,
+    /// an operator such as d or gU has been typed and waits for the motion that says what to
+    /// work on
+    OperatorPending,
+    /// the prompt of / or ?, where the pattern to search for is typed
+    Search,
+    /// :s with the c flag asking whether to substitute a match
+    Confirm,
     ShutDown,
 }
 
@@ -16,40 +30,366 @@ impl EditorMode {
             EditorMode::Insert => String::from("insert"),
             EditorMode::Visual => String::from("visual"),
             EditorMode::Command => String::from("command"),
+            EditorMode::OperatorPending => String::from("operator pending"),
+            EditorMode::Search => String::from("search"),
+            EditorMode::Confirm => String::from("confirm"),
             EditorMode::ShutDown => String::from(""),
         }
     }
 }
 
 pub struct FileData {
-    pub file_name: String,
-    pub file_handle: fs::File,
+    /// None for the scratch buffer opened when jbvim is started without a file
+    pub file_name: Option<String>,
+    /// None when there is nothing on disk yet, the file is created by the first :w
+    pub file_handle: Option<fs::File>,
 }
 
 impl FileData {
-    pub fn build(mut args: impl Iterator<Item = String>) -> Result<FileData, &'static str> {
+    /// the files named on the command line, each of which gets a buffer. the scratch buffer
+    /// when there are none
+    pub fn build(mut args: impl Iterator<Item = String>) -> Result<Vec<FileData>, String> {
         args.next();
 
-        let file_name = match args.next() {
-            Some(arg) => arg,
-            None => return Err("No file name provided, exiting..."),
+        let files: Vec<FileData> = args.map(FileData::open).collect::<Result<_, _>>()?;
+        match files.is_empty() {
+            true => Ok(vec![FileData::scratch()]),
+            false => Ok(files),
+        }
+    }
+    pub fn scratch() -> FileData {
+        FileData {
+            file_name: None,
+            file_handle: None,
+        }
+    }
+    pub fn open(file_name: String) -> Result<FileData, String> {
+        // a path that doesn't exist yet opens an empty buffer, anything else that can't be
+        // opened is an error since writing to it later would fail the same way
+        let file_handle = match fs::File::open(&file_name) {
+            Ok(file_handle) => Some(file_handle),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(format!("can't open {file_name}: {e}")),
         };
 
-        let file_handle;
-        //if Path::new(&file_name).exists() {
-        // open the file and read the lines into the terminal... or
-        // at least collect it so that it is easy to do so later.
-        file_handle = fs::File::open(&file_name).unwrap();
-
         Ok(FileData {
-            file_name,
+            file_name: Some(file_name),
             file_handle,
         })
     }
+    pub fn is_new_file(&self) -> bool {
+        self.file_name.is_some() && self.file_handle.is_none()
+    }
+    /// whether `file_name` is the file of this buffer, however the path to it is spelled
+    pub fn is_named(&self, file_name: &str) -> bool {
+        self.file_name
+            .as_deref()
+            .is_some_and(|own_name| same_file(own_name, file_name))
+    }
+    /// reads the whole file, an empty string for a new file or the scratch buffer
+    pub fn read_contents(&mut self) -> io::Result<String> {
+        let mut contents = String::new();
+        if let Some(file_handle) = &mut self.file_handle {
+            file_handle.read_to_string(&mut contents)?;
+        }
+        Ok(contents)
+    }
+}
+/// whether two paths name the same file, so ./foo.txt and foo.txt are one file. a file that
+/// doesn't exist yet can't be looked up, so its path is only tidied up to compare it
+pub fn same_file(a: &str, b: &str) -> bool {
+    a == b || full_path(a) == full_path(b)
+}
+
+/// the absolute path to `file_name` with symlinks followed, or with . and .. worked out by hand
+/// when the file isn't there
+fn full_path(file_name: &str) -> PathBuf {
+    if let Ok(path) = fs::canonicalize(file_name) {
+        return path;
+    }
+    let path = env::current_dir().unwrap_or_default().join(file_name);
+    let mut full = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                full.pop();
+            }
+            component => full.push(component),
+        }
+    }
+    full
+}
+
+/// the editor's own record of where the cursor is, 1-indexed like terminal coordinates. this is
+/// derived from the gap positions of the content buffer, so the terminal is only ever told where
+/// to draw the cursor and never asked where it is
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Cursor {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Cursor {
+    pub fn new(line: usize, col: usize) -> Cursor {
+        Cursor { line, col }
+    }
+}
+
+/// the settings changed with :set
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    /// long lines continue on the next screen row instead of running off the right edge
+    pub wrap: bool,
+    /// wrapped lines are broken at a blank rather than in the middle of a word
+    pub linebreak: bool,
+    /// how many columns > and < move a line
+    pub shiftwidth: usize,
+    /// the longest line gq makes, 0 for the default of 79
+    pub textwidth: usize,
+    /// the tool that "+ and "* copy to and paste from: wl-copy, xclip, xsel, auto for the first
+    /// of those that is installed or none
+    pub clipcmd: String,
+    /// yanks to "+ and "* are also sent to the terminal with the OSC 52 escape
+    pub osc52: bool,
+    /// every match of the last search is highlighted until :nohlsearch
+    pub hlsearch: bool,
+    /// the cursor shows the first match while the pattern is still being typed
+    pub incsearch: bool,
+    /// searches go round the end of the buffer and carry on from the other end
+    pub wrapscan: bool,
+    /// patterns match upper and lower case alike
+    pub ignorecase: bool,
+    /// with ignorecase, a pattern with an upper case letter in it still matches case
+    pub smartcase: bool,
+}
+
+impl Options {
+    pub fn new() -> Options {
+        Options {
+            wrap: true,
+            linebreak: false,
+            shiftwidth: 4,
+            textwidth: 0,
+            clipcmd: String::from("auto"),
+            osc52: true,
+            hlsearch: false,
+            incsearch: true,
+            wrapscan: true,
+            ignorecase: false,
+            smartcase: false,
+        }
+    }
+    fn number(&mut self, name: &str) -> Option<&mut usize> {
+        match name {
+            "shiftwidth" | "sw" => Some(&mut self.shiftwidth),
+            "textwidth" | "tw" => Some(&mut self.textwidth),
+            _ => None,
+        }
+    }
+    fn flag(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "wrap" => Some(&mut self.wrap),
+            "linebreak" | "lbr" => Some(&mut self.linebreak),
+            "osc52" => Some(&mut self.osc52),
+            "hlsearch" | "hls" => Some(&mut self.hlsearch),
+            "incsearch" | "is" => Some(&mut self.incsearch),
+            "wrapscan" | "ws" => Some(&mut self.wrapscan),
+            "ignorecase" | "ic" => Some(&mut self.ignorecase),
+            "smartcase" | "scs" => Some(&mut self.smartcase),
+            _ => None,
+        }
+    }
+    /// applies the arguments of one :set. `wrap` turns an option on, `nowrap` off, `invwrap` or
+    /// `wrap!` flips it and `wrap?` asks for its value. a number option is set with `sw=8` and
+    /// shown by its name alone, and clipcmd the same way. returns what should be shown, if anything
+    pub fn set(&mut self, args: &str) -> Result<Option<String>, String> {
+        let mut shown = Vec::new();
+        for arg in args.split_whitespace() {
+            let unknown = || format!("E518: Unknown option: {arg}");
+            let name = arg.split(['=', '?']).next().unwrap_or(arg);
+            if name == "clipcmd" {
+                match arg.split_once('=') {
+                    Some((_, value)) if clipboard::is_valid_setting(value) => {
+                        self.clipcmd = String::from(value);
+                    }
+                    Some(_) => return Err(format!("E474: Invalid argument: {arg}")),
+                    None => shown.push(format!("clipcmd={}", self.clipcmd)),
+                }
+            } else if let Some(number) = self.number(name) {
+                match arg.split_once('=') {
+                    Some((_, value)) => {
+                        *number = value
+                            .parse()
+                            .map_err(|_| format!("E521: Number required after =: {arg}"))?;
+                    }
+                    None => shown.push(format!("{name}={number}")),
+                }
+            } else if let Some(name) = arg.strip_suffix('?') {
+                let value = *self.flag(name).ok_or_else(unknown)?;
+                shown.push(format!("{}{name}", if value { "" } else { "no" }));
+            } else if let Some(name) = arg.strip_suffix('!').or(arg.strip_prefix("inv")) {
+                let flag = self.flag(name).ok_or_else(unknown)?;
+                *flag = !*flag;
+            } else if let Some(flag) = self.flag(arg) {
+                *flag = true;
+            } else {
+                let name = arg.strip_prefix("no").ok_or_else(unknown)?;
+                *self.flag(name).ok_or_else(unknown)? = false;
+            }
+        }
+        Ok((!shown.is_empty()).then(|| shown.join(" ")))
+    }
+    /// every option and its value, for :set on its own
+    pub fn show_all(&self) -> String {
+        let show = |name: &str, value: bool| format!("{}{name}", if value { "" } else { "no" });
+        format!(
+            "clipcmd={}  {}  {}  {}  {}  {}  {}  {}  {}",
+            self.clipcmd,
+            show("hlsearch", self.hlsearch),
+            show("ignorecase", self.ignorecase),
+            show("incsearch", self.incsearch),
+            show("linebreak", self.linebreak),
+            show("osc52", self.osc52),
+            show("smartcase", self.smartcase),
+            show("wrap", self.wrap),
+            show("wrapscan", self.wrapscan)
+        )
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::new()
+    }
 }
+
 pub struct EditorState {
     pub editor_mode: EditorMode,
     pub previous_mode: EditorMode,
+    pub cursor: Cursor,
+    /// first key of a two key normal mode command such as gg or zz, waiting for its second key
+    pub pending_key: Option<char>,
+    /// one line of feedback such as an error, shown in the command row until the next key
+    pub message: Option<String>,
+    /// output too long for the command row, drawn above the information bar until a key is hit
+    pub output: Vec<String>,
+    /// line and column of every mark set with m{a-z}
+    pub marks: HashMap<char, (usize, usize)>,
+    pub options: Options,
+    /// the line typed so far in command mode
+    pub command: String,
+    /// the shape of the selection while in visual mode
+    pub visual_kind: RangeKind,
+    /// the end of the selection that stays put while the cursor moves, 1-indexed like the cursor
+    pub visual_anchor: (usize, usize),
+    /// the last selection, for gv
+    pub last_visual: Option<VisualSelection>,
+    /// set while typing the text that c on a block puts on every line of it
+    pub block_insert: Option<BlockInsert>,
+    /// the count, register and operator of the command being typed
+    pub pending: PendingCommand,
+    /// what has been typed since insert mode was entered, which becomes the ". register
+    pub inserted: String,
+    /// the count i and a were given, the number of times what was typed goes in once Esc is hit
+    pub insert_count: usize,
+    /// the last f, F, t or T and its char, for ; and ,
+    pub last_find: Option<(Find, char)>,
+    pub search: SearchState,
+    /// the pattern, replacement and flags of the last :s
+    pub last_substitute: Option<LastSubstitute>,
+    /// the :s waiting at the confirm prompt
+    pub confirm: Option<Substitution>,
+    /// set while :g runs its command on the lines it marked
+    pub in_global: bool,
+    /// the register keys are being recorded into, after q{reg}
+    pub recording: Option<char>,
+}
+
+/// the parts of a normal mode command typed so far, `[count]["x]operator[count]motion`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PendingCommand {
+    /// the count being typed, before the operator or before the motion once there is one
+    pub count: Option<usize>,
+    /// the register named with "x
+    pub register: Option<char>,
+    pub operator: Option<Operator>,
+    /// the count typed before the operator, which multiplies the one typed after it
+    pub operator_count: Option<usize>,
+}
+
+impl PendingCommand {
+    /// adds a digit to the count being typed
+    pub fn push_digit(&mut self, digit: u32) {
+        let count = self.count.unwrap_or(0);
+        self.count = Some(count.saturating_mul(10).saturating_add(digit as usize));
+    }
+    /// the count the whole command was given, None when no count was typed at all
+    pub fn total_count(&self) -> Option<usize> {
+        match (self.operator_count, self.count) {
+            (None, None) => None,
+            (before, after) => Some(before.unwrap_or(1).saturating_mul(after.unwrap_or(1))),
+        }
+    }
+}
+
+/// the last search, and the one being typed at the / or ? prompt
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchState {
+    /// the last pattern searched for, which n and N look for again
+    pub pattern: Option<String>,
+    /// whether the last search was made with / rather than ?
+    pub forward: bool,
+    /// cleared by :nohlsearch and set again by the next search, so hlsearch can stay on
+    pub highlight: bool,
+    /// whether the prompt being typed at is that of /
+    pub prompt_forward: bool,
+    /// where the cursor was when the prompt was opened, 1-indexed like the cursor
+    pub origin: (usize, usize),
+    /// the mode the prompt goes back to
+    pub return_mode: EditorMode,
+    /// the match incsearch is showing while the pattern is typed
+    pub current: Option<TextRange>,
+}
+
+impl SearchState {
+    pub fn new() -> SearchState {
+        SearchState {
+            pattern: None,
+            forward: true,
+            highlight: true,
+            prompt_forward: true,
+            origin: (1, 1),
+            return_mode: EditorMode::Normal,
+            current: None,
+        }
+    }
+}
+
+impl Default for SearchState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// a selection as it was when visual mode was left
+#[derive(Clone, Copy, Debug)]
+pub struct VisualSelection {
+    pub kind: RangeKind,
+    pub anchor: (usize, usize),
+    pub cursor: (usize, usize),
+}
+
+/// what is needed to copy the text typed on the first line of a changed block to the rest of it
+/// once insert mode is left
+#[derive(Clone, Copy, Debug)]
+pub struct BlockInsert {
+    pub first: usize,
+    pub last: usize,
+    pub col: usize,
+    /// the length of the first line before anything was typed, the text typed is what it grew by
+    pub len_before: usize,
+    pub line_count: usize,
 }
 
 impl EditorState {
@@ -57,6 +397,26 @@ impl EditorState {
         EditorState {
             editor_mode,
             previous_mode,
+            cursor: Cursor::new(1, 1),
+            pending_key: None,
+            message: None,
+            output: Vec::new(),
+            marks: HashMap::new(),
+            options: Options::new(),
+            command: String::new(),
+            visual_kind: RangeKind::Char,
+            visual_anchor: (1, 1),
+            last_visual: None,
+            block_insert: None,
+            inserted: String::new(),
+            insert_count: 1,
+            pending: PendingCommand::default(),
+            last_find: None,
+            search: SearchState::new(),
+            last_substitute: None,
+            confirm: None,
+            in_global: false,
+            recording: None,
         }
     }
     pub fn update_editor_mode(&mut self, mode: EditorMode) {
@@ -66,4 +426,32 @@ impl EditorState {
     pub fn get_current_mode(&self) -> EditorMode {
         self.editor_mode
     }
+    /// the name of the mode shown in the command row, which tells the visual modes apart
+    pub fn mode_label(&self) -> String {
+        let label = match (self.editor_mode, self.visual_kind) {
+            (EditorMode::Visual, RangeKind::Line) => String::from("visual line"),
+            (EditorMode::Visual, RangeKind::Block) => String::from("visual block"),
+            (mode, _) => mode.value(),
+        };
+        match self.recording {
+            Some(name) => format!("{label} recording @{name}"),
+            None => label,
+        }
+    }
+    /// what is selected between the anchor and the cursor, 0-indexed. None outside visual mode
+    pub fn selection(&self) -> Option<TextRange> {
+        if self.editor_mode != EditorMode::Visual {
+            return None;
+        }
+        let (line, col) = self.visual_anchor;
+        Some(TextRange::between(
+            self.visual_kind,
+            (line - 1, col - 1),
+            (self.cursor.line - 1, self.cursor.col - 1),
+        ))
+    }
+    /// re-derives the cursor from where the gaps of the content buffer currently sit
+    pub fn sync_cursor(&mut self, (line, col): (usize, usize)) {
+        self.cursor = Cursor::new(line, col);
+    }
 }
@@ -0,0 +1,864 @@
+use crate::config::Options;
+use crate::regex::Regex;
+use crate::search;
+use gap_buffer::GapBuffer;
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// what an address counts from, before any +N or -N offsets are applied
+#[derive(Clone, Debug, PartialEq)]
+pub enum AddressBase {
+    /// a line number, `10`
+    Line(usize),
+    /// the cursor line, `.`
+    Current,
+    /// the last line, `$`
+    Last,
+    /// the line a mark was set on, `'a`
+    Mark(char),
+    /// the next line matching a pattern, `/pat/` searching forward and `?pat?` backward
+    Search { pattern: String, forward: bool },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Address {
+    pub base: AddressBase,
+    pub offset: isize,
+}
+
+/// the lines a command applies to. `start` is None when no address was typed at all
+#[derive(Clone, Debug, PartialEq)]
+pub struct Range {
+    pub start: Option<Address>,
+    pub end: Option<Address>,
+    /// separated by ';', so the end is counted from the start rather than from the cursor
+    pub relative: bool,
+    /// two addresses were typed rather than one, which :join tells apart
+    pub pair: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    /// only a range was typed, `:10` moves to line 10
+    Goto,
+    Write(Option<String>),
+    Quit,
+    WriteQuit(Option<String>),
+    /// :x, like :wq but only writes when the buffer was modified
+    Exit(Option<String>),
+    Edit(Option<String>),
+    Delete,
+    Undo,
+    Redo,
+    Undolist,
+    Set(Option<String>),
+    /// :registers and :display, optionally given the names of the registers to list
+    Registers(Option<String>),
+    /// :nohlsearch, stops highlighting the matches of the last search until the next one
+    Nohlsearch,
+    /// :s/pat/rep/flags, and :& or :&& to repeat the last one
+    Substitute(Substitute),
+    /// :g/pat/cmd runs cmd on every line matching pat, :v/pat/cmd and :g!/pat/cmd on every line
+    /// that doesn't
+    Global {
+        pattern: String,
+        command: String,
+        invert: bool,
+    },
+    /// :join, with ! the lines are joined without changing any white space
+    Join,
+    Print,
+    /// :normal {commands}, types the commands as normal mode keys, once on each line of a range
+    Normal(String),
+    /// :b N, :b name or :b #, edits another buffer
+    Buffer(Option<String>),
+    Bnext,
+    /// :bp and :bN
+    Bprevious,
+    /// :bd, with no buffer named it is the one being edited
+    Bdelete(Option<String>),
+    /// :ls, :buffers and :files
+    Buffers,
+    /// :split and :vsplit, optionally with the file to edit in the new window
+    Split {
+        vertical: bool,
+        file_name: Option<String>,
+    },
+    /// :new and :vnew, a new window on an empty buffer
+    New {
+        vertical: bool,
+    },
+    Close,
+    Only,
+    /// :qall, quits with every window
+    Qall,
+    /// :tabnew and :tabedit, optionally with the file to edit in the new tab page
+    Tabnew(Option<String>),
+    Tabclose,
+    Tabonly,
+}
+
+/// what was typed after :s. the pattern and replacement are None when the last ones are repeated
+#[derive(Clone, Debug, PartialEq)]
+pub struct Substitute {
+    pub parts: Option<(String, String)>,
+    /// the flag letters as typed, `&` first to keep the flags of the last substitute
+    pub flags: String,
+    /// `:s/a/b/ 3` substitutes in 3 lines starting at the end of the range
+    pub count: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExCommand {
+    pub range: Range,
+    pub bang: bool,
+    pub command: Command,
+}
+
+/// every command as (full name, shortest abbreviation allowed). abbreviations are matched in
+/// this order, so a command that should win a shared prefix goes first, like vim's table
+const COMMANDS: [(&str, usize); 40] = [
+    ("write", 1),
+    ("wq", 2),
+    ("xit", 1),
+    ("exit", 3),
+    ("quit", 1),
+    ("qall", 2),
+    ("split", 2),
+    ("vsplit", 2),
+    ("new", 3),
+    ("vnew", 3),
+    ("close", 3),
+    ("only", 2),
+    ("tabnew", 6),
+    ("tabedit", 4),
+    ("tabclose", 4),
+    ("tabonly", 4),
+    ("edit", 1),
+    ("buffer", 1),
+    ("bnext", 2),
+    ("bNext", 2),
+    ("bprevious", 2),
+    ("bdelete", 2),
+    ("buffers", 7),
+    ("files", 5),
+    ("ls", 2),
+    ("delete", 1),
+    ("substitute", 1),
+    ("&", 1),
+    ("global", 1),
+    ("vglobal", 1),
+    ("join", 1),
+    ("print", 1),
+    ("normal", 4),
+    ("undo", 1),
+    ("redo", 3),
+    ("undolist", 5),
+    ("set", 2),
+    ("registers", 3),
+    ("display", 2),
+    ("nohlsearch", 3),
+];
+
+/// parses one command line, typed without the leading ':'
+pub fn parse(input: &str) -> Result<ExCommand, String> {
+    let mut chars = input.trim_start_matches([':', ' ']).chars().peekable();
+    let range = parse_range(&mut chars)?;
+    skip_spaces(&mut chars);
+
+    let mut name = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_alphabetic()) {
+        name.push(c);
+    }
+    if name.is_empty() {
+        if let Some(c) = chars.next_if_eq(&'&') {
+            name.push(c);
+        }
+    }
+    let bang = chars.next_if_eq(&'!').is_some();
+    let rest: String = chars.collect();
+    let arg = match rest.trim() {
+        "" => None,
+        arg => Some(String::from(arg)),
+    };
+
+    if name.is_empty() {
+        if bang || arg.is_some() {
+            return Err(format!("E492: Not an editor command: {}", input.trim()));
+        }
+        return Ok(ExCommand {
+            range,
+            bang,
+            command: Command::Goto,
+        });
+    }
+
+    let full_name = COMMANDS
+        .iter()
+        .find(|(full, min)| name.len() >= *min && full.starts_with(&name))
+        .map(|(full, _)| *full)
+        .ok_or_else(|| format!("E492: Not an editor command: {}", input.trim()))?;
+
+    let command = match full_name {
+        "write" => Command::Write(arg),
+        "wq" => Command::WriteQuit(arg),
+        "xit" | "exit" => Command::Exit(arg),
+        "edit" => Command::Edit(arg),
+        "buffer" => Command::Buffer(arg),
+        "bdelete" => Command::Bdelete(arg),
+        "tabnew" | "tabedit" => Command::Tabnew(arg),
+        "split" | "vsplit" => Command::Split {
+            vertical: full_name == "vsplit",
+            file_name: arg,
+        },
+        "set" => Command::Set(arg),
+        "registers" | "display" => Command::Registers(arg),
+        "substitute" => Command::Substitute(parse_substitute(rest.trim_start(), true)?),
+        "&" => Command::Substitute(parse_substitute(rest.trim_start(), false)?),
+        // spaces at the end are keys like any other
+        "normal" => match rest.trim_start() {
+            "" => return Err(String::from("E471: Argument required")),
+            commands => Command::Normal(String::from(commands)),
+        },
+        "global" | "vglobal" => {
+            let (pattern, command) = parse_global(arg.as_deref().unwrap_or(""))?;
+            Command::Global {
+                pattern,
+                command,
+                invert: bang || full_name == "vglobal",
+            }
+        }
+        other => {
+            if let Some(arg) = arg {
+                return Err(format!("E488: Trailing characters: {arg}"));
+            }
+            match other {
+                "quit" => Command::Quit,
+                "delete" => Command::Delete,
+                "undo" => Command::Undo,
+                "redo" => Command::Redo,
+                "nohlsearch" => Command::Nohlsearch,
+                "join" => Command::Join,
+                "print" => Command::Print,
+                "bnext" => Command::Bnext,
+                "bNext" | "bprevious" => Command::Bprevious,
+                "buffers" | "files" | "ls" => Command::Buffers,
+                "new" | "vnew" => Command::New {
+                    vertical: full_name == "vnew",
+                },
+                "close" => Command::Close,
+                "only" => Command::Only,
+                "qall" => Command::Qall,
+                "tabclose" => Command::Tabclose,
+                "tabonly" => Command::Tabonly,
+                "undolist" => Command::Undolist,
+                _ => unreachable!("{other} is in COMMANDS but never parsed"),
+            }
+        }
+    };
+
+    let takes_range = matches!(
+        command,
+        Command::Write(_)
+            | Command::WriteQuit(_)
+            | Command::Exit(_)
+            | Command::Delete
+            | Command::Substitute(_)
+            | Command::Global { .. }
+            | Command::Join
+            | Command::Print
+            | Command::Normal(_)
+    );
+    if range.start.is_some() && !takes_range {
+        return Err(String::from("E481: No range allowed"));
+    }
+    let takes_bang = !matches!(
+        command,
+        Command::Delete
+            | Command::Undo
+            | Command::Redo
+            | Command::Undolist
+            | Command::Set(_)
+            | Command::Registers(_)
+            | Command::Nohlsearch
+            | Command::Substitute(_)
+            | Command::Print
+    );
+    if bang && !takes_bang {
+        return Err(String::from("E477: No ! allowed"));
+    }
+
+    Ok(ExCommand {
+        range,
+        bang,
+        command,
+    })
+}
+
+/// splits the argument of :s into pattern, replacement, flags and count. the first character is
+/// the delimiter unless it could start the flags, in which case the last substitute is repeated.
+/// :& never takes a pattern, its flags start right away
+fn parse_substitute(arg: &str, delimited: bool) -> Result<Substitute, String> {
+    let mut chars = arg.chars().peekable();
+    let delimiter = chars
+        .peek()
+        .copied()
+        .filter(|c| delimited && !c.is_alphanumeric() && !"\\\"|& ".contains(*c));
+
+    let parts = match delimiter {
+        Some(delimiter) => {
+            chars.next();
+            let pattern = until_delimiter(&mut chars, delimiter);
+            let replacement = until_delimiter(&mut chars, delimiter);
+            Some((pattern, replacement))
+        }
+        None => None,
+    };
+
+    let mut flags = String::new();
+    if let Some(c) = chars.next_if_eq(&'&') {
+        flags.push(c);
+    }
+    while let Some(c) = chars.next_if(|c| "gciIne".contains(*c)) {
+        flags.push(c);
+    }
+    skip_spaces(&mut chars);
+    let count = match chars.peek() {
+        Some(c) if c.is_ascii_digit() => match parse_number(&mut chars) {
+            0 => return Err(String::from("E939: Positive count required")),
+            count => Some(count),
+        },
+        _ => None,
+    };
+    skip_spaces(&mut chars);
+
+    let trailing: String = chars.collect();
+    if !trailing.is_empty() {
+        return Err(format!("E488: Trailing characters: {trailing}"));
+    }
+    Ok(Substitute {
+        parts,
+        flags,
+        count,
+    })
+}
+
+/// splits the argument of :g into the pattern and the command to run on the lines it matches
+fn parse_global(arg: &str) -> Result<(String, String), String> {
+    let mut chars = arg.chars().peekable();
+    match chars.next() {
+        Some(delimiter) if !delimiter.is_alphanumeric() && !"\\\"|".contains(delimiter) => {
+            let pattern = until_delimiter(&mut chars, delimiter);
+            let command: String = chars.collect();
+            Ok((pattern, String::from(command.trim_start())))
+        }
+        Some(_) => Err(String::from(
+            "E146: Regular expressions can't be delimited by letters",
+        )),
+        None => Err(String::from(
+            "E148: Regular expression missing from :global",
+        )),
+    }
+}
+
+/// takes characters up to an unescaped delimiter, leaving escapes as they are except for an
+/// escaped delimiter, which becomes the delimiter itself
+fn until_delimiter(chars: &mut Peekable<Chars>, delimiter: char) -> String {
+    let mut text = String::new();
+    while let Some(c) = chars.next() {
+        if c == delimiter {
+            break;
+        }
+        if c == '\\' {
+            match chars.next() {
+                Some(next) if next == delimiter => text.push(next),
+                Some(next) => {
+                    text.push(c);
+                    text.push(next);
+                }
+                None => text.push(c),
+            }
+            continue;
+        }
+        text.push(c);
+    }
+    text
+}
+
+fn skip_spaces(chars: &mut Peekable<Chars>) {
+    while chars.next_if_eq(&' ').is_some() {}
+}
+
+fn parse_range(chars: &mut Peekable<Chars>) -> Result<Range, String> {
+    if chars.next_if_eq(&'%').is_some() {
+        return Ok(Range {
+            start: Some(Address {
+                base: AddressBase::Line(1),
+                offset: 0,
+            }),
+            end: Some(Address {
+                base: AddressBase::Last,
+                offset: 0,
+            }),
+            relative: false,
+            pair: true,
+        });
+    }
+    let start = parse_address(chars)?;
+    skip_spaces(chars);
+    let relative = match chars.peek() {
+        Some(',') => false,
+        Some(';') => true,
+        _ => {
+            return Ok(Range {
+                end: start.clone(),
+                start,
+                relative: false,
+                pair: false,
+            })
+        }
+    };
+    chars.next();
+    skip_spaces(chars);
+
+    // a missing address on either side of the separator means the cursor line
+    let current = Address {
+        base: AddressBase::Current,
+        offset: 0,
+    };
+    let start = start.unwrap_or(current.clone());
+    let end = parse_address(chars)?.unwrap_or(current);
+    Ok(Range {
+        start: Some(start),
+        end: Some(end),
+        relative,
+        pair: true,
+    })
+}
+
+fn parse_address(chars: &mut Peekable<Chars>) -> Result<Option<Address>, String> {
+    let base = match chars.peek() {
+        Some(c) if c.is_ascii_digit() => Some(AddressBase::Line(parse_number(chars))),
+        Some('.') => {
+            chars.next();
+            Some(AddressBase::Current)
+        }
+        Some('$') => {
+            chars.next();
+            Some(AddressBase::Last)
+        }
+        Some('\'') => {
+            chars.next();
+            match chars.next() {
+                Some(mark) => Some(AddressBase::Mark(mark)),
+                None => return Err(String::from("E20: Mark not set")),
+            }
+        }
+        Some(&delimiter @ ('/' | '?')) => {
+            chars.next();
+            let mut pattern = String::new();
+            // the closing delimiter may be left off at the end of the line, \/ is a literal /
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' if chars.next_if_eq(&delimiter).is_some() => pattern.push(delimiter),
+                    c if c == delimiter => break,
+                    c => pattern.push(c),
+                }
+            }
+            Some(AddressBase::Search {
+                pattern,
+                forward: delimiter == '/',
+            })
+        }
+        // an offset on its own counts from the cursor line, `:+3`
+        Some('+' | '-') => Some(AddressBase::Current),
+        _ => None,
+    };
+    let base = match base {
+        Some(base) => base,
+        None => return Ok(None),
+    };
+
+    let mut offset = 0;
+    while let Some(sign) = chars.next_if(|c| *c == '+' || *c == '-') {
+        let amount = match chars.peek() {
+            Some(c) if c.is_ascii_digit() => parse_number(chars) as isize,
+            _ => 1,
+        };
+        offset += if sign == '+' { amount } else { -amount };
+    }
+    Ok(Some(Address { base, offset }))
+}
+
+fn parse_number(chars: &mut Peekable<Chars>) -> usize {
+    let mut number = 0usize;
+    while let Some(digit) = chars.next_if(|c| c.is_ascii_digit()) {
+        number = number
+            .saturating_mul(10)
+            .saturating_add(digit.to_digit(10).unwrap() as usize);
+    }
+    number
+}
+
+impl Address {
+    /// works out the 1-indexed line this address points at, counting from `current`. a line past
+    /// the end is the last line when `clamp` is set, and E16 otherwise
+    fn resolve(
+        &self,
+        current: usize,
+        content_buffer: &GapBuffer<GapBuffer<char>>,
+        marks: &HashMap<char, (usize, usize)>,
+        options: &Options,
+        clamp: bool,
+    ) -> Result<usize, String> {
+        let last = content_buffer.get_filled_items();
+        let line = match &self.base {
+            AddressBase::Line(line) => *line,
+            AddressBase::Current => current,
+            AddressBase::Last => last,
+            AddressBase::Mark(mark) => match marks.get(mark) {
+                Some((line, _)) => *line,
+                None => return Err(String::from("E20: Mark not set")),
+            },
+            AddressBase::Search { pattern, forward } => {
+                let regex = search::compile(pattern, options)?;
+                search_line(content_buffer, current, &regex, *forward)
+                    .ok_or_else(|| format!("E486: Pattern not found: {pattern}"))?
+            }
+        };
+        let line = line as isize + self.offset;
+        if clamp && line > last as isize {
+            return Ok(last);
+        }
+        if line < 0 || line as usize > last {
+            return Err(String::from("E16: Invalid range"));
+        }
+        Ok(line as usize)
+    }
+}
+
+impl Range {
+    /// the first and last line the range covers, both 1-indexed. a range that wasn't given
+    /// covers `default`, and a range typed backwards is turned around. `options` says whether
+    /// /pattern/ addresses ignore case
+    pub fn resolve(
+        &self,
+        content_buffer: &GapBuffer<GapBuffer<char>>,
+        marks: &HashMap<char, (usize, usize)>,
+        options: &Options,
+        default: (usize, usize),
+    ) -> Result<(usize, usize), String> {
+        self.resolve_lines(content_buffer, marks, options, default, false)
+    }
+
+    /// like `resolve`, but lines past the end are the last line. a bare :99 goes there the way vi
+    /// does, where a command given that range would be E16
+    pub fn resolve_clamped(
+        &self,
+        content_buffer: &GapBuffer<GapBuffer<char>>,
+        marks: &HashMap<char, (usize, usize)>,
+        options: &Options,
+        default: (usize, usize),
+    ) -> Result<(usize, usize), String> {
+        self.resolve_lines(content_buffer, marks, options, default, true)
+    }
+
+    fn resolve_lines(
+        &self,
+        content_buffer: &GapBuffer<GapBuffer<char>>,
+        marks: &HashMap<char, (usize, usize)>,
+        options: &Options,
+        default: (usize, usize),
+        clamp: bool,
+    ) -> Result<(usize, usize), String> {
+        let (start, end) = match (&self.start, &self.end) {
+            (Some(start), Some(end)) => (start, end),
+            _ => return Ok(default),
+        };
+        let current = content_buffer.cursor_position().0;
+        let start = start.resolve(current, content_buffer, marks, options, clamp)?;
+        let from = if self.relative { start } else { current };
+        let end = end.resolve(from, content_buffer, marks, options, clamp)?;
+
+        // line 0 is only meaningful to the commands that go to it, so it means the first line
+        let (start, end) = (start.max(1), end.max(1));
+        Ok((start.min(end), start.max(end)))
+    }
+}
+
+/// the 1-indexed number of the first line after (or before) `from` that `pattern` matches in,
+/// wrapping around the end of the buffer like vim's wrapscan
+fn search_line(
+    content_buffer: &GapBuffer<GapBuffer<char>>,
+    from: usize,
+    regex: &Regex,
+    forward: bool,
+) -> Option<usize> {
+    let last = content_buffer.get_filled_items();
+    (1..=last)
+        .map(|step| {
+            if forward {
+                (from - 1 + step) % last
+            } else {
+                (from - 1 + last * 2 - step) % last
+            }
+        })
+        .find(|index| regex.next_match(content_buffer, (*index, 0)).is_some())
+        .map(|index| index + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(base: AddressBase, offset: isize) -> Option<Address> {
+        Some(Address { base, offset })
+    }
+
+    #[test]
+    fn abbreviations_and_bang() {
+        let command = parse("q!").unwrap();
+        assert_eq!(command.command, Command::Quit);
+        assert!(command.bang);
+
+        assert_eq!(parse("wq").unwrap().command, Command::WriteQuit(None));
+        assert_eq!(parse("undol").unwrap().command, Command::Undolist);
+        assert_eq!(parse("x").unwrap().command, Command::Exit(None));
+        assert_eq!(parse("u").unwrap().command, Command::Undo);
+        assert_eq!(parse("reg").unwrap().command, Command::Registers(None));
+        assert_eq!(parse("noh").unwrap().command, Command::Nohlsearch);
+        assert_eq!(
+            parse("di a1").unwrap().command,
+            Command::Registers(Some(String::from("a1")))
+        );
+        assert_eq!(
+            parse("w other.txt").unwrap().command,
+            Command::Write(Some(String::from("other.txt")))
+        );
+        assert_eq!(
+            parse("foo").unwrap_err(),
+            "E492: Not an editor command: foo"
+        );
+    }
+
+    #[test]
+    fn every_command_is_parsed() {
+        // only :undolist may come out as Command::Undolist, and none of them may reach the
+        // unreachable arm
+        for (name, _) in COMMANDS {
+            let command = parse(name).map(|command| command.command);
+            assert_eq!(
+                command == Ok(Command::Undolist),
+                name == "undolist",
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn ranges() {
+        let range = parse("5,12w part.txt").unwrap().range;
+        assert_eq!(range.start, address(AddressBase::Line(5), 0));
+        assert_eq!(range.end, address(AddressBase::Line(12), 0));
+
+        let range = parse(".,$-2d").unwrap().range;
+        assert_eq!(range.start, address(AddressBase::Current, 0));
+        assert_eq!(range.end, address(AddressBase::Last, -2));
+
+        let range = parse("'a;/end/+1d").unwrap().range;
+        assert!(range.relative);
+        assert_eq!(range.start, address(AddressBase::Mark('a'), 0));
+        let search = AddressBase::Search {
+            pattern: String::from("end"),
+            forward: true,
+        };
+        assert_eq!(range.end, address(search, 1));
+
+        let goto = parse("10").unwrap();
+        assert_eq!(goto.command, Command::Goto);
+        assert_eq!(goto.range.end, address(AddressBase::Line(10), 0));
+    }
+
+    #[test]
+    fn resolve_against_a_buffer() {
+        let content_buffer = GapBuffer::build_nested("one\ntwo\nthree\nfour\n");
+        let marks = HashMap::from([('a', (2, 1))]);
+        let resolve = |input: &str| {
+            parse(input)
+                .unwrap()
+                .range
+                .resolve(&content_buffer, &marks, &Options::new(), (1, 1))
+        };
+
+        assert_eq!(resolve("%d"), Ok((1, 4)));
+        assert_eq!(resolve("'a,$d"), Ok((2, 4)));
+        assert_eq!(resolve("/thr/d"), Ok((3, 3)));
+        assert_eq!(resolve("/^t\\w*o$/d"), Ok((2, 2)));
+        assert_eq!(resolve("?one?,+1d"), Ok((1, 2)));
+        assert_eq!(resolve("3,1d"), Ok((1, 3)));
+        assert!(resolve("9d").is_err());
+        assert!(resolve("'b d").is_err());
+
+        let goto = |input: &str| {
+            parse(input).unwrap().range.resolve_clamped(
+                &content_buffer,
+                &marks,
+                &Options::new(),
+                (1, 1),
+            )
+        };
+        assert_eq!(goto("99"), Ok((4, 4)));
+        assert_eq!(goto("2"), Ok((2, 2)));
+        assert!(goto("-9").is_err());
+    }
+
+    #[test]
+    fn substitute() {
+        let substitute = |input: &str| match parse(input).unwrap().command {
+            Command::Substitute(substitute) => substitute,
+            other => panic!("not a substitute: {other:?}"),
+        };
+        let parts = |pattern: &str, replacement: &str| {
+            Some((String::from(pattern), String::from(replacement)))
+        };
+
+        let command = substitute("s/a\\/b/c d/g 3");
+        assert_eq!(command.parts, parts("a/b", "c d"));
+        assert_eq!(command.flags, "g");
+        assert_eq!(command.count, Some(3));
+
+        assert_eq!(substitute("s#x#\\1").parts, parts("x", "\\1"));
+        assert_eq!(substitute("s").parts, None);
+        let command = substitute("&&");
+        assert_eq!(command.parts, None);
+        assert_eq!(command.flags, "&");
+        assert_eq!(
+            parse("%&&").unwrap().range.start,
+            address(AddressBase::Line(1), 0)
+        );
+
+        assert_eq!(
+            parse("s/a/b/gx").unwrap_err(),
+            "E488: Trailing characters: x"
+        );
+        assert_eq!(
+            parse("s/a/b/ 0").unwrap_err(),
+            "E939: Positive count required"
+        );
+    }
+
+    #[test]
+    fn global() {
+        let command = parse("g/^$/,/./-j").unwrap().command;
+        assert_eq!(
+            command,
+            Command::Global {
+                pattern: String::from("^$"),
+                command: String::from(",/./-j"),
+                invert: false,
+            }
+        );
+        let command = parse("v/keep/d").unwrap().command;
+        assert!(matches!(command, Command::Global { invert: true, .. }));
+        let command = parse("g!/a\\/b/").unwrap().command;
+        assert_eq!(
+            command,
+            Command::Global {
+                pattern: String::from("a/b"),
+                command: String::new(),
+                invert: true,
+            }
+        );
+        assert!(parse("gxfoox").is_err());
+        assert_eq!(
+            parse("g").unwrap_err(),
+            "E148: Regular expression missing from :global"
+        );
+    }
+
+    #[test]
+    fn normal() {
+        let command = parse("%norm! Ax ").unwrap();
+        assert_eq!(command.command, Command::Normal(String::from("Ax ")));
+        assert!(command.bang);
+        assert!(command.range.pair);
+        assert_eq!(
+            parse("normal @q").unwrap().command,
+            Command::Normal(String::from("@q"))
+        );
+        assert!(parse("nor x").is_err());
+        assert_eq!(parse("norm").unwrap_err(), "E471: Argument required");
+    }
+
+    #[test]
+    fn buffer_commands() {
+        assert_eq!(
+            parse("b 3").unwrap().command,
+            Command::Buffer(Some(String::from("3")))
+        );
+        assert_eq!(
+            parse("buffer#").unwrap().command,
+            Command::Buffer(Some(String::from("#")))
+        );
+        assert_eq!(parse("bn").unwrap().command, Command::Bnext);
+        assert_eq!(parse("bN").unwrap().command, Command::Bprevious);
+        assert_eq!(parse("bprev").unwrap().command, Command::Bprevious);
+        let command = parse("bd! 2").unwrap();
+        assert_eq!(command.command, Command::Bdelete(Some(String::from("2"))));
+        assert!(command.bang);
+        assert_eq!(parse("ls").unwrap().command, Command::Buffers);
+        assert_eq!(parse("buffers").unwrap().command, Command::Buffers);
+        assert_eq!(parse("files").unwrap().command, Command::Buffers);
+        assert_eq!(parse("3bn").unwrap_err(), "E481: No range allowed");
+    }
+
+    #[test]
+    fn window_commands() {
+        assert_eq!(
+            parse("sp").unwrap().command,
+            Command::Split {
+                vertical: false,
+                file_name: None
+            }
+        );
+        assert_eq!(
+            parse("vs b.txt").unwrap().command,
+            Command::Split {
+                vertical: true,
+                file_name: Some(String::from("b.txt"))
+            }
+        );
+        assert_eq!(
+            parse("vne").unwrap().command,
+            Command::New { vertical: true }
+        );
+        assert_eq!(parse("clo").unwrap().command, Command::Close);
+        assert_eq!(parse("on").unwrap().command, Command::Only);
+        // a single q is still :quit
+        assert_eq!(parse("q").unwrap().command, Command::Quit);
+        assert!(parse("qa!").unwrap().bang);
+        assert_eq!(parse("new x").unwrap_err(), "E488: Trailing characters: x");
+    }
+
+    #[test]
+    fn tab_commands() {
+        assert_eq!(parse("tabnew").unwrap().command, Command::Tabnew(None));
+        assert_eq!(
+            parse("tabe a.txt").unwrap().command,
+            Command::Tabnew(Some(String::from("a.txt")))
+        );
+        assert_eq!(parse("tabc").unwrap().command, Command::Tabclose);
+        assert_eq!(parse("tabo").unwrap().command, Command::Tabonly);
+        assert_eq!(
+            parse("tabn").unwrap_err(),
+            "E492: Not an editor command: tabn"
+        );
+    }
+
+    #[test]
+    fn range_not_allowed() {
+        assert_eq!(parse("3q").unwrap_err(), "E481: No range allowed");
+        assert_eq!(parse("d!").unwrap_err(), "E477: No ! allowed");
+    }
+}
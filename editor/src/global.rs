@@ -0,0 +1,87 @@
+use crate::config::EditorMode;
+use crate::search;
+use crate::{execute_command, place_cursor, EditorConfig};
+
+/// :g/pat/cmd, and :v/pat/cmd with `invert`. every line of the range that matches, or doesn't
+/// for :v, is marked first and then `command` runs with the cursor on each marked line that is
+/// still there. the marks are kept on the lines themselves, so they stay right however many
+/// lines the command deletes or adds before them. all of it is undone with a single u
+pub fn global(
+    editor_config: &mut EditorConfig,
+    pattern: &str,
+    command: &str,
+    invert: bool,
+    (start, end): (usize, usize),
+) {
+    let editor_state = &mut editor_config.editor_state;
+    if editor_state.in_global {
+        editor_state.message = Some(String::from("E147: Cannot do :global recursive"));
+        return;
+    }
+    let pattern = match pattern {
+        "" => match &editor_state.search.pattern {
+            Some(pattern) => pattern.clone(),
+            None => {
+                editor_state.message = Some(String::from("E35: No previous regular expression"));
+                return;
+            }
+        },
+        pattern => String::from(pattern),
+    };
+    let regex = match search::compile(&pattern, &editor_state.options) {
+        Ok(regex) => regex,
+        Err(e) => {
+            editor_state.message = Some(e);
+            return;
+        }
+    };
+    editor_state.search.pattern = Some(pattern.clone());
+    editor_state.search.highlight = true;
+
+    let content_buffer = &mut editor_config.gap_buffer;
+    content_buffer.clear_marks();
+    let mut marked = 0;
+    for line in start - 1..end {
+        if regex.next_match(content_buffer, (line, 0)).is_some() != invert {
+            content_buffer.set_marked(line);
+            marked += 1;
+        }
+    }
+    if marked == 0 {
+        editor_state.message = Some(match invert {
+            false => format!("Pattern not found: {pattern}"),
+            true => format!("Pattern found in every line: {pattern}"),
+        });
+        return;
+    }
+
+    // with no command the lines are listed, as :p would
+    let command = match command {
+        "" => "p",
+        command => command,
+    };
+    let lines_before = content_buffer.get_filled_items();
+    editor_state.in_global = true;
+    editor_config.undo_tree.begin(content_buffer);
+    editor_config.undo_tree.hold();
+    while let Some(line) = editor_config.gap_buffer.take_marked() {
+        place_cursor(&mut editor_config.gap_buffer, (line, 0));
+        execute_command(command, editor_config);
+        if editor_config.editor_state.editor_mode == EditorMode::ShutDown {
+            break;
+        }
+    }
+    let content_buffer = &mut editor_config.gap_buffer;
+    content_buffer.clear_marks();
+    editor_config.undo_tree.release();
+    editor_config.undo_tree.commit(content_buffer);
+
+    let editor_state = &mut editor_config.editor_state;
+    editor_state.in_global = false;
+    let lines_after = content_buffer.get_filled_items();
+    if lines_before > lines_after + 2 {
+        editor_state.message = Some(format!("{} fewer lines", lines_before - lines_after));
+    } else if lines_after > lines_before + 2 {
+        editor_state.message = Some(format!("{} more lines", lines_after - lines_before));
+    }
+}
@@ -1,28 +1,76 @@
 pub mod config;
 pub use config::FileData;
+mod buffer;
+mod clipboard;
+mod ex;
+mod global;
+mod macros;
+mod motion;
+mod operator;
+mod ops;
+mod regex;
+mod register;
+mod repeat;
+mod search;
+mod substitute;
+mod tab;
+mod textobject;
 mod tui;
-use config::{EditorMode, EditorState};
+mod undo;
+mod visual;
+mod window;
+use buffer::Buffer;
+use config::{EditorMode, EditorState, PendingCommand};
+use ex::Command;
 use gap_buffer::GapBuffer;
+use motion::{Find, Motion};
+use ops::{Operator, RangeKind, TextRange};
+use register::{Register, Registers};
 use std::env;
 use std::error::Error;
 use std::fs;
-use std::io::{self, Read, Write};
+use std::io::{self, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
 use std::process;
-use terminol::{cursor, Cursor};
+use terminol::{cursor, Key, KeyCode, KeyReader, Modifiers};
 use termios::Termios;
+use tui::Viewport;
+use undo::UndoTree;
 
-const RIGHT_SIDE_PADDING: usize = 50;
+/// the byte the terminal sends for the backspace key
+const BACKSPACE: char = '\x7f';
 
 struct EditorConfig {
     editor_state: EditorState,
     original_settings: Termios,
     file_data: FileData,
     gap_buffer: GapBuffer<GapBuffer<char>>,
+    viewport: Viewport,
+    undo_tree: UndoTree,
+    /// whether the buffer differs from the file on disk, shown as [+] in the information bar
+    modified: bool,
+    /// the last changedtick of the buffer that was seen, a new one means something was edited
+    changedtick: u64,
+    /// the undo change the file on disk matches, undoing back to it makes the buffer unmodified
+    saved_seq: usize,
+    registers: Registers,
+    /// the keys of the command being typed and of the last change, for .
+    repeat: repeat::Recorder,
+    /// the keys of the macro being recorded and the register played last
+    macros: macros::Macros,
+    /// the buffers of the other files that are open
+    buffers: buffer::BufferList,
+    /// the windows the screen is split into, and which one is being edited
+    windows: window::Windows,
+    /// the windows of the other tab pages
+    tabs: tab::TabPages,
+    /// the frame being drawn and the one the terminal shows
+    screen: terminol::Screen,
 }
 
 fn initialize_tui_state() {
     cursor::move_home();
-    cursor::save_cursor_position();
     terminol::enable_alternate_buffer();
     terminol::clear_screen();
 }
@@ -32,377 +80,1256 @@ fn setup_terminal(cmd_args: env::Args) -> EditorConfig {
 
     initialize_tui_state();
 
-    let file_data = FileData::build(cmd_args).unwrap_or_else(|err| {
-        println!("problem parsing args: {err}");
+    let mut files = FileData::build(cmd_args).unwrap_or_else(|err| {
         graceful_exit(&original_settings);
-        process::exit(0);
+        println!("problem parsing args: {err}");
+        process::exit(1);
     });
+    let mut file_data = files.remove(0);
 
-    let file_contents = fs::read_to_string(&file_data.file_name).unwrap();
+    let file_contents = file_data.read_contents().unwrap_or_else(|err| {
+        graceful_exit(&original_settings);
+        println!("problem reading file: {err}");
+        process::exit(1);
+    });
+    if let (true, Some(file_name)) = (file_data.is_new_file(), &file_data.file_name) {
+        editor_state.message = Some(format!("\"{file_name}\" [New File]"));
+    }
 
-    let max_size = terminol::get_terminal_size().ws_col as usize - RIGHT_SIDE_PADDING;
-    let content_buffer = GapBuffer::build_nested(&file_contents, max_size);
+    let content_buffer = GapBuffer::build_nested(&file_contents);
+    let viewport = Viewport::new(&terminol::get_terminal_size());
 
-    let content = content_buffer.get_content();
-    tui::write_existing_file(content);
+    let undo_tree = load_undo_file(&file_data, &file_contents, &mut editor_state);
+    // every other file named on the command line waits in a buffer of its own
+    let mut buffers = buffer::BufferList::new();
+    for file_data in files {
+        let buffer = Buffer::load(file_data, &mut editor_state).unwrap_or_else(|err| {
+            graceful_exit(&original_settings);
+            println!("problem reading file: {err}");
+            process::exit(1);
+        });
+        buffers.add(buffer);
+    }
 
-    cursor::restore_cursor_position();
-    tui::update_tui(&mut editor_state);
+    editor_state.sync_cursor(content_buffer.cursor_position());
 
-    EditorConfig {
+    let mut editor_config = EditorConfig {
         editor_state,
         original_settings,
         file_data,
+        changedtick: content_buffer.changedtick(),
         gap_buffer: content_buffer,
+        viewport,
+        saved_seq: undo_tree.seq_cur(),
+        undo_tree,
+        modified: false,
+        registers: Registers::new(),
+        repeat: repeat::Recorder::default(),
+        macros: macros::Macros::default(),
+        windows: window::Windows::new(buffers.current, viewport),
+        tabs: tab::TabPages::new(),
+        buffers,
+        screen: terminol::Screen::from_terminal(),
+    };
+    redraw(&mut editor_config);
+    editor_config
+}
+/// picks up the undo history left by an earlier session, as long as the file still holds exactly
+/// the text it was written for. anything else starts a fresh tree and says why
+fn load_undo_file(
+    file_data: &FileData,
+    file_contents: &str,
+    editor_state: &mut EditorState,
+) -> UndoTree {
+    let path = match file_data
+        .file_name
+        .as_deref()
+        .and_then(undo::undo_file_path)
+    {
+        Some(path) => path,
+        None => return UndoTree::new(),
+    };
+    match UndoTree::read_file(&path, undo::content_hash(file_contents)) {
+        Ok(Some(undo_tree)) => undo_tree,
+        Ok(None) => UndoTree::new(),
+        Err(e) => {
+            editor_state.message = Some(String::from(e));
+            UndoTree::new()
+        }
     }
 }
 pub fn run(cmd_args: env::Args) -> Result<Termios, Box<dyn Error>> {
     let mut editor_config = setup_terminal(cmd_args);
 
-    let mut command = String::new();
+    let mut key_reader = KeyReader::new();
 
     loop {
-        let mut input = [0u8; 3];
+        let mut key = Key::plain(KeyCode::Unknown);
         // opening reader gets rid of the shell prompt guy
         if editor_config.editor_state.editor_mode != EditorMode::ShutDown {
-            io::stdin().read(&mut input)?;
+            key = match key_reader.read_key() {
+                Ok(key) => key,
+                // the terminal hung up, so there is nobody left to type anything and no
+                // terminal to restore either
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            };
         }
-        match editor_config.editor_state.get_current_mode() {
-            EditorMode::Normal => normal_mode_handler(
-                &input,
-                &mut editor_config.editor_state,
-                &mut editor_config.gap_buffer,
-            ),
-            EditorMode::Insert => insert_mode_handler(
-                &input,
-                &mut editor_config.editor_state,
-                &mut editor_config.gap_buffer,
-            ),
-            EditorMode::Visual => normal_mode_handler(
-                &input,
-                &mut editor_config.editor_state,
-                &mut editor_config.gap_buffer,
-            ),
-            EditorMode::Command => command_mode_handler(
-                &input,
-                &mut editor_config.editor_state,
-                &mut editor_config.gap_buffer,
-                &mut editor_config.file_data,
-                &mut command,
-            ),
-            EditorMode::ShutDown => {
-                graceful_exit(&editor_config.original_settings);
-                break;
-            }
-        };
-        tui::update_tui(&mut editor_config.editor_state);
+        // any key dismisses output that was too long for the command row
+        if !editor_config.editor_state.output.is_empty() {
+            editor_config.editor_state.output.clear();
+            redraw(&mut editor_config);
+            continue;
+        }
+        editor_config.editor_state.message = None;
+        if editor_config.editor_state.editor_mode == EditorMode::ShutDown {
+            graceful_exit(&editor_config.original_settings);
+            break;
+        }
+        macros::record(&key, &mut editor_config);
+        feed_key(&key, &mut editor_config);
+        update_modified(&mut editor_config);
+        redraw(&mut editor_config);
     }
     Ok(editor_config.original_settings)
 }
 
-fn normal_mode_handler(
-    input: &[u8],
-    editor_state: &mut EditorState,
-    content_buffer: &mut GapBuffer<GapBuffer<char>>,
-) {
-    cursor::enable_standard_cursor();
-    let line = Cursor::get_cursor_coords().line;
-    let line_buf = content_buffer.get_nested();
-    match input[0] {
-        b':' => {
+/// draws every window. the one being edited shows the buffer in EditorConfig, the others show
+/// theirs from the buffer list, or the same one when they are on the buffer being edited
+fn redraw(editor_config: &mut EditorConfig) {
+    let rects = window::layout(editor_config);
+    let tabs = tab::labels(editor_config);
+    let EditorConfig {
+        editor_state,
+        file_data,
+        gap_buffer,
+        viewport,
+        modified,
+        buffers,
+        windows,
+        screen,
+        ..
+    } = editor_config;
+    // the windows are drawn in the order they are laid out in
+    let order = |id: usize| rects.iter().position(|(window, _)| *window == id);
+    windows.list.sort_by_key(|window| order(window.id));
+    let active_id = windows.active;
+    let mut panes = Vec::new();
+    let mut active = 0;
+    let mut active_viewport = Some(viewport);
+    for (window, (_, rect)) in windows.list.iter_mut().zip(rects) {
+        let own_viewport = match window.id == active_id {
+            true => active_viewport.take(),
+            false => None,
+        };
+        if let Some(viewport) = own_viewport {
+            active = panes.len();
+            panes.push(tui::Pane {
+                content_buffer: gap_buffer,
+                viewport,
+                rect,
+                cursor: editor_state.cursor,
+                modified: *modified,
+                name: Some(buffer::display_name(file_data)),
+            });
+            continue;
+        }
+        let (content_buffer, modified, file_data) = match buffers.get(window.buffer) {
+            Some(buffer) => (&buffer.gap_buffer, buffer.modified, &buffer.file_data),
+            None => (&*gap_buffer, *modified, &*file_data),
+        };
+        let (line, col) = window::clamp(content_buffer, window.cursor);
+        panes.push(tui::Pane {
+            content_buffer,
+            viewport: &mut window.viewport,
+            rect,
+            cursor: config::Cursor::new(line + 1, col + 1),
+            modified,
+            name: Some(buffer::display_name(file_data)),
+        });
+    }
+    tui::update_tui(screen, editor_state, &tabs, &mut panes, active);
+}
+
+/// handles `key` as if it was typed, so that . can repeat the change it makes. macros and
+/// :normal type their keys through here
+pub(crate) fn feed_key(key: &Key, editor_config: &mut EditorConfig) {
+    repeat::before_key(key, editor_config);
+    handle_key(key, editor_config);
+    repeat::after_key(editor_config);
+}
+
+/// hands `key` to the handler of the mode the editor is in. . comes back through here to type
+/// the keys of the change it repeats
+pub(crate) fn handle_key(key: &Key, editor_config: &mut EditorConfig) {
+    match editor_config.editor_state.get_current_mode() {
+        EditorMode::Normal => normal_mode_handler(key, editor_config),
+        EditorMode::Insert => insert_mode_handler(key, editor_config),
+        EditorMode::Visual => visual::visual_mode_handler(key, editor_config),
+        EditorMode::Command => command_mode_handler(key, editor_config),
+        EditorMode::Search => search::search_prompt_handler(key, editor_config),
+        EditorMode::Confirm => substitute::confirm_handler(key, editor_config),
+        EditorMode::OperatorPending => operator::operator_pending_handler(key, editor_config),
+        EditorMode::ShutDown => (),
+    };
+    // the gaps are the single source of truth for where the cursor is, so re-derive it once
+    // the handler has finished moving them
+    editor_config
+        .editor_state
+        .sync_cursor(editor_config.gap_buffer.cursor_position());
+}
+
+/// any edit marks the buffer modified. once the edit is closed in the undo tree, the undo state
+/// decides instead, so undoing everything since the last write makes the buffer clean again.
+/// besides after every key typed, this runs before anything that checks for changes, since keys
+/// played by a macro, :normal or . all count as the one key that started them
+pub(crate) fn update_modified(editor_config: &mut EditorConfig) {
+    let changedtick = editor_config.gap_buffer.changedtick();
+    if changedtick != editor_config.changedtick {
+        editor_config.changedtick = changedtick;
+        editor_config.modified = true;
+    }
+    if !editor_config.undo_tree.is_open() {
+        editor_config.modified = editor_config.undo_tree.seq_cur() != editor_config.saved_seq;
+    }
+}
+
+fn normal_mode_handler(key: &Key, editor_config: &mut EditorConfig) {
+    let editor_state = &mut editor_config.editor_state;
+    let pending_key = editor_state.pending_key.take();
+    // a count or a register name is kept for the key after it, any other key uses them up
+    let prefix = match (pending_key, key.code, key.modifiers) {
+        (Some('"'), _, _) => true,
+        (None, KeyCode::Char('1'..='9'), Modifiers::NONE) => true,
+        (None, KeyCode::Char('0'), Modifiers::NONE) => editor_state.pending.count.is_some(),
+        _ => false,
+    };
+    match pending_key {
+        Some(first) => two_key_handler(first, key, editor_config),
+        None => normal_key_handler(key, editor_config),
+    }
+    let editor_state = &mut editor_config.editor_state;
+    // the count typed before / or ? is for the search once the pattern has been typed
+    let waiting = editor_state.pending_key.is_some()
+        || matches!(
+            editor_state.editor_mode,
+            EditorMode::OperatorPending | EditorMode::Search
+        );
+    if !prefix && !waiting {
+        editor_state.pending = PendingCommand::default();
+    }
+}
+
+fn normal_key_handler(key: &Key, editor_config: &mut EditorConfig) {
+    let editor_state = &mut editor_config.editor_state;
+    let content_buffer = &mut editor_config.gap_buffer;
+    match (key.code, key.modifiers) {
+        (KeyCode::Char(':'), Modifiers::NONE) => {
             editor_state.update_editor_mode(EditorMode::Command);
         }
-        // enter | escape | arrow keys| <C-c>
-        b'j' | b'k' | b'l' | b'h' | 13 | 27 | 183 | 184 | 185 | 186 | 3 => {
-            basic_movement_handler(input, content_buffer, editor_state);
+        (KeyCode::Char(digit @ '1'..='9'), Modifiers::NONE) => {
+            editor_state
+                .pending
+                .push_digit(digit.to_digit(10).unwrap_or(0));
+        }
+        (KeyCode::Char('0'), Modifiers::NONE) if editor_state.pending.count.is_some() => {
+            editor_state.pending.push_digit(0);
+        }
+        // escape | arrow keys | <C-c>
+        (KeyCode::Char('j' | 'k' | 'l' | 'h'), Modifiers::NONE)
+        | (KeyCode::Esc | KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right, _)
+        | (KeyCode::Char('c'), Modifiers::CTRL) => {
+            let count = editor_state.pending.total_count().unwrap_or(1);
+            for _ in 0..count {
+                basic_movement_handler(key, content_buffer, editor_state);
+            }
         }
-        b'i' => {
+        (KeyCode::Char('i'), Modifiers::NONE) => {
+            editor_config.undo_tree.begin(content_buffer);
+            editor_state.insert_count = editor_state.pending.total_count().unwrap_or(1);
             editor_state.update_editor_mode(EditorMode::Insert);
         }
-        b'v' => editor_state.update_editor_mode(EditorMode::Visual),
-        b'a' => {
-            move_right(line_buf);
+        (KeyCode::Char('v'), Modifiers::NONE) => visual::enter(editor_config, RangeKind::Char),
+        (KeyCode::Char('V'), Modifiers::NONE) => visual::enter(editor_config, RangeKind::Line),
+        (KeyCode::Char('v'), Modifiers::CTRL) => visual::enter(editor_config, RangeKind::Block),
+        (KeyCode::Char(c @ ('p' | 'P')), Modifiers::NONE) => put_handler(
+            editor_config,
+            Put {
+                before: c == 'P',
+                cursor_after: false,
+                match_indent: false,
+            },
+        ),
+        (KeyCode::Char('a'), Modifiers::NONE) => {
+            editor_config.undo_tree.begin(content_buffer);
+            editor_state.insert_count = editor_state.pending.total_count().unwrap_or(1);
+            // an empty line only holds its newline, appending there must not step past it
+            let line_buf = content_buffer.get_nested();
+            if line_buf.get_len() != 0 {
+                line_buf.move_gap_right();
+            }
             editor_state.update_editor_mode(EditorMode::Insert);
         }
-        b'0' => {
-            line_buf.reset();
-            cursor::move_cursor_to(line, 1);
+        (KeyCode::Char(c @ ('/' | '?')), Modifiers::NONE) => {
+            search::open_prompt(editor_config, c == '/')
         }
-
-        b'$' => {
-            //let line = Cursor::get_cursor_coords().line;
-            //let len = line_buf.grab_to_end(true).len();
-            // move to char at the end of the line
-            let col = line_buf.move_to_last_char();
-            cursor::move_cursor_to(line, col + 1);
+        (KeyCode::Char(c @ ('n' | 'N')), Modifiers::NONE) => search::jump(editor_config, c == 'N'),
+        (KeyCode::Char(c @ ('*' | '#')), Modifiers::NONE) => {
+            search::search_word(editor_config, c == '*', true)
+        }
+        // the last :s again on this line, without its flags
+        (KeyCode::Char('&'), Modifiers::NONE) => execute_command("s", editor_config),
+        (KeyCode::Home, _) => motion_handler(editor_config, Motion::LineStart),
+        (KeyCode::End, _) => motion_handler(editor_config, Motion::LineEnd),
+        (
+            KeyCode::Char(
+                c @ ('g' | 'z' | 'm' | 'Z' | '"' | 'f' | 'F' | 't' | 'T' | ']' | '[' | 'r' | '@'),
+            ),
+            Modifiers::NONE,
+        ) => editor_state.pending_key = Some(c),
+        // ^W is kept as the control char it is typed as
+        (KeyCode::Char('w'), Modifiers::CTRL) => editor_state.pending_key = Some('\u{17}'),
+        (KeyCode::Char('d' | 'u' | 'f' | 'b'), Modifiers::CTRL)
+        | (KeyCode::PageUp | KeyCode::PageDown, _) => {
+            scroll_handler(key, content_buffer, &mut editor_config.viewport)
         }
-        b'w' => {
-            let i = line_buf.move_to_next_word();
-            for _ in 0..i {
-                cursor::move_right(1);
+        (KeyCode::Char('u'), Modifiers::NONE) => undo_handler(editor_config, UndoStep::Undo),
+        (KeyCode::Char('r'), Modifiers::CTRL) => undo_handler(editor_config, UndoStep::Redo),
+        (KeyCode::Char('U'), Modifiers::NONE) => undo_line(editor_config),
+        (KeyCode::Char('.'), Modifiers::NONE) => repeat::replay(editor_config),
+        (KeyCode::Char('q'), Modifiers::NONE) => match editor_state.recording {
+            Some(_) => macros::stop(editor_config),
+            None => editor_state.pending_key = Some('q'),
+        },
+        (KeyCode::Char('x'), Modifiers::NONE) => delete_chars(editor_config),
+        (KeyCode::Char('J'), Modifiers::NONE) => {
+            // a count of 1 joins two lines just like no count does
+            let count = editor_state.pending.total_count().unwrap_or(2).max(2);
+            let line = content_buffer.cursor_position().0 - 1;
+            let last_line = content_buffer.get_filled_items() - 1;
+            let range = TextRange {
+                kind: RangeKind::Line,
+                start: (line, 0),
+                end: ((line + count - 1).min(last_line), 0),
+            };
+            operator::apply(editor_config, Operator::Join, range);
+        }
+        (KeyCode::Char(c), Modifiers::NONE) => {
+            if let Some(operator) = Operator::from_key(false, c) {
+                operator::start(editor_config, operator);
+            } else if let Some(motion) = Motion::from_key(c) {
+                motion_handler(editor_config, motion);
             }
         }
         _ => (),
     }
 }
 
-fn enter_handler(content_buffer: &mut GapBuffer<GapBuffer<char>>) {
-    let line = Cursor::get_cursor_coords().line;
-    content_buffer.move_line_contents_enter(line);
+/// handles the second key of the two key commands gg, ge, gE, g-, g+, gv, g*, g#, g&, gp, gP,
+/// ]p, [p, gt, gT, zz, zt, zb, ZZ, ZQ, m{a-z}, r{char}, q{reg} and @{reg}, the char looked for
+/// by f, F, t and T, the register named after " and the operators that start with g, and the
+/// window commands after ^W
+fn two_key_handler(first: char, second: &Key, editor_config: &mut EditorConfig) {
+    if first == '\u{17}' {
+        return window::ctrl_w(editor_config, second);
+    }
+    let content_buffer = &mut editor_config.gap_buffer;
+    let viewport = &mut editor_config.viewport;
+    let (line, _) = content_buffer.cursor_position();
+    let line = line - 1;
+    let second = match (second.code, second.modifiers) {
+        (KeyCode::Char(c), Modifiers::NONE | Modifiers::SHIFT) => c,
+        _ => return,
+    };
+    if let Some(find) = Find::from_key(first) {
+        return motion_handler(editor_config, Motion::FindChar(find, second));
+    }
+    match (first, second) {
+        ('"', name) => editor_config.editor_state.pending.register = Some(name),
+        ('g', '-') => undo_handler(editor_config, UndoStep::Earlier),
+        ('g', '+') => undo_handler(editor_config, UndoStep::Later),
+        ('g', 'v') => visual::reselect(editor_config),
+        ('g', c @ ('*' | '#')) => search::search_word(editor_config, c == '*', false),
+        ('g', '&') => substitute::repeat_everywhere(editor_config),
+        ('g', 't') => tab::next(
+            editor_config,
+            editor_config.editor_state.pending.total_count(),
+        ),
+        ('g', 'T') => tab::previous(
+            editor_config,
+            editor_config.editor_state.pending.total_count(),
+        ),
+        ('g' | ']' | '[', c @ ('p' | 'P')) => put_handler(
+            editor_config,
+            Put {
+                before: c == 'P' || first == '[',
+                cursor_after: first == 'g',
+                match_indent: first != 'g',
+            },
+        ),
+        ('g', c) => {
+            if let Some(operator) = Operator::from_key(true, c) {
+                operator::start(editor_config, operator);
+            } else if let Some(motion) = Motion::from_g_key(c) {
+                motion_handler(editor_config, motion);
+            }
+        }
+        ('z', 'z') => viewport.center_on(line),
+        ('z', 't') => viewport.top_on(line),
+        ('z', 'b') => viewport.bottom_on(line),
+        ('Z', 'Z') => execute_command("x", editor_config),
+        ('Z', 'Q') => execute_command("q!", editor_config),
+        ('m', mark @ 'a'..='z') => {
+            let position = content_buffer.cursor_position();
+            editor_config.editor_state.marks.insert(mark, position);
+        }
+        ('r', c) => replace_chars(editor_config, c),
+        ('q', name) => macros::start(editor_config, name),
+        ('@', name) => macros::play(editor_config, name),
+        _ => (),
+    }
+}
 
-    let content = content_buffer.get_content();
-    let mut content = content.lines();
-    let len = content_buffer.get_filled_items();
+/// x, deletes the char under the cursor and the count - 1 after it, but never the newline
+fn delete_chars(editor_config: &mut EditorConfig) {
+    let content_buffer = &editor_config.gap_buffer;
+    let count = editor_config
+        .editor_state
+        .pending
+        .total_count()
+        .unwrap_or(1);
+    let (line, col) = content_buffer.cursor_position();
+    let (line, col) = (line - 1, col - 1);
+    let len = ops::line_chars(content_buffer, line)
+        .iter()
+        .filter(|c| **c != '\n')
+        .count();
+    if col >= len {
+        return;
+    }
+    let range = TextRange {
+        kind: RangeKind::Char,
+        start: (line, col),
+        end: (line, (col + count).min(len) - 1),
+    };
+    operator::apply(editor_config, Operator::Delete, range);
+}
 
-    for _ in 0..line {
-        content.next();
+/// r{char}, puts `c` in place of the char under the cursor and the count - 1 after it. nothing
+/// happens when the line hasn't got that many chars left
+fn replace_chars(editor_config: &mut EditorConfig, c: char) {
+    let content_buffer = &mut editor_config.gap_buffer;
+    let count = editor_config
+        .editor_state
+        .pending
+        .total_count()
+        .unwrap_or(1);
+    let (line, col) = content_buffer.cursor_position();
+    let (line, col) = (line - 1, col - 1);
+    let len = ops::line_chars(content_buffer, line)
+        .iter()
+        .filter(|c| **c != '\n')
+        .count();
+    if col + count > len {
+        return;
     }
+    let range = TextRange {
+        kind: RangeKind::Char,
+        start: (line, col),
+        end: (line, col + count - 1),
+    };
+    editor_config.undo_tree.begin(content_buffer);
+    ops::map_chars(content_buffer, &range, |_| c);
+    editor_config.undo_tree.commit(content_buffer);
+    place_cursor(content_buffer, range.end);
+}
 
-    terminol::clear_end_of_screen();
-    cursor::move_cursor_to(line + 1, 1);
+/// moves the cursor where `motion` goes, given the count typed before it
+fn motion_handler(editor_config: &mut EditorConfig, motion: Motion) {
+    let editor_state = &mut editor_config.editor_state;
+    let content_buffer = &mut editor_config.gap_buffer;
+    if let Motion::FindChar(find, c) = motion {
+        editor_state.last_find = Some((find, c));
+    }
+    let context = motion::Context {
+        count: editor_state.pending.total_count(),
+        screen: (editor_config.viewport.top, editor_config.viewport.bottom()),
+        last_find: editor_state.last_find,
+        last_search: search::last_search(editor_state),
+        wrapscan: editor_state.options.wrapscan,
+    };
+    let (line, col) = content_buffer.cursor_position();
+    if let Some((target, _)) = motion::target(content_buffer, (line - 1, col - 1), motion, &context)
+    {
+        place_cursor(content_buffer, target);
+    }
+}
 
-    tui::update_below(line, len, content);
+enum UndoStep {
+    /// u, back to the parent state
+    Undo,
+    /// <C-r>, forward along the branch last visited
+    Redo,
+    /// g-, back to the previous state in time, whichever branch it is on
+    Earlier,
+    /// g+, forward to the next state in time
+    Later,
 }
 
-fn insert_mode_handler(
-    input: &[u8],
-    editor_state: &mut EditorState,
-    content_buffer: &mut GapBuffer<GapBuffer<char>>,
-) {
-    match input[0] {
-        // return/enter
-        13 => {
-            enter_handler(content_buffer);
+fn undo_handler(editor_config: &mut EditorConfig, step: UndoStep) {
+    let undo_tree = &mut editor_config.undo_tree;
+    let content_buffer = &mut editor_config.gap_buffer;
+    let cursor = match step {
+        UndoStep::Undo => undo_tree.undo(content_buffer),
+        UndoStep::Redo => undo_tree.redo(content_buffer),
+        UndoStep::Earlier => match undo_tree.seq_cur() {
+            0 => None,
+            seq => undo_tree.goto_change(seq - 1, content_buffer),
+        },
+        UndoStep::Later => undo_tree.goto_change(undo_tree.seq_cur() + 1, content_buffer),
+    };
+    match cursor {
+        Some(cursor) => content_buffer.set_cursor_position(cursor),
+        None => {
+            let message = match step {
+                UndoStep::Undo | UndoStep::Earlier => "Already at oldest change",
+                UndoStep::Redo | UndoStep::Later => "Already at newest change",
+            };
+            editor_config.editor_state.message = Some(String::from(message));
         }
-        //backspace
-        127 => {
-            // if at beginning of line then move the lines contents up to the last line
-            // only if it does not exceed the limit for length of the terminal window
-            let line_buf = content_buffer.get_nested();
+    }
+}
 
-            if line_buf.is_buf_begin() {
-                terminol::clear_end_of_screen();
-                let line = Cursor::get_cursor_coords().line;
-                // handle the backspace data structure operation
-                let line_len = content_buffer.move_line_contents_backspace(line);
-                let gui_len = line_len + 1;
-
-                let content = content_buffer.get_content();
-                let mut content = content.lines();
-                let len = content_buffer.get_filled_items();
-
-                for _ in 0..line - 2 {
-                    content.next();
-                }
+/// U, puts back the line that was changed last as it was before the run of changes made to it.
+/// this is a change of its own, so it can be undone with u or undone again with another U
+fn undo_line(editor_config: &mut EditorConfig) {
+    let undo_tree = &mut editor_config.undo_tree;
+    let content_buffer = &mut editor_config.gap_buffer;
+    let (line, original) = match undo_tree.line_undo.take() {
+        Some(line_undo) => line_undo,
+        None => return,
+    };
+    let current = match content_buffer.get(line) {
+        Some(line_buf) => line_buf.get_text(),
+        None => return,
+    };
 
-                cursor::move_cursor_to(line - 1, gui_len);
-                tui::update_below(line - 2, len, content);
-            } else {
-                terminol::clear_end_of_line();
+    undo_tree.begin(content_buffer);
+    content_buffer.replace_lines(line, 1, &[original]);
+    undo_tree.commit(content_buffer);
+    undo_tree.line_undo = Some((line, current));
+    content_buffer.set_cursor_position((line + 1, 1));
+}
 
-                line_buf.delete_item();
-                cursor::backspace();
-                let line_end = line_buf.grab_to_end(false);
+/// the ways of putting text back from a register
+#[derive(Clone, Copy)]
+struct Put {
+    /// P rather than p
+    before: bool,
+    /// gp and gP, which leave the cursor just after the text
+    cursor_after: bool,
+    /// ]p and [p, which indent the lines put to match the cursor line
+    match_indent: bool,
+}
 
-                // tell tui that we need to update the line we're on with the content we just got
-                // after our current position
-                cursor::write_char(&input[0]);
-                tui::update_line(line_end);
+/// the register called `name` as put would put it. "% is the file name, and "+ and "* are read
+/// from the system clipboard when there is a tool for it
+fn read_register(editor_config: &mut EditorConfig, name: Option<char>) -> Option<Register> {
+    match name {
+        Some('%') => editor_config
+            .file_data
+            .file_name
+            .as_ref()
+            .map(|file_name| Register {
+                text: vec![file_name.clone()],
+                kind: RangeKind::Char,
+            }),
+        Some(name @ ('+' | '*')) => {
+            let last_copied = editor_config.registers.get(Some(name));
+            let clipcmd = &editor_config.editor_state.options.clipcmd;
+            let path = env::var_os("PATH").unwrap_or_default();
+            match clipboard::paste(name, last_copied.clone(), clipcmd, &path) {
+                Some(Ok(register)) => Some(register),
+                Some(Err(e)) => {
+                    editor_config.editor_state.message = Some(e);
+                    last_copied
+                }
+                None => last_copied,
             }
         }
-        // <C-c> | Esc
-        3 | 27 | 183 | 184 | 185 | 186 => {
-            basic_movement_handler(input, content_buffer, editor_state);
-        }
-        _ => {
-            let max_size = terminol::get_terminal_size().ws_col as usize - RIGHT_SIDE_PADDING;
-            let line_buf_items = content_buffer.get_nested().get_filled_items();
-
-            if line_buf_items + 1 >= max_size {
-                enter_handler(content_buffer);
-            }
-
-            let line_buf = content_buffer.get_nested();
+        name => editor_config.registers.get(name),
+    }
+}
 
-            if line_buf.is_line_end() {
-                line_buf.insert_left(input[0] as char);
-                cursor::write_char(&input[0]);
-            } else {
-                terminol::clear_end_of_line();
-                // grab from 1 after the gaps end until the next newline or until the eof
-                let line_end = line_buf.grab_to_end(false);
-                // insert the char to the gap and the tui
-                line_buf.insert_left(input[0] as char);
-                cursor::write_char(&input[0]);
-                // tell tui that we need to update the line we're on with the content we just got
-                // after our current position
-                tui::update_line(line_end);
-            }
+/// p, P, gp, gP, ]p and [p, puts the text last yanked or deleted, or what is in the register
+/// named with "x, after or before the cursor, as many times over as the count says
+fn put_handler(editor_config: &mut EditorConfig, put: Put) {
+    let name = editor_config.editor_state.pending.register;
+    let count = editor_config
+        .editor_state
+        .pending
+        .total_count()
+        .unwrap_or(1);
+    let mut register = match read_register(editor_config, name) {
+        Some(register) => register.repeated(count),
+        None => {
+            editor_config.editor_state.message =
+                Some(format!("E353: Nothing in register {}", name.unwrap_or('"')));
+            return;
         }
     };
+    let content_buffer = &mut editor_config.gap_buffer;
+    let (line, col) = content_buffer.cursor_position();
+    if put.match_indent && register.kind == RangeKind::Line {
+        let indent: String = ops::line_chars(content_buffer, line - 1)
+            .into_iter()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .collect();
+        register.text = ops::reindented(&register.text, &indent);
+    }
+
+    editor_config.undo_tree.begin(content_buffer);
+    let (cursor, after) = ops::put(
+        content_buffer,
+        (line - 1, col - 1),
+        &register.text,
+        register.kind,
+        put.before,
+    );
+    editor_config.undo_tree.commit(content_buffer);
+    match put.cursor_after {
+        // just past the text may be past the end of its line, where the cursor can't rest
+        true => {
+            let last_line = content_buffer.get_filled_items() - 1;
+            content_buffer.move_gap_to(after.0.min(last_line));
+            content_buffer.get_nested().move_to_col(after.1);
+        }
+        false => place_cursor(content_buffer, cursor),
+    }
 }
 
-fn basic_movement_handler(
-    input: &[u8],
+/// scrolls the viewport for <C-d>/<C-u> (half a screen) and <C-f>/<C-b> (a screen less two lines
+/// of context), dragging the cursor along so that it stays on screen
+fn scroll_handler(
+    key: &Key,
     content_buffer: &mut GapBuffer<GapBuffer<char>>,
-    editor_state: &mut EditorState,
+    viewport: &mut Viewport,
 ) {
-    let num = input[0] + input[1] + input[2];
-
-    match num {
-        // escape key handler
-        3 | 27 => editor_state.update_editor_mode(EditorMode::Normal),
-        // up arrow or k key
-        183 | b'k' => {
-            if content_buffer.is_first_line() {
-                return;
-            } else {
-                content_buffer.move_gap_left();
-                let line_buf = content_buffer.get_nested();
-                let line_len = line_buf.get_len();
-
-                line_buf.reset();
-
-                let line = Cursor::get_cursor_coords().line;
-                let new_line = line - 1;
+    let last_line = content_buffer.get_filled_items() - 1;
+    let (line, _) = content_buffer.cursor_position();
+    let line = line - 1;
+    let half = (viewport.height / 2).max(1);
+    let page = viewport.height.saturating_sub(2).max(1);
 
-                let col = Cursor::get_cursor_coords().col;
+    let target = match key.code {
+        // <C-d>
+        KeyCode::Char('d') => {
+            viewport.top = (viewport.top + half).min(last_line);
+            line + half
+        }
+        // <C-u>
+        KeyCode::Char('u') => {
+            viewport.top = viewport.top.saturating_sub(half);
+            line.saturating_sub(half)
+        }
+        // <C-f>
+        KeyCode::Char('f') | KeyCode::PageDown => {
+            viewport.top = (viewport.top + page).min(last_line);
+            line.max(viewport.top)
+        }
+        // <C-b>
+        KeyCode::Char('b') | KeyCode::PageUp => {
+            viewport.top = viewport.top.saturating_sub(page);
+            line.min(viewport.bottom())
+        }
+        _ => line,
+    };
+    move_to_line(content_buffer, target.min(last_line));
+}
 
-                let new_col;
+fn enter_handler(content_buffer: &mut GapBuffer<GapBuffer<char>>) {
+    let (line, _) = content_buffer.cursor_position();
+    content_buffer.move_line_contents_enter(line);
+}
 
-                if line_len >= col {
-                    new_col = col;
-                } else {
-                    new_col = line_len;
+fn insert_mode_handler(key: &Key, editor_config: &mut EditorConfig) {
+    let editor_state = &mut editor_config.editor_state;
+    let content_buffer = &mut editor_config.gap_buffer;
+    match (key.code, key.modifiers) {
+        // return/enter
+        (KeyCode::Enter, _) => {
+            enter_handler(content_buffer);
+            editor_state.inserted.push('\n');
+        }
+        //backspace
+        (KeyCode::Backspace, _) | (KeyCode::Char('h'), Modifiers::CTRL) => {
+            if !backspace_handler(content_buffer) {
+                return;
+            }
+            backspace_inserted(&mut editor_state.inserted);
+        }
+        // <C-c> | Esc | arrow keys
+        (KeyCode::Char('c'), Modifiers::CTRL)
+        | (KeyCode::Esc | KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right, _) => {
+            // a count is only used when the insert ends with Esc, <C-c> drops it
+            let count = std::mem::replace(&mut editor_state.insert_count, 1);
+            if key.code == KeyCode::Esc {
+                for _ in 1..count {
+                    type_inserted(content_buffer, &editor_state.inserted);
                 }
-
-                for _ in 1..new_col {
-                    line_buf.move_gap_right();
+            }
+            basic_movement_handler(key, content_buffer, editor_state);
+            // everything typed since entering insert mode is undone as one change
+            if editor_state.editor_mode != EditorMode::Insert {
+                if let Some(block) = editor_state.block_insert.take() {
+                    visual::finish_block_insert(content_buffer, block);
                 }
-
-                cursor::move_cursor_to(new_line, new_col);
+                editor_config.undo_tree.commit(content_buffer);
+                let inserted = std::mem::take(&mut editor_state.inserted);
+                editor_config.registers.last_insert = Some(inserted);
             }
         }
-        // down arrow or j key
-        184 | b'j' => {
-            content_buffer.move_gap_right();
-            if content_buffer.is_last_line() {
-                content_buffer.move_gap_left();
-                return;
-            } else {
-                let line_buf = content_buffer.get_nested();
-                let line_len = line_buf.get_len();
+        (KeyCode::Char(_), Modifiers::NONE | Modifiers::SHIFT) | (KeyCode::Tab, _) => {
+            let c = match key.code {
+                KeyCode::Char(c) => c,
+                _ => '\t',
+            };
+            // the whole viewport is repainted after every key, so only the buffer needs updating
+            content_buffer.get_nested().insert_left(c);
+            editor_state.inserted.push(c);
+        }
+        _ => (),
+    };
+}
 
-                line_buf.reset();
+/// deletes the char before the cursor, or moves the line up onto the one above it when the
+/// cursor is at its start. false on the start of the first line, where there is nothing to delete
+fn backspace_handler(content_buffer: &mut GapBuffer<GapBuffer<char>>) -> bool {
+    let (line, _) = content_buffer.cursor_position();
+    let line_buf = content_buffer.get_nested();
+    if line_buf.is_buf_begin() {
+        if content_buffer.is_first_line() {
+            return false;
+        }
+        // handle the backspace data structure operation
+        content_buffer.move_line_contents_backspace(line);
+    } else {
+        line_buf.delete_item();
+    }
+    true
+}
 
-                let line = Cursor::get_cursor_coords().line;
-                let new_line = line + 1;
+/// types what an insert put in once more at the cursor, for the count given to i and a
+fn type_inserted(content_buffer: &mut GapBuffer<GapBuffer<char>>, inserted: &str) {
+    for c in inserted.chars() {
+        match c {
+            '\n' => enter_handler(content_buffer),
+            BACKSPACE => {
+                backspace_handler(content_buffer);
+            }
+            c => content_buffer.get_nested().insert_left(c),
+        }
+    }
+}
 
-                let col = Cursor::get_cursor_coords().col;
+/// takes the last char typed in this insert back out of `inserted`. a backspace over text that
+/// was there before the insert began is kept as the byte the terminal sends for it instead
+fn backspace_inserted(inserted: &mut String) {
+    match inserted.chars().last() {
+        Some(c) if c != BACKSPACE => {
+            inserted.pop();
+        }
+        _ => inserted.push(BACKSPACE),
+    }
+}
 
-                let new_col;
+/// moves the gap of the outer buffer to `line` (0-indexed) and places the gap of the line we land
+/// on at the column we came from, or at the last char when that line is shorter
+fn move_to_line(content_buffer: &mut GapBuffer<GapBuffer<char>>, line: usize) {
+    let col = content_buffer.nested().gap_begin;
+    place_cursor(content_buffer, (line, col));
+}
 
-                if line_len == 0 {
-                    new_col = 1;
-                } else if line_len >= col {
-                    new_col = col;
-                } else {
-                    new_col = line_len;
-                }
+/// puts the cursor on the 0-indexed `line` and `col`, or on the last char when the line is
+/// shorter, which is where normal mode keeps it
+pub(crate) fn place_cursor(
+    content_buffer: &mut GapBuffer<GapBuffer<char>>,
+    (line, col): (usize, usize),
+) {
+    let last_line = content_buffer.get_filled_items() - 1;
+    content_buffer.move_gap_to(line.min(last_line));
 
-                for _ in 0..(new_col - 1) {
-                    line_buf.move_gap_right();
-                }
+    let line_buf = content_buffer.get_nested();
+    let last_char = line_buf.get_len().saturating_sub(1);
+    line_buf.move_to_col(col.min(last_char));
+}
 
-                cursor::move_cursor_to(new_line, new_col);
+fn basic_movement_handler(
+    key: &Key,
+    content_buffer: &mut GapBuffer<GapBuffer<char>>,
+    editor_state: &mut EditorState,
+) {
+    let code = match (key.code, key.modifiers) {
+        (KeyCode::Char(c), Modifiers::NONE) => match c {
+            'k' => KeyCode::Up,
+            'j' => KeyCode::Down,
+            'l' => KeyCode::Right,
+            'h' => KeyCode::Left,
+            _ => return,
+        },
+        (KeyCode::Char('c'), Modifiers::CTRL) => KeyCode::Esc,
+        (code, _) => code,
+    };
+
+    match code {
+        // escape key handler
+        KeyCode::Esc => editor_state.update_editor_mode(EditorMode::Normal),
+        // up arrow or k key
+        KeyCode::Up => {
+            if content_buffer.is_first_line() {
+                return;
             }
+            let (line, _) = content_buffer.cursor_position();
+            move_to_line(content_buffer, line - 2);
+        }
+        // down arrow or j key
+        KeyCode::Down => {
+            let (line, _) = content_buffer.cursor_position();
+            move_to_line(content_buffer, line);
         }
         // right arrow or l key
-        185 | b'l' => {
+        KeyCode::Right => {
             let line_buf = content_buffer.get_nested();
             if !line_buf.is_line_end() {
-                move_right(line_buf);
+                line_buf.move_gap_right();
             }
         }
         // left arrow or h key
-        186 | b'h' => {
+        KeyCode::Left => {
             let line_buf = content_buffer.get_nested();
             if line_buf.is_buf_begin() {
                 return;
             }
-            move_left(line_buf);
+            line_buf.move_gap_left();
         }
         _ => (),
     }
 }
 /// handles input given once user has entered 'command' mode. This mode is entered from normal mode
-/// by entering a colon ':' key. Once in this mode the user can enter q to quit the editor or w
-/// to write the current file. Entering 'wq' writes the current file before exiting the program.
-///
-/// function returns Ok with some integer in the case of a valid character. All escape characters
-/// for command mode, including <C-c>
-fn command_mode_handler(
-    input: &[u8],
-    editor_state: &mut EditorState,
-    content_buffer: &mut GapBuffer<GapBuffer<char>>,
-    file_data: &mut FileData,
-    command: &mut String,
-) {
-    editor_state.previous_mode = EditorMode::Command;
-    match input[0] {
+/// by entering a colon ':' key, or from visual mode with the selection already filled in as the
+/// range. The typed line is collected in the editor state and handed to the ex parser once Enter
+/// is hit. Esc or <C-c> throws the line away, as does backspacing past its start
+fn command_mode_handler(key: &Key, editor_config: &mut EditorConfig) {
+    let command = &mut editor_config.editor_state.command;
+    match (key.code, key.modifiers) {
         // return/enter key code
-        13 => {
-            let len = command.len();
-            let mut command_chars = command.chars();
-            for _ in 0..len {
-                command_parser(
-                    &command_chars.next(),
-                    content_buffer,
-                    file_data,
-                    editor_state,
-                )
+        (KeyCode::Enter, _) => {
+            let command = std::mem::take(command);
+            execute_command(&command, editor_config);
+            // a single line of output fits in the command row
+            let output = &mut editor_config.editor_state.output;
+            if output.len() == 1 {
+                editor_config.editor_state.message = output.pop();
+            }
+            if !command.is_empty() {
+                editor_config.registers.last_command = Some(command);
             }
-            if editor_state.editor_mode != EditorMode::ShutDown {
+            let editor_state = &mut editor_config.editor_state;
+            // :s with the c flag stays at its prompt
+            if editor_state.editor_mode == EditorMode::Command {
                 editor_state.update_editor_mode(EditorMode::Normal);
             }
+        }
+        // backspace key code, backspacing past the start of the line leaves it
+        (KeyCode::Backspace, _) if command.pop().is_none() => {
+            editor_config
+                .editor_state
+                .update_editor_mode(EditorMode::Normal);
+        }
+        // <C-c> | Esc
+        (KeyCode::Char('c'), Modifiers::CTRL) | (KeyCode::Esc, _) => {
             String::clear(command);
+            editor_config
+                .editor_state
+                .update_editor_mode(EditorMode::Normal);
         }
-        // backspace key code
-        127 => {
-            command.pop();
-            cursor::backspace();
+        (KeyCode::Char(c), Modifiers::NONE | Modifiers::SHIFT) => {
+            command.push(c);
         }
-        // code for <C-c> | ascii code for Esc
-        3 | 27 => {
-            editor_state.update_editor_mode(EditorMode::Normal);
+        _ => (),
+    };
+}
+
+/// parses a command line, works out the lines its range covers and carries it out. anything
+/// that goes wrong along the way ends up in the command row
+pub(crate) fn execute_command(input: &str, editor_config: &mut EditorConfig) {
+    update_modified(editor_config);
+    let ex_command = match ex::parse(input) {
+        Ok(ex_command) => ex_command,
+        Err(e) => {
+            editor_config.editor_state.message = Some(e);
+            return;
         }
-        _ => {
-            command.push(input[0] as char);
-            cursor::write_char(&input[0]);
+    };
+    let content_buffer = &editor_config.gap_buffer;
+    let current = content_buffer.cursor_position().0;
+    let default = match ex_command.command {
+        Command::Write(_) | Command::WriteQuit(_) | Command::Exit(_) | Command::Global { .. } => {
+            (1, content_buffer.get_filled_items())
         }
+        _ => (current, current),
     };
+    // a bare address past the end goes to the last line, any other command is given E16 for it
+    let resolve = match ex_command.command {
+        Command::Goto => ex::Range::resolve_clamped,
+        _ => ex::Range::resolve,
+    };
+    let range = resolve(
+        &ex_command.range,
+        content_buffer,
+        &editor_config.editor_state.marks,
+        &editor_config.editor_state.options,
+        default,
+    );
+    let (start, end) = match range {
+        Ok(range) => range,
+        Err(e) => {
+            editor_config.editor_state.message = Some(e);
+            return;
+        }
+    };
+
+    match ex_command.command {
+        Command::Goto => {
+            if ex_command.range.start.is_some() {
+                editor_config.gap_buffer.set_cursor_position((end, 1));
+            }
+        }
+        Command::Write(file_name) => {
+            write_command(editor_config, file_name, ex_command.bang, (start, end));
+        }
+        Command::WriteQuit(file_name) => {
+            if write_command(editor_config, file_name, ex_command.bang, (start, end)) {
+                quit(editor_config, ex_command.bang);
+            }
+        }
+        Command::Exit(file_name) => {
+            // only writes when there is something to write, unlike :wq
+            let written = !editor_config.modified && file_name.is_none()
+                || write_command(editor_config, file_name, ex_command.bang, (start, end));
+            if written {
+                quit(editor_config, ex_command.bang);
+            }
+        }
+        Command::Quit => {
+            // the buffer stays on screen when another window shows it
+            let current = editor_config.buffers.current;
+            let shown = tab::shows_elsewhere(editor_config, current);
+            if editor_config.modified && !ex_command.bang && !shown {
+                editor_config.editor_state.message = Some(String::from(
+                    "E37: No write since last change (add ! to override)",
+                ));
+                return;
+            }
+            quit(editor_config, ex_command.bang)
+        }
+        Command::Edit(file_name) => buffer::edit(editor_config, file_name, ex_command.bang),
+        Command::Buffer(name) => buffer::buffer(editor_config, name.as_deref()),
+        Command::Bnext => buffer::cycle(editor_config, false),
+        Command::Bprevious => buffer::cycle(editor_config, true),
+        Command::Bdelete(name) => buffer::delete(editor_config, name.as_deref(), ex_command.bang),
+        Command::Buffers => buffer::list(editor_config),
+        Command::Split {
+            vertical,
+            file_name,
+        } => window::split(editor_config, vertical, file_name, false),
+        Command::New { vertical } => window::split(editor_config, vertical, None, true),
+        Command::Close => window::close(editor_config),
+        Command::Tabnew(file_name) => tab::new(editor_config, file_name),
+        Command::Tabclose => tab::close(editor_config),
+        Command::Tabonly => tab::only(editor_config),
+        Command::Only => window::only(editor_config),
+        Command::Qall => {
+            if editor_config.modified && !ex_command.bang {
+                editor_config.editor_state.message = Some(String::from(
+                    "E37: No write since last change (add ! to override)",
+                ));
+                return;
+            }
+            quit_all(editor_config, ex_command.bang)
+        }
+        Command::Delete => delete_lines(editor_config, start, end),
+        Command::Undo => undo_handler(editor_config, UndoStep::Undo),
+        Command::Redo => undo_handler(editor_config, UndoStep::Redo),
+        Command::Undolist => undolist(editor_config),
+        Command::Registers(names) => list_registers(editor_config, names),
+        Command::Nohlsearch => editor_config.editor_state.search.highlight = false,
+        Command::Substitute(substitute) => {
+            substitute::substitute(editor_config, substitute, (start, end))
+        }
+        Command::Global {
+            pattern,
+            command,
+            invert,
+        } => global::global(editor_config, &pattern, &command, invert, (start, end)),
+        Command::Join => {
+            // with one line or none :j joins it with the one below, like J
+            let end = match ex_command.range.pair {
+                true => end,
+                false => end + 1,
+            };
+            join_command(editor_config, start, end, ex_command.bang)
+        }
+        Command::Print => {
+            let lines = editor_config.gap_buffer.get_lines();
+            editor_config
+                .editor_state
+                .output
+                .extend_from_slice(&lines[start - 1..end]);
+            editor_config.gap_buffer.set_cursor_position((end, 1));
+        }
+        Command::Normal(commands) => {
+            let lines = ex_command.range.start.map(|_| (start, end));
+            macros::normal(editor_config, &commands, lines)
+        }
+        Command::Set(args) => {
+            let editor_state = &mut editor_config.editor_state;
+            // turning hlsearch on shows the last search again even after :nohlsearch
+            let highlight = args.as_deref().is_some_and(|args| {
+                args.split_whitespace()
+                    .any(|arg| matches!(arg, "hlsearch" | "hls"))
+            });
+            editor_state.search.highlight |= highlight;
+            let options = &mut editor_state.options;
+            let shown = match args {
+                Some(args) => options.set(&args),
+                None => Ok(Some(options.show_all())),
+            };
+            match shown {
+                Ok(shown) => editor_config.editor_state.message = shown,
+                Err(e) => editor_config.editor_state.message = Some(e),
+            }
+        }
+    }
 }
 
-/// this function handles the parsing of commands recieved from command mode upon recieving input
-/// of the Enter key.
-fn command_parser(
-    command: &Option<char>,
-    content_buffer: &mut GapBuffer<GapBuffer<char>>,
-    file_data: &FileData,
-    editor_state: &mut EditorState,
-) {
-    match command {
-        Some(c) => match c {
-            'q' => {
-                editor_state.update_editor_mode(EditorMode::ShutDown);
+/// :w, :w file and :5,12w file. writes the lines from `start` to `end` and returns whether it
+/// worked. writing over some other file that already exists needs a !
+fn write_command(
+    editor_config: &mut EditorConfig,
+    file_name: Option<String>,
+    bang: bool,
+    (start, end): (usize, usize),
+) -> bool {
+    let file_data = &mut editor_config.file_data;
+    let editor_state = &mut editor_config.editor_state;
+    let whole_buffer = (start, end) == (1, editor_config.gap_buffer.get_filled_items());
+
+    let file_name = match file_name.or(file_data.file_name.clone()) {
+        Some(file_name) => file_name,
+        None => {
+            editor_state.message = Some(String::from("E32: No file name"));
+            return false;
+        }
+    };
+    if let Err(e) = check_write(file_data, &file_name, bang, whole_buffer) {
+        editor_state.message = Some(e);
+        return false;
+    }
+    let own_file = file_data.is_named(&file_name);
+    // a scratch buffer takes the name of the first file it is written to as a whole, once that
+    // write worked
+    let adopt_name = file_data.file_name.is_none() && whole_buffer;
+
+    let lines = editor_config.gap_buffer.get_lines();
+    let mut data = lines[start - 1..end].join("\n");
+    data.push('\n');
+    // the buffer is left as it is when the write fails, so nothing is lost and :w can be tried
+    // again once the problem is sorted out
+    if let Err(e) = save_file_contents(&file_name, &data) {
+        editor_state.message = Some(match e.kind() {
+            io::ErrorKind::PermissionDenied | io::ErrorKind::NotFound => {
+                format!("\"{file_name}\" E212: Can't open file for writing: {e}")
             }
-            'w' => save_file_contents(file_data, content_buffer),
-            _ => (),
+            _ => format!("\"{file_name}\" E514: Write error: {e}"),
+        });
+        return false;
+    }
+    editor_state.message = Some(format!(
+        "\"{file_name}\" {}L, {}B written",
+        end - start + 1,
+        data.len()
+    ));
+
+    if adopt_name {
+        file_data.file_name = Some(file_name.clone());
+    }
+    if own_file || adopt_name {
+        // the file exists from here on, so it is no longer new
+        if file_data.file_handle.is_none() {
+            file_data.file_handle = fs::File::open(&file_name).ok();
+        }
+        if whole_buffer {
+            editor_config.saved_seq = editor_config.undo_tree.seq_cur();
+            editor_config.modified = false;
+            write_undo_file(editor_config);
+        }
+    }
+    true
+}
+
+/// whether the buffer of `file_data` can be written to `file_name`. writing over some other file
+/// that is already there needs !, and so does writing only part of the buffer to its own file
+fn check_write(
+    file_data: &FileData,
+    file_name: &str,
+    bang: bool,
+    whole_buffer: bool,
+) -> Result<(), String> {
+    let own_file = file_data.is_named(file_name);
+    if !own_file && !bang && Path::new(file_name).exists() {
+        return Err(String::from("E13: File exists (add ! to override)"));
+    }
+    if own_file && !whole_buffer && !bang {
+        return Err(String::from("E140: Use ! to write partial buffer"));
+    }
+    Ok(())
+}
+
+/// leaves jbvim once the buffer being edited is taken care of. the other buffers can't have
+/// changes that weren't written either, unless ! throws them away. when the screen is split
+/// only the window being edited is closed, and the last window of a tab page closes the tab page
+fn quit(editor_config: &mut EditorConfig, bang: bool) {
+    // with more than one window only the one being edited goes
+    if editor_config.windows.list.len() > 1 {
+        return window::close(editor_config);
+    }
+    if editor_config.tabs.shown() {
+        return tab::close(editor_config);
+    }
+    quit_all(editor_config, bang)
+}
+
+/// shuts the editor down, as long as no other buffer has changes that weren't written
+fn quit_all(editor_config: &mut EditorConfig, bang: bool) {
+    update_modified(editor_config);
+    if let (Some(buffer), false) = (editor_config.buffers.first_modified(), bang) {
+        let name = buffer.file_data.file_name.as_deref().unwrap_or("[No Name]");
+        editor_config.editor_state.message = Some(format!(
+            "E162: No write since last change for buffer \"{name}\""
+        ));
+        return;
+    }
+    editor_config
+        .editor_state
+        .update_editor_mode(EditorMode::ShutDown)
+}
+
+/// :d, removes whole lines. the buffer always keeps at least one line
+fn delete_lines(editor_config: &mut EditorConfig, start: usize, end: usize) {
+    let content_buffer = &mut editor_config.gap_buffer;
+    let count = end - start + 1;
+    let lines = TextRange::between(RangeKind::Line, (start - 1, 0), (end - 1, 0));
+    let text = ops::get_text(content_buffer, &lines);
+    editor_config.registers.delete(
+        None,
+        Register {
+            text,
+            kind: RangeKind::Line,
         },
-        None => {
-            write!(io::stdout(), "no command given")
-                .unwrap_or_else(|e| panic!("io error occurred during write: {e}"));
+    );
+
+    editor_config.undo_tree.begin(content_buffer);
+    content_buffer.replace_lines(start - 1, count, &[]);
+    if content_buffer.get_filled_items() == 0 {
+        content_buffer.replace_lines(0, 0, &[String::new()]);
+    }
+    editor_config.undo_tree.commit(content_buffer);
+
+    content_buffer.set_cursor_position((start, 1));
+    if count > 2 {
+        editor_config.editor_state.message = Some(format!("{count} fewer lines"));
+    }
+}
+
+/// :join, puts the lines from `start` to `end` on one line the way J does, or with `keep_spaces`
+/// exactly as they are
+fn join_command(editor_config: &mut EditorConfig, start: usize, end: usize, keep_spaces: bool) {
+    let content_buffer = &mut editor_config.gap_buffer;
+    if start >= end || end > content_buffer.get_filled_items() {
+        return;
+    }
+    editor_config.undo_tree.begin(content_buffer);
+    let cursor = match keep_spaces {
+        false => ops::join_lines(content_buffer, start - 1, end - 1),
+        true => {
+            let lines = &content_buffer.get_lines()[start - 1..end];
+            let col = lines[..lines.len() - 1].concat().chars().count();
+            content_buffer.replace_lines(start - 1, lines.len(), &[lines.concat()]);
+            (start - 1, col)
         }
+    };
+    editor_config.undo_tree.commit(content_buffer);
+    place_cursor(content_buffer, cursor);
+}
+
+/// :undolist, lists the leaves of the undo tree, i.e. the last change of every branch
+fn undolist(editor_config: &mut EditorConfig) {
+    let leaves = editor_config.undo_tree.leaves();
+    if leaves.is_empty() {
+        editor_config.editor_state.message = Some(String::from("Nothing to undo"));
+        return;
+    }
+    let mut output = vec![String::from("number changes  when")];
+    for (number, changes, age) in leaves {
+        output.push(format!("{number:>6} {changes:>7}  {}", format_age(age)));
+    }
+    editor_config.editor_state.output = output;
+}
+
+/// :registers and :display, lists what is in every register, or only in the ones named
+fn list_registers(editor_config: &mut EditorConfig, names: Option<String>) {
+    let mut registers = editor_config.registers.list();
+    if let Some(register) = read_register(editor_config, Some('%')) {
+        registers.push(('%', register));
+    }
+    if let Some(names) = names {
+        registers.retain(|(name, _)| names.contains(*name));
+    }
+    let width = editor_config.viewport.width;
+    let mut output = vec![String::from("Type Name Content")];
+    for (name, register) in registers {
+        let kind = match register.kind {
+            RangeKind::Char => 'c',
+            RangeKind::Line => 'l',
+            RangeKind::Block => 'b',
+        };
+        let mut text = register.text.join("\n");
+        if register.kind == RangeKind::Line {
+            text.push('\n');
+        }
+        // newlines and other control chars are shown the way vim shows them, as ^J and so on
+        let content: String = text
+            .chars()
+            .flat_map(|c| match c {
+                '\0'..='\x1f' | BACKSPACE => vec!['^', char::from(c as u8 ^ 0x40)],
+                c => vec![c],
+            })
+            .collect();
+        let line: String = format!("  {kind}  \"{name}   {content}")
+            .chars()
+            .take(width)
+            .collect();
+        output.push(line);
+    }
+    editor_config.editor_state.output = output;
+}
+
+fn format_age(seconds: u64) -> String {
+    match seconds {
+        0..=99 => format!("{seconds} seconds ago"),
+        100..=5999 => format!("{} minutes ago", seconds / 60),
+        _ => format!("{} hours ago", seconds / 3600),
     }
 }
 
@@ -410,17 +1337,241 @@ fn graceful_exit(original_settings: &Termios) {
     terminol::disable_alternate_buffer();
     terminol::disable_raw_mode(original_settings);
 }
-pub fn save_file_contents(file_data: &FileData, content_buffer: &mut GapBuffer<GapBuffer<char>>) {
-    let data = content_buffer.get_content();
+/// writes the undo tree next to a save, keyed by the hash of what was just written so the next
+/// session only picks it up if the file is still the same
+fn write_undo_file(editor_config: &mut EditorConfig) {
+    let path = match editor_config
+        .file_data
+        .file_name
+        .as_deref()
+        .and_then(undo::undo_file_path)
+    {
+        Some(path) => path,
+        None => return,
+    };
+    let hash = undo::content_hash(&editor_config.gap_buffer.get_content());
+    if let Err(e) = editor_config.undo_tree.write_file(&path, hash) {
+        editor_config.editor_state.message = Some(format!(
+            "E828: Cannot open undo file for writing: {} ({e})",
+            path.display()
+        ));
+    }
+}
+
+/// writes `data` to `file_name` without ever leaving a half written file behind. the data goes
+/// to a temporary file next to the original, which is synced to disk and then renamed over it,
+/// so a crash or a full disk leaves either the old file or the new one. the temporary file gets
+/// the permissions and owner of the original first.
+///
+/// when that isn't possible, because the directory can't be written to or the owner can't be
+/// kept, the file is overwritten in place instead like vim does
+pub fn save_file_contents(file_name: &str, data: &str) -> io::Result<()> {
+    // write through symlinks rather than replacing the link with a plain file
+    let target = fs::canonicalize(file_name).unwrap_or_else(|_| PathBuf::from(file_name));
+    let metadata = fs::metadata(&target).ok();
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let base_name = target.file_name().unwrap_or_default().to_string_lossy();
+    let temp = dir.join(format!(".{base_name}.{}.jbvim", process::id()));
+
+    let temp_file = match create_temp_file(&temp, metadata.as_ref()) {
+        Ok(temp_file) => temp_file,
+        Err(_) => {
+            let _ = fs::remove_file(&temp);
+            return write_in_place(&target, data);
+        }
+    };
+    // a failure from here on leaves the original untouched
+    if let Err(e) = write_and_sync(temp_file, data).and_then(|_| fs::rename(&temp, &target)) {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+    // make the rename itself durable
+    if let Ok(dir) = fs::File::open(&dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
 
-    fs::write(format!("./{}", file_data.file_name), data).expect("should write to /file_name");
+fn create_temp_file(temp: &Path, metadata: Option<&fs::Metadata>) -> io::Result<fs::File> {
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(temp)?;
+    if let Some(metadata) = metadata {
+        std::os::unix::fs::fchown(&file, Some(metadata.uid()), Some(metadata.gid()))?;
+        file.set_permissions(metadata.permissions())?;
+    }
+    Ok(file)
 }
 
-fn move_right(line_buf: &mut GapBuffer<char>) {
-    line_buf.move_gap_right();
-    cursor::move_right(1);
+fn write_and_sync(mut file: fs::File, data: &str) -> io::Result<()> {
+    file.write_all(data.as_bytes())?;
+    file.sync_all()
 }
-fn move_left(line_buf: &mut GapBuffer<char>) {
-    line_buf.move_gap_left();
-    cursor::move_left(1);
+
+fn write_in_place(target: &Path, data: &str) -> io::Result<()> {
+    write_and_sync(fs::File::create(target)?, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// an editor on a scratch buffer holding `text`, which never touches the terminal as long as
+    /// nothing is drawn
+    fn editor_on(text: &str) -> EditorConfig {
+        let content_buffer = GapBuffer::build_nested(text);
+        let viewport = Viewport {
+            top: 0,
+            left: 0,
+            height: 20,
+            width: 80,
+        };
+        let undo_tree = UndoTree::new();
+        let buffers = buffer::BufferList::new();
+        EditorConfig {
+            editor_state: EditorState::new(EditorMode::Normal, EditorMode::Normal),
+            // SAFETY: termios is a plain C struct of integers, for which all zeroes is valid. it
+            // is only ever handed back to the terminal by run
+            original_settings: unsafe { std::mem::zeroed() },
+            file_data: FileData::scratch(),
+            changedtick: content_buffer.changedtick(),
+            gap_buffer: content_buffer,
+            viewport,
+            saved_seq: undo_tree.seq_cur(),
+            undo_tree,
+            modified: false,
+            registers: Registers::new(),
+            repeat: repeat::Recorder::default(),
+            macros: macros::Macros::default(),
+            windows: window::Windows::new(buffers.current, viewport),
+            tabs: tab::TabPages::new(),
+            buffers,
+            screen: terminol::Screen::new(22, 80),
+        }
+    }
+
+    /// types `keys` as if they came one at a time from the terminal
+    fn type_keys(editor_config: &mut EditorConfig, keys: &str) {
+        for key in terminol::input::parse_keys(keys.as_bytes()) {
+            macros::record(&key, editor_config);
+            feed_key(&key, editor_config);
+            update_modified(editor_config);
+        }
+    }
+
+    #[test]
+    fn macro_that_edits_and_quits() {
+        let mut editor_config = editor_on("abc\n");
+        editor_config
+            .registers
+            .record('q', Register::chars("x:q\r"));
+        type_keys(&mut editor_config, "@q");
+        assert_eq!(editor_config.gap_buffer.get_lines(), ["bc"]);
+        assert_eq!(editor_config.editor_state.editor_mode, EditorMode::Normal);
+        assert_eq!(
+            editor_config.editor_state.message.as_deref(),
+            Some("E37: No write since last change (add ! to override)")
+        );
+
+        // ZZ has nowhere to write the scratch buffer to, so it doesn't quit either
+        type_keys(&mut editor_config, ":normal ddZZ\r");
+        assert_eq!(editor_config.editor_state.editor_mode, EditorMode::Normal);
+        assert_eq!(
+            editor_config.editor_state.message.as_deref(),
+            Some("E32: No file name")
+        );
+    }
+
+    #[test]
+    fn g_ampersand_uses_the_last_search() {
+        let mut editor_config = editor_on("a b\na b\n");
+        type_keys(&mut editor_config, ":s/a/x/g\r");
+        type_keys(&mut editor_config, "/b\r");
+        type_keys(&mut editor_config, "g&");
+        assert_eq!(editor_config.gap_buffer.get_lines(), ["x x", "a x"]);
+    }
+
+    #[test]
+    fn insert_with_a_count() {
+        let mut editor_config = editor_on("x\n");
+        type_keys(&mut editor_config, "ia\x1b");
+        type_keys(&mut editor_config, "3.");
+        assert_eq!(editor_config.gap_buffer.get_lines(), ["aaaax"]);
+
+        type_keys(&mut editor_config, "2ab\rc\x1b");
+        assert_eq!(editor_config.gap_buffer.get_lines(), ["aaaaxb", "cb", "c"]);
+        assert_eq!(editor_config.registers.last_insert.as_deref(), Some("b\nc"));
+    }
+
+    #[test]
+    fn save_keeps_permissions_and_leaves_no_temp_file() {
+        let dir = env::temp_dir().join(format!("jbvim-save-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file_name = dir.join("saved.txt");
+        fs::write(&file_name, "old\n").unwrap();
+        fs::set_permissions(&file_name, fs::Permissions::from_mode(0o640)).unwrap();
+
+        save_file_contents(file_name.to_str().unwrap(), "new\n").unwrap();
+
+        assert_eq!(fs::read_to_string(&file_name).unwrap(), "new\n");
+        let mode = fs::metadata(&file_name).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn backspace_only_takes_back_what_was_typed() {
+        let mut inserted = String::from("ab");
+        backspace_inserted(&mut inserted);
+        assert_eq!(inserted, "a");
+        backspace_inserted(&mut inserted);
+        backspace_inserted(&mut inserted);
+        inserted.push('x');
+        backspace_inserted(&mut inserted);
+        backspace_inserted(&mut inserted);
+        assert_eq!(inserted, "\x7f\x7f");
+    }
+
+    #[test]
+    fn scratch_buffer_does_not_write_over_existing_file() {
+        let dir = env::temp_dir().join(format!("jbvim-write-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file_name = dir.join("existing.txt");
+        fs::write(&file_name, "keep me\n").unwrap();
+        let file_name = file_name.to_str().unwrap();
+
+        let scratch = FileData::scratch();
+        assert_eq!(
+            check_write(&scratch, file_name, false, true),
+            Err(String::from("E13: File exists (add ! to override)"))
+        );
+        assert_eq!(check_write(&scratch, file_name, true, true), Ok(()));
+
+        // the buffer's own file is written over without !, but only as a whole
+        let own = FileData::open(String::from(file_name)).unwrap();
+        assert_eq!(check_write(&own, file_name, false, true), Ok(()));
+        assert_eq!(
+            check_write(&own, file_name, false, false),
+            Err(String::from("E140: Use ! to write partial buffer"))
+        );
+
+        // and it is the same file however the path to it is written
+        let spelled = dir.join(".").join("existing.txt");
+        let spelled = spelled.to_str().unwrap();
+        assert_eq!(check_write(&own, spelled, false, true), Ok(()));
+        assert_eq!(
+            check_write(&own, spelled, false, false),
+            Err(String::from("E140: Use ! to write partial buffer"))
+        );
+        let new = FileData::open(dir.join("new.txt").to_str().unwrap().to_owned()).unwrap();
+        let spelled = dir.join("sub").join("..").join("new.txt");
+        assert!(new.is_named(spelled.to_str().unwrap()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,185 @@
+use crate::config::{EditorMode, PendingCommand};
+use crate::ops::RangeKind;
+use crate::register::Register;
+use crate::{execute_command, feed_key, place_cursor, read_register, EditorConfig};
+use terminol::{Key, KeyCode};
+
+/// how deep macros and :normal may run each other before it is taken for a loop
+const MAX_DEPTH: usize = 100;
+
+/// the keys typed since q{reg}, and what @@ plays again
+#[derive(Debug, Default)]
+pub struct Macros {
+    keys: Vec<Key>,
+    last: Option<char>,
+    depth: usize,
+}
+
+/// keeps `key` while a macro is being recorded. only typed keys come through here, the ones a
+/// macro, :normal or . types aren't recorded a second time
+pub fn record(key: &Key, editor_config: &mut EditorConfig) {
+    if editor_config.editor_state.recording.is_some() {
+        editor_config.macros.keys.push(*key);
+    }
+}
+
+/// q{reg}, starts recording into a letter or digit register or the unnamed one. an uppercase
+/// letter adds to what the register already holds
+pub fn start(editor_config: &mut EditorConfig, name: char) {
+    if name.is_ascii_alphanumeric() || name == '"' {
+        editor_config.macros.keys.clear();
+        editor_config.editor_state.recording = Some(name);
+    }
+}
+
+/// q while recording, the keys go to the register as text. the q that stopped it was the last
+/// key recorded and isn't kept
+pub fn stop(editor_config: &mut EditorConfig) {
+    let Some(name) = editor_config.editor_state.recording.take() else {
+        return;
+    };
+    let mut keys = std::mem::take(&mut editor_config.macros.keys);
+    keys.pop();
+    let text = terminol::encode_keys(&keys);
+    editor_config.registers.record(name, Register::chars(&text));
+}
+
+/// an error stops a macro, so one that repeats itself until a search fails comes to an end
+fn failed(editor_config: &EditorConfig) -> bool {
+    let editor_state = &editor_config.editor_state;
+    let error = editor_state.message.as_deref().is_some_and(|message| {
+        let mut chars = message.chars();
+        chars.next() == Some('E') && chars.next().is_some_and(|c| c.is_ascii_digit())
+    });
+    error || editor_state.editor_mode == EditorMode::ShutDown
+}
+
+/// types `keys` through the handlers of whatever mode the editor is in, until one of them fails.
+/// false when they have run into an error
+fn type_keys(editor_config: &mut EditorConfig, keys: &[Key]) -> bool {
+    for key in keys {
+        feed_key(key, editor_config);
+        if failed(editor_config) {
+            return false;
+        }
+    }
+    true
+}
+
+/// counts one more macro or :normal running inside the others, None when there are too many
+fn enter(editor_config: &mut EditorConfig) -> Option<()> {
+    let macros = &mut editor_config.macros;
+    if macros.depth == MAX_DEPTH {
+        editor_config.editor_state.message = Some(String::from("E169: Command too recursive"));
+        return None;
+    }
+    macros.depth += 1;
+    Some(())
+}
+
+/// @{reg}, types the keys in the register count times over. @@ plays the register played last
+/// again and @: runs the last command line
+pub fn play(editor_config: &mut EditorConfig, name: char) {
+    let editor_state = &mut editor_config.editor_state;
+    let count = editor_state.pending.total_count().unwrap_or(1);
+    // the keys start a command of their own, the count was for the @
+    editor_state.pending = PendingCommand::default();
+    editor_state.message = None;
+    let name = match (name, editor_config.macros.last) {
+        ('@', Some(last)) => last,
+        ('@', None) => {
+            editor_state.message = Some(String::from("E748: No previously used register"));
+            return;
+        }
+        (name, _) => name,
+    };
+    if name == ':' {
+        let Some(command) = editor_config.registers.last_command.clone() else {
+            editor_state.message = Some(String::from("E30: No previous command line"));
+            return;
+        };
+        editor_config.macros.last = Some(':');
+        for _ in 0..count {
+            execute_command(&command, editor_config);
+            if failed(editor_config) {
+                break;
+            }
+        }
+        return;
+    }
+    let Some(register) = read_register(editor_config, Some(name)) else {
+        return;
+    };
+    editor_config.macros.last = Some(name);
+    // a register of lines ends in a newline, as it would have been typed
+    let mut text = register.text.join("\n");
+    if register.kind == RangeKind::Line {
+        text.push('\n');
+    }
+    let keys = terminol::decode_keys(&text);
+    if enter(editor_config).is_none() {
+        return;
+    }
+    for _ in 0..count {
+        if !type_keys(editor_config, &keys) {
+            break;
+        }
+    }
+    editor_config.macros.depth -= 1;
+}
+
+/// :[range]normal {commands}, types `commands` as normal mode keys once on each line of the
+/// range, with the cursor at its start, or once where the cursor is when no range was given.
+/// whatever they leave unfinished is ended as <Esc> would, and all of it is undone with a
+/// single u
+pub fn normal(editor_config: &mut EditorConfig, commands: &str, lines: Option<(usize, usize)>) {
+    if commands.is_empty() || enter(editor_config).is_none() {
+        return;
+    }
+    let keys: Vec<Key> = commands
+        .chars()
+        .map(|c| Key::plain(KeyCode::Char(c)))
+        .collect();
+    // the command line that ran :normal is done with
+    editor_config
+        .editor_state
+        .update_editor_mode(EditorMode::Normal);
+    editor_config.undo_tree.begin(&editor_config.gap_buffer);
+    editor_config.undo_tree.hold();
+    let (start, end) = lines.unwrap_or_default();
+    let mut line = start;
+    loop {
+        if lines.is_some() {
+            // the lines are taken by number, those deleted on the way are simply not there
+            if line > end || line > editor_config.gap_buffer.get_filled_items() {
+                break;
+            }
+            place_cursor(&mut editor_config.gap_buffer, (line - 1, 0));
+        }
+        // an error on one line doesn't stop the keys on the next
+        editor_config.editor_state.message = None;
+        type_keys(editor_config, &keys);
+        finish(editor_config);
+        if editor_config.editor_state.editor_mode == EditorMode::ShutDown || lines.is_none() {
+            break;
+        }
+        line += 1;
+    }
+    editor_config.undo_tree.release();
+    editor_config.undo_tree.commit(&editor_config.gap_buffer);
+    editor_config.macros.depth -= 1;
+}
+
+/// types <Esc> until the editor is back in normal mode with nothing pending
+fn finish(editor_config: &mut EditorConfig) {
+    for _ in 0..3 {
+        let editor_state = &editor_config.editor_state;
+        let done = editor_state.editor_mode == EditorMode::Normal
+            && editor_state.pending_key.is_none()
+            && editor_state.pending == PendingCommand::default();
+        if done || editor_state.editor_mode == EditorMode::ShutDown {
+            return;
+        }
+        feed_key(&Key::plain(KeyCode::Esc), editor_config);
+    }
+}
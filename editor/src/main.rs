@@ -1,4 +1,3 @@
-use editor;
 use std::env;
 use std::error::Error;
 
@@ -0,0 +1,683 @@
+use crate::ops::{first_non_blank, line_chars};
+use crate::regex::Regex;
+use crate::search;
+use gap_buffer::GapBuffer;
+
+/// the four ways of looking for a char in the line, f, F, t and T
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Find {
+    Forward,
+    Backward,
+    TillForward,
+    TillBackward,
+}
+
+impl Find {
+    pub fn from_key(key: char) -> Option<Find> {
+        match key {
+            'f' => Some(Find::Forward),
+            'F' => Some(Find::Backward),
+            't' => Some(Find::TillForward),
+            'T' => Some(Find::TillBackward),
+            _ => None,
+        }
+    }
+    fn reversed(self) -> Find {
+        match self {
+            Find::Forward => Find::Backward,
+            Find::Backward => Find::Forward,
+            Find::TillForward => Find::TillBackward,
+            Find::TillBackward => Find::TillForward,
+        }
+    }
+}
+
+/// everything that moves the cursor and can be given to an operator. `big` is for the WORD
+/// versions, W B E and gE, where a word is anything between blanks
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    LineStart,
+    LineEnd,
+    WordForward {
+        big: bool,
+    },
+    WordBackward {
+        big: bool,
+    },
+    WordEnd {
+        big: bool,
+    },
+    WordEndBackward {
+        big: bool,
+    },
+    FindChar(Find, char),
+    /// ; and ,, the last f, F, t or T again, the other way round for ,
+    RepeatFind {
+        reverse: bool,
+    },
+    /// %, the bracket matching the one under or after the cursor
+    MatchPair,
+    SentenceForward,
+    SentenceBackward,
+    ParagraphForward,
+    ParagraphBackward,
+    ScreenTop,
+    ScreenMiddle,
+    ScreenBottom,
+    /// gg
+    FirstLine,
+    /// G
+    LastLine,
+    /// n and N, the next match of the last search, the other way round for N
+    SearchNext {
+        reverse: bool,
+    },
+}
+
+impl Motion {
+    /// the motion for a key that needs nothing after it
+    pub fn from_key(key: char) -> Option<Motion> {
+        let motion = match key {
+            'h' => Motion::Left,
+            'l' | ' ' => Motion::Right,
+            'k' => Motion::Up,
+            'j' => Motion::Down,
+            '0' => Motion::LineStart,
+            '$' => Motion::LineEnd,
+            'w' => Motion::WordForward { big: false },
+            'W' => Motion::WordForward { big: true },
+            'b' => Motion::WordBackward { big: false },
+            'B' => Motion::WordBackward { big: true },
+            'e' => Motion::WordEnd { big: false },
+            'E' => Motion::WordEnd { big: true },
+            ';' => Motion::RepeatFind { reverse: false },
+            ',' => Motion::RepeatFind { reverse: true },
+            '%' => Motion::MatchPair,
+            ')' => Motion::SentenceForward,
+            '(' => Motion::SentenceBackward,
+            '}' => Motion::ParagraphForward,
+            '{' => Motion::ParagraphBackward,
+            'H' => Motion::ScreenTop,
+            'M' => Motion::ScreenMiddle,
+            'L' => Motion::ScreenBottom,
+            'G' => Motion::LastLine,
+            'n' => Motion::SearchNext { reverse: false },
+            'N' => Motion::SearchNext { reverse: true },
+            _ => return None,
+        };
+        Some(motion)
+    }
+    /// the motion for a key typed after g
+    pub fn from_g_key(key: char) -> Option<Motion> {
+        match key {
+            'g' => Some(Motion::FirstLine),
+            'e' => Some(Motion::WordEndBackward { big: false }),
+            'E' => Some(Motion::WordEndBackward { big: true }),
+            _ => None,
+        }
+    }
+}
+
+/// how much of the text between the cursor and where a motion lands an operator takes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MotionKind {
+    /// up to but not including the char landed on, like w
+    Exclusive,
+    /// up to and including it, like e
+    Inclusive,
+    /// every line from the cursor's to the one landed on, like j
+    Linewise,
+}
+
+/// what a motion needs to know besides the buffer and the cursor
+pub struct Context {
+    /// the count typed, None when there was none. G, gg and % behave differently with one
+    pub count: Option<usize>,
+    /// the first and last line on screen, for H, M and L
+    pub screen: (usize, usize),
+    /// the last f, F, t or T, for ; and ,
+    pub last_find: Option<(Find, char)>,
+    /// the last search and whether it went forward, for n and N
+    pub last_search: Option<(Regex, bool)>,
+    /// whether n and N go round the end of the buffer
+    pub wrapscan: bool,
+}
+
+/// where `motion` takes the 0-indexed `cursor`, and how an operator should treat the text
+/// moved over. None when the motion can't be made, such as f for a char that isn't there
+pub fn target(
+    content_buffer: &GapBuffer<GapBuffer<char>>,
+    cursor: (usize, usize),
+    motion: Motion,
+    context: &Context,
+) -> Option<((usize, usize), MotionKind)> {
+    let count = context.count.unwrap_or(1).max(1);
+    let (line, col) = cursor;
+    let last_line = content_buffer.get_filled_items() - 1;
+    let line_len = |line| line_chars(content_buffer, line).len();
+    let to_line = |line: usize| {
+        let line = line.min(last_line);
+        Some((
+            (line, first_non_blank(content_buffer, line)),
+            MotionKind::Linewise,
+        ))
+    };
+
+    match motion {
+        Motion::Left => match col {
+            0 => None,
+            _ => Some(((line, col.saturating_sub(count)), MotionKind::Exclusive)),
+        },
+        Motion::Right => {
+            let len = line_len(line);
+            match len {
+                0 => None,
+                _ => Some(((line, (col + count).min(len)), MotionKind::Exclusive)),
+            }
+        }
+        Motion::Up => match line {
+            0 => None,
+            _ => Some(((line.saturating_sub(count), col), MotionKind::Linewise)),
+        },
+        Motion::Down => match line == last_line {
+            true => None,
+            false => Some((((line + count).min(last_line), col), MotionKind::Linewise)),
+        },
+        Motion::LineStart => Some(((line, 0), MotionKind::Exclusive)),
+        Motion::LineEnd => {
+            let line = (line + count - 1).min(last_line);
+            match line_len(line) {
+                // there is nothing to take on an empty line, not even its newline
+                0 => Some(((line, 0), MotionKind::Exclusive)),
+                len => Some(((line, len - 1), MotionKind::Inclusive)),
+            }
+        }
+        Motion::WordForward { big } => {
+            let mut walker = Walker::new(content_buffer, cursor);
+            for _ in 0..count {
+                word_forward(&mut walker, big);
+            }
+            Some((walker.pos(), MotionKind::Exclusive))
+        }
+        Motion::WordBackward { big } => {
+            let mut walker = Walker::new(content_buffer, cursor);
+            for _ in 0..count {
+                word_backward(&mut walker, big);
+            }
+            Some((walker.pos(), MotionKind::Exclusive))
+        }
+        Motion::WordEnd { big } => {
+            let mut walker = Walker::new(content_buffer, cursor);
+            for _ in 0..count {
+                word_end(&mut walker, big);
+            }
+            Some((walker.pos(), MotionKind::Inclusive))
+        }
+        Motion::WordEndBackward { big } => {
+            let mut walker = Walker::new(content_buffer, cursor);
+            for _ in 0..count {
+                word_end_backward(&mut walker, big);
+            }
+            Some((walker.pos(), MotionKind::Inclusive))
+        }
+        Motion::FindChar(find, c) => find_char(content_buffer, cursor, find, c, count, false),
+        Motion::RepeatFind { reverse } => {
+            let (find, c) = context.last_find?;
+            let find = if reverse { find.reversed() } else { find };
+            find_char(content_buffer, cursor, find, c, count, true)
+        }
+        Motion::MatchPair => match context.count {
+            // with a count, % goes that far through the file instead
+            Some(percent) if percent <= 100 => {
+                to_line(((percent * (last_line + 1)).div_ceil(100)).saturating_sub(1))
+            }
+            Some(_) => None,
+            None => Some((match_pair(content_buffer, cursor)?, MotionKind::Inclusive)),
+        },
+        Motion::SearchNext { reverse } => {
+            let (regex, forward) = context.last_search.as_ref()?;
+            let mut at = cursor;
+            for _ in 0..count {
+                let forward = *forward != reverse;
+                at = search::find(content_buffer, regex, at, forward, context.wrapscan)?
+                    .0
+                    .start;
+            }
+            Some((at, MotionKind::Exclusive))
+        }
+        Motion::SentenceForward => {
+            let mut walker = Walker::new(content_buffer, cursor);
+            for _ in 0..count {
+                while walker.next() && !is_sentence_start(content_buffer, &walker) {}
+            }
+            Some((walker.pos(), MotionKind::Exclusive))
+        }
+        Motion::SentenceBackward => {
+            let mut walker = Walker::new(content_buffer, cursor);
+            for _ in 0..count {
+                while walker.prev() && !is_sentence_start(content_buffer, &walker) {}
+            }
+            Some((walker.pos(), MotionKind::Exclusive))
+        }
+        Motion::ParagraphForward => {
+            let mut line = line;
+            for _ in 0..count {
+                while line < last_line && line_len(line) == 0 {
+                    line += 1;
+                }
+                while line < last_line && line_len(line) != 0 {
+                    line += 1;
+                }
+            }
+            // past the last paragraph the motion stops at the very end of the file
+            match line_len(line) {
+                0 => Some(((line, 0), MotionKind::Exclusive)),
+                len => Some(((line, len), MotionKind::Exclusive)),
+            }
+        }
+        Motion::ParagraphBackward => {
+            let mut line = line;
+            for _ in 0..count {
+                while line > 0 && line_len(line) == 0 {
+                    line -= 1;
+                }
+                while line > 0 && line_len(line) != 0 {
+                    line -= 1;
+                }
+            }
+            Some(((line, 0), MotionKind::Exclusive))
+        }
+        Motion::ScreenTop => {
+            let (top, bottom) = context.screen;
+            to_line((top + count - 1).min(bottom))
+        }
+        Motion::ScreenMiddle => {
+            let (top, bottom) = context.screen;
+            to_line((top + bottom.min(last_line)) / 2)
+        }
+        Motion::ScreenBottom => {
+            let (top, bottom) = context.screen;
+            to_line(bottom.min(last_line).saturating_sub(count - 1).max(top))
+        }
+        Motion::FirstLine => to_line(context.count.unwrap_or(1).saturating_sub(1)),
+        Motion::LastLine => match context.count {
+            Some(count) => to_line(count.saturating_sub(1)),
+            None => to_line(last_line),
+        },
+    }
+}
+
+/// the end of the word under the cursor for cw, which unlike e stays put when the cursor is on
+/// the last char of a word already
+pub fn change_word_end(
+    content_buffer: &GapBuffer<GapBuffer<char>>,
+    cursor: (usize, usize),
+    count: usize,
+    big: bool,
+) -> (usize, usize) {
+    let mut walker = Walker::new(content_buffer, cursor);
+    let class = walker.class(big);
+    let at_word_end = walker.col + 1 >= walker.chars.len()
+        || class_of(Some(walker.chars[walker.col + 1]), false, big) != class;
+    let steps = if at_word_end { count - 1 } else { count };
+    for _ in 0..steps {
+        word_end(&mut walker, big);
+    }
+    walker.pos()
+}
+
+/// where the last of `count` w motions starts from, which decides whether dw stops at the end of
+/// the line instead of taking the newline with it
+pub fn word_forward_from(
+    content_buffer: &GapBuffer<GapBuffer<char>>,
+    cursor: (usize, usize),
+    count: usize,
+    big: bool,
+) -> (usize, usize) {
+    let mut walker = Walker::new(content_buffer, cursor);
+    for _ in 1..count {
+        word_forward(&mut walker, big);
+    }
+    walker.pos()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Class {
+    Blank,
+    /// an empty line, which w, b and ge stop at as if it were a word
+    EmptyLine,
+    Punctuation,
+    Word,
+}
+
+fn class_of(c: Option<char>, empty_line: bool, big: bool) -> Class {
+    match c {
+        None if empty_line => Class::EmptyLine,
+        None | Some(' ' | '\t') => Class::Blank,
+        Some(_) if big => Class::Word,
+        Some(c) if c.is_alphanumeric() || c == '_' => Class::Word,
+        Some(_) => Class::Punctuation,
+    }
+}
+
+/// steps through the buffer one char at a time across lines. every line has one extra position
+/// past its last char that stands for its newline
+struct Walker<'a> {
+    content_buffer: &'a GapBuffer<GapBuffer<char>>,
+    line: usize,
+    col: usize,
+    chars: Vec<char>,
+}
+
+impl<'a> Walker<'a> {
+    fn new(content_buffer: &'a GapBuffer<GapBuffer<char>>, (line, col): (usize, usize)) -> Self {
+        let chars = line_chars(content_buffer, line);
+        Walker {
+            content_buffer,
+            line,
+            col: col.min(chars.len()),
+            chars,
+        }
+    }
+    fn pos(&self) -> (usize, usize) {
+        (self.line, self.col)
+    }
+    /// the char at the position, None for the newline
+    fn char(&self) -> Option<char> {
+        self.chars.get(self.col).copied()
+    }
+    fn class(&self, big: bool) -> Class {
+        class_of(self.char(), self.chars.is_empty(), big)
+    }
+    fn next(&mut self) -> bool {
+        if self.col < self.chars.len() {
+            self.col += 1;
+        } else if self.line + 1 < self.content_buffer.get_filled_items() {
+            self.line += 1;
+            self.chars = line_chars(self.content_buffer, self.line);
+            self.col = 0;
+        } else {
+            return false;
+        }
+        true
+    }
+    fn prev(&mut self) -> bool {
+        if self.col > 0 {
+            self.col -= 1;
+        } else if self.line > 0 {
+            self.line -= 1;
+            self.chars = line_chars(self.content_buffer, self.line);
+            self.col = self.chars.len();
+        } else {
+            return false;
+        }
+        true
+    }
+}
+
+fn word_forward(walker: &mut Walker, big: bool) {
+    let class = walker.class(big);
+    match class {
+        Class::Word | Class::Punctuation => {
+            while walker.class(big) == class {
+                if !walker.next() {
+                    return;
+                }
+            }
+        }
+        Class::EmptyLine => {
+            if !walker.next() {
+                return;
+            }
+        }
+        Class::Blank => (),
+    }
+    while walker.class(big) == Class::Blank {
+        if !walker.next() {
+            return;
+        }
+    }
+}
+
+fn word_backward(walker: &mut Walker, big: bool) {
+    if !walker.prev() {
+        return;
+    }
+    while walker.class(big) == Class::Blank {
+        if !walker.prev() {
+            return;
+        }
+    }
+    let class = walker.class(big);
+    if class == Class::EmptyLine {
+        return;
+    }
+    while walker.col > 0 && class_of(Some(walker.chars[walker.col - 1]), false, big) == class {
+        walker.col -= 1;
+    }
+}
+
+fn word_end(walker: &mut Walker, big: bool) {
+    if !walker.next() {
+        return;
+    }
+    while matches!(walker.class(big), Class::Blank | Class::EmptyLine) {
+        if !walker.next() {
+            return;
+        }
+    }
+    let class = walker.class(big);
+    while walker.col + 1 < walker.chars.len()
+        && class_of(Some(walker.chars[walker.col + 1]), false, big) == class
+    {
+        walker.col += 1;
+    }
+}
+
+fn word_end_backward(walker: &mut Walker, big: bool) {
+    let class = walker.class(big);
+    match class {
+        Class::Word | Class::Punctuation => {
+            while walker.class(big) == class {
+                if !walker.prev() {
+                    return;
+                }
+            }
+        }
+        Class::Blank | Class::EmptyLine => {
+            if !walker.prev() {
+                return;
+            }
+        }
+    }
+    while walker.class(big) == Class::Blank {
+        if !walker.prev() {
+            return;
+        }
+    }
+}
+
+/// f, F, t and T. a repeated t or T looks past a match right next to the cursor, or it would
+/// never get anywhere
+fn find_char(
+    content_buffer: &GapBuffer<GapBuffer<char>>,
+    (line, col): (usize, usize),
+    find: Find,
+    c: char,
+    count: usize,
+    repeat: bool,
+) -> Option<((usize, usize), MotionKind)> {
+    let chars = line_chars(content_buffer, line);
+    let skip = usize::from(repeat && matches!(find, Find::TillForward | Find::TillBackward));
+    match find {
+        Find::Forward | Find::TillForward => {
+            let from = col + 1 + skip;
+            let found = (from..chars.len())
+                .filter(|i| chars[*i] == c)
+                .nth(count - 1)?;
+            let found = if find == Find::TillForward {
+                found - 1
+            } else {
+                found
+            };
+            Some(((line, found), MotionKind::Inclusive))
+        }
+        Find::Backward | Find::TillBackward => {
+            let to = col.checked_sub(skip)?;
+            let found = (0..to).rev().filter(|i| chars[*i] == c).nth(count - 1)?;
+            let found = if find == Find::TillBackward {
+                found + 1
+            } else {
+                found
+            };
+            Some(((line, found), MotionKind::Exclusive))
+        }
+    }
+}
+
+/// the bracket matching the first one at or after the cursor on its line, looked for across
+/// lines and skipping over the pairs nested inside
+fn match_pair(
+    content_buffer: &GapBuffer<GapBuffer<char>>,
+    (line, col): (usize, usize),
+) -> Option<(usize, usize)> {
+    const PAIRS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+    let chars = line_chars(content_buffer, line);
+    let start = (col..chars.len()).find(|i| {
+        PAIRS
+            .iter()
+            .any(|(open, close)| chars[*i] == *open || chars[*i] == *close)
+    })?;
+    let bracket = chars[start];
+    let (open, close) = *PAIRS
+        .iter()
+        .find(|(open, close)| bracket == *open || bracket == *close)?;
+
+    content_buffer.find_unmatched((line, start), open, close, bracket == open)
+}
+
+/// the start of the sentence `pos` is in and the start of the one after it, or the end of the
+/// buffer when there is none, for the is and as text objects
+pub fn sentence_bounds(
+    content_buffer: &GapBuffer<GapBuffer<char>>,
+    pos: (usize, usize),
+) -> ((usize, usize), (usize, usize)) {
+    let mut walker = Walker::new(content_buffer, pos);
+    while !is_sentence_start(content_buffer, &walker) && walker.prev() {}
+    let start = walker.pos();
+    while walker.next() && !is_sentence_start(content_buffer, &walker) {}
+    (start, walker.pos())
+}
+
+/// whether a sentence starts where the walker is: at an empty line that follows text, or at the
+/// first char after a . ! or ? (and any closing brackets or quotes) followed by a blank
+fn is_sentence_start(content_buffer: &GapBuffer<GapBuffer<char>>, walker: &Walker) -> bool {
+    match walker.class(true) {
+        Class::EmptyLine => {
+            return walker.line == 0 || !line_chars(content_buffer, walker.line - 1).is_empty();
+        }
+        Class::Blank => return false,
+        _ => (),
+    }
+    let mut before = Walker::new(content_buffer, walker.pos());
+    let mut blank_between = false;
+    loop {
+        if !before.prev() {
+            return true;
+        }
+        match before.class(true) {
+            Class::Blank => blank_between = true,
+            Class::EmptyLine => return true,
+            _ => break,
+        }
+    }
+    if !blank_between {
+        return false;
+    }
+    while matches!(before.char(), Some(')' | ']' | '"' | '\'')) {
+        if !before.prev() {
+            return false;
+        }
+    }
+    matches!(before.char(), Some('.' | '!' | '?'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(count: Option<usize>) -> Context {
+        Context {
+            count,
+            screen: (0, 20),
+            last_find: None,
+            last_search: None,
+            wrapscan: true,
+        }
+    }
+
+    fn land(
+        text: &str,
+        cursor: (usize, usize),
+        motion: Motion,
+        count: Option<usize>,
+    ) -> (usize, usize) {
+        let content_buffer = GapBuffer::build_nested(text);
+        target(&content_buffer, cursor, motion, &context(count))
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn word_motions() {
+        let text = "foo.bar baz\n\n  qux\n";
+        let w = Motion::WordForward { big: false };
+        assert_eq!(land(text, (0, 0), w, None), (0, 3));
+        assert_eq!(land(text, (0, 0), w, Some(3)), (0, 8));
+        // an empty line is a word of its own
+        assert_eq!(land(text, (0, 8), w, None), (1, 0));
+        assert_eq!(
+            land(text, (0, 0), Motion::WordForward { big: true }, None),
+            (0, 8)
+        );
+        assert_eq!(
+            land(text, (2, 2), Motion::WordBackward { big: false }, None),
+            (1, 0)
+        );
+        assert_eq!(
+            land(text, (0, 0), Motion::WordEnd { big: false }, None),
+            (0, 2)
+        );
+        assert_eq!(
+            land(text, (0, 9), Motion::WordEndBackward { big: false }, None),
+            (0, 6)
+        );
+    }
+
+    #[test]
+    fn finds_and_pairs() {
+        let text = "if (a[1]) {\n  b(c)\n}\n";
+        let f = |find, c| Motion::FindChar(find, c);
+        assert_eq!(land(text, (0, 0), f(Find::Forward, 'a'), None), (0, 4));
+        assert_eq!(land(text, (0, 0), f(Find::TillForward, ')'), None), (0, 7));
+        assert_eq!(land(text, (0, 8), f(Find::Backward, '('), None), (0, 3));
+        assert_eq!(land(text, (0, 0), Motion::MatchPair, None), (0, 8));
+        assert_eq!(land(text, (0, 10), Motion::MatchPair, None), (2, 0));
+        assert_eq!(land(text, (2, 0), Motion::MatchPair, None), (0, 10));
+    }
+
+    #[test]
+    fn sentences_and_paragraphs() {
+        let text = "One. Two\nthree!  Four\n\nFive\n";
+        assert_eq!(land(text, (0, 0), Motion::SentenceForward, None), (0, 5));
+        assert_eq!(land(text, (0, 5), Motion::SentenceForward, None), (1, 8));
+        assert_eq!(land(text, (1, 8), Motion::SentenceBackward, None), (0, 5));
+        assert_eq!(land(text, (0, 2), Motion::ParagraphForward, None), (2, 0));
+        assert_eq!(land(text, (3, 2), Motion::ParagraphBackward, None), (2, 0));
+        assert_eq!(land(text, (3, 2), Motion::LastLine, Some(2)), (1, 0));
+    }
+}
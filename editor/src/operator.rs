@@ -0,0 +1,424 @@
+use crate::clipboard;
+use crate::config::{BlockInsert, EditorMode, PendingCommand};
+use crate::motion::{self, Context, Find, Motion, MotionKind};
+use crate::ops::{self, Operator, RangeKind, TextRange};
+use crate::register::{Register, Registers};
+use crate::search;
+use crate::textobject::{self, TextObject};
+use crate::{place_cursor, EditorConfig};
+use gap_buffer::GapBuffer;
+use std::env;
+use terminol::{Key, KeyCode, Modifiers};
+
+/// the longest line gq makes when textwidth isn't set, the same as vim
+const DEFAULT_TEXT_WIDTH: usize = 79;
+
+/// d, c, y and the other operators typed in normal mode. nothing happens until the motion that
+/// says what to work on has been typed in operator pending mode
+pub fn start(editor_config: &mut EditorConfig, operator: Operator) {
+    let editor_state = &mut editor_config.editor_state;
+    editor_state.pending.operator = Some(operator);
+    editor_state.pending.operator_count = editor_state.pending.count.take();
+    editor_state.update_editor_mode(EditorMode::OperatorPending);
+}
+
+/// handles the keys after an operator: a count, then a motion, a text object or the operator's
+/// own key again for whole lines, as in dd or gUU. anything else throws the whole command away
+pub fn operator_pending_handler(key: &Key, editor_config: &mut EditorConfig) {
+    let editor_state = &mut editor_config.editor_state;
+    let operator = match editor_state.pending.operator {
+        Some(operator) => operator,
+        None => return cancel(editor_config),
+    };
+    let char_key = match (key.code, key.modifiers) {
+        (KeyCode::Char(c), Modifiers::NONE | Modifiers::SHIFT) => Some(c),
+        _ => None,
+    };
+
+    if let Some(first) = editor_state.pending_key.take() {
+        let motion = match (first, char_key) {
+            // g~g~, gugu and the like work on lines just as g~~ does
+            ('g', Some(c)) if operator.is_g() && c == operator.line_key() => {
+                return on_lines(editor_config, operator)
+            }
+            ('g', Some(c)) => Motion::from_g_key(c),
+            (first @ ('i' | 'a'), Some(c)) => {
+                return on_object(editor_config, operator, first == 'i', c)
+            }
+            (first, Some(c)) => Find::from_key(first).map(|find| Motion::FindChar(find, c)),
+            _ => None,
+        };
+        match motion {
+            Some(motion) => on_motion(editor_config, operator, motion),
+            None => cancel(editor_config),
+        }
+        return;
+    }
+
+    let motion = match (key.code, key.modifiers) {
+        (KeyCode::Esc, _) | (KeyCode::Char('c'), Modifiers::CTRL) => None,
+        (KeyCode::Char(digit @ '1'..='9'), Modifiers::NONE) => {
+            editor_state
+                .pending
+                .push_digit(digit.to_digit(10).unwrap_or(0));
+            return;
+        }
+        (KeyCode::Char('0'), Modifiers::NONE) if editor_state.pending.count.is_some() => {
+            editor_state.pending.push_digit(0);
+            return;
+        }
+        (KeyCode::Char(c), Modifiers::NONE) if c == operator.line_key() => {
+            return on_lines(editor_config, operator)
+        }
+        (KeyCode::Char(c @ ('g' | 'f' | 'F' | 't' | 'T' | 'i' | 'a')), Modifiers::NONE) => {
+            editor_state.pending_key = Some(c);
+            return;
+        }
+        (KeyCode::Char(c @ ('/' | '?')), Modifiers::NONE) => {
+            return search::open_prompt(editor_config, c == '/')
+        }
+        (KeyCode::Char(c), Modifiers::NONE) => Motion::from_key(c),
+        (KeyCode::Left, _) => Some(Motion::Left),
+        (KeyCode::Right, _) => Some(Motion::Right),
+        (KeyCode::Up, _) => Some(Motion::Up),
+        (KeyCode::Down, _) => Some(Motion::Down),
+        (KeyCode::Home, _) => Some(Motion::LineStart),
+        (KeyCode::End, _) => Some(Motion::LineEnd),
+        _ => None,
+    };
+    match motion {
+        Some(motion) => on_motion(editor_config, operator, motion),
+        None => cancel(editor_config),
+    }
+}
+
+/// drops the command typed so far and goes back to normal mode
+pub fn cancel(editor_config: &mut EditorConfig) {
+    let editor_state = &mut editor_config.editor_state;
+    editor_state.pending = PendingCommand::default();
+    editor_state.pending_key = None;
+    editor_state.update_editor_mode(EditorMode::Normal);
+}
+
+/// dd, yy, >> and so on, the operator on the cursor's line and the count - 1 lines below it
+fn on_lines(editor_config: &mut EditorConfig, operator: Operator) {
+    let content_buffer = &editor_config.gap_buffer;
+    let count = editor_config
+        .editor_state
+        .pending
+        .total_count()
+        .unwrap_or(1);
+    let (line, col) = content_buffer.cursor_position();
+    let (line, col) = (line - 1, col - 1);
+    let last_line = content_buffer.get_filled_items() - 1;
+    let range = TextRange {
+        kind: RangeKind::Line,
+        start: (line, col),
+        end: ((line + count - 1).min(last_line), col),
+    };
+    apply(editor_config, operator, range);
+}
+
+/// diw, ca( and the rest, the operator on the text object named by `key`
+fn on_object(editor_config: &mut EditorConfig, operator: Operator, inner: bool, key: char) {
+    let count = editor_config
+        .editor_state
+        .pending
+        .total_count()
+        .unwrap_or(1);
+    let (line, col) = editor_config.gap_buffer.cursor_position();
+    let range = TextObject::from_key(key).and_then(|object| {
+        textobject::select(
+            &editor_config.gap_buffer,
+            (line - 1, col - 1),
+            object,
+            inner,
+            count,
+        )
+    });
+    match range {
+        Some(range) => apply(editor_config, operator, range),
+        None => cancel(editor_config),
+    }
+}
+
+/// the operator on the text between the cursor and where `motion` takes it, as in dw or d/foo
+pub fn on_motion(editor_config: &mut EditorConfig, operator: Operator, motion: Motion) {
+    let editor_state = &mut editor_config.editor_state;
+    let content_buffer = &editor_config.gap_buffer;
+    if let Motion::FindChar(find, c) = motion {
+        editor_state.last_find = Some((find, c));
+    }
+    let context = Context {
+        count: editor_state.pending.total_count(),
+        screen: (editor_config.viewport.top, editor_config.viewport.bottom()),
+        last_find: editor_state.last_find,
+        last_search: search::last_search(editor_state),
+        wrapscan: editor_state.options.wrapscan,
+    };
+    let (line, col) = content_buffer.cursor_position();
+    let cursor = (line - 1, col - 1);
+    let count = context.count.unwrap_or(1).max(1);
+
+    let target = match motion {
+        // cw on a word changes up to its end and leaves the blanks after it alone
+        Motion::WordForward { big }
+            if operator == Operator::Change && on_non_blank(content_buffer, cursor) =>
+        {
+            let end = motion::change_word_end(content_buffer, cursor, count, big);
+            Some((end, MotionKind::Inclusive))
+        }
+        // the last word moved over ending its line ends the text operated on there too, rather
+        // than taking in the newline and the indent of the next line
+        Motion::WordForward { big } => {
+            let from = motion::word_forward_from(content_buffer, cursor, count, big);
+            match motion::target(content_buffer, cursor, motion, &context) {
+                Some((to, kind)) if to.0 > from.0 => {
+                    let len = ops::line_chars(content_buffer, from.0).len();
+                    Some(((from.0, len), kind))
+                }
+                target => target,
+            }
+        }
+        _ => motion::target(content_buffer, cursor, motion, &context),
+    };
+    let range = match target {
+        Some((to, kind)) => motion_range(content_buffer, cursor, to, kind),
+        None => return cancel(editor_config),
+    };
+    match range {
+        Some(range) => apply(editor_config, operator, range),
+        // there is nothing to change, but c still starts insert mode like it would have
+        None if operator == Operator::Change => {
+            editor_config.undo_tree.begin(&editor_config.gap_buffer);
+            cancel(editor_config);
+            editor_config
+                .editor_state
+                .update_editor_mode(EditorMode::Insert);
+        }
+        None => cancel(editor_config),
+    }
+}
+
+fn on_non_blank(content_buffer: &GapBuffer<GapBuffer<char>>, (line, col): (usize, usize)) -> bool {
+    ops::line_chars(content_buffer, line)
+        .get(col)
+        .is_some_and(|c| *c != ' ' && *c != '\t')
+}
+
+/// the text between the cursor and where a motion landed. an exclusive motion ending at the start
+/// of a later line stops at the end of the line before it instead, and takes whole lines when it
+/// also started before any text on its line, which is what makes d} delete whole paragraphs.
+/// None when an exclusive motion didn't move at all
+fn motion_range(
+    content_buffer: &GapBuffer<GapBuffer<char>>,
+    cursor: (usize, usize),
+    to: (usize, usize),
+    kind: MotionKind,
+) -> Option<TextRange> {
+    let (start, end) = if cursor <= to {
+        (cursor, to)
+    } else {
+        (to, cursor)
+    };
+    let range = |kind, end| TextRange { kind, start, end };
+    match kind {
+        MotionKind::Linewise => Some(range(RangeKind::Line, end)),
+        MotionKind::Inclusive => Some(range(RangeKind::Char, end)),
+        MotionKind::Exclusive if start == end => None,
+        MotionKind::Exclusive if end.1 == 0 => {
+            let before = end.0 - 1;
+            let len = ops::line_chars(content_buffer, before).len();
+            if start.1 <= ops::first_non_blank(content_buffer, start.0) {
+                Some(range(RangeKind::Line, (before, 0)))
+            } else {
+                // an empty line before has no last char to stop on, so its newline is taken
+                Some(range(RangeKind::Char, (before, len.saturating_sub(1))))
+            }
+        }
+        MotionKind::Exclusive => Some(range(RangeKind::Char, (end.0, end.1 - 1))),
+    }
+}
+
+/// carries out `operator` on `range`, for operator pending and visual mode alike. whatever the
+/// operator takes out of the buffer or yanks goes to the register named in the pending command
+pub fn apply(editor_config: &mut EditorConfig, operator: Operator, range: TextRange) {
+    let register_name = editor_config.editor_state.pending.register;
+    editor_config.editor_state.pending = PendingCommand::default();
+    editor_config
+        .editor_state
+        .update_editor_mode(EditorMode::Normal);
+    let stores = matches!(
+        operator,
+        Operator::Yank | Operator::Delete | Operator::Change
+    );
+    if stores && !Registers::is_writable(register_name) {
+        editor_config.editor_state.message = Some(format!(
+            "E354: Invalid register name: '{}'",
+            register_name.unwrap_or('"')
+        ));
+        return;
+    }
+    if stores {
+        store(editor_config, operator, register_name, &range);
+    }
+
+    let content_buffer = &mut editor_config.gap_buffer;
+    let undo_tree = &mut editor_config.undo_tree;
+    let options = &editor_config.editor_state.options;
+    let line_start = |content_buffer: &GapBuffer<GapBuffer<char>>, line: usize| {
+        (line, ops::first_non_blank(content_buffer, line))
+    };
+
+    // a yank leaves the buffer as it was, so the undo tree needn't copy it
+    let changes = operator != Operator::Yank;
+    if changes {
+        undo_tree.begin(content_buffer);
+    }
+    let cursor = match operator {
+        Operator::Yank => range.start,
+        Operator::Delete => {
+            let cursor = ops::delete(content_buffer, &range);
+            match range.kind {
+                RangeKind::Line => line_start(content_buffer, cursor.0),
+                _ => cursor,
+            }
+        }
+        Operator::Change => {
+            change(editor_config, &range);
+            // the change stays open until insert mode is left, so it is undone with the typing
+            return;
+        }
+        Operator::ShiftRight | Operator::ShiftLeft => {
+            let right = operator == Operator::ShiftRight;
+            ops::shift_lines(content_buffer, range.lines(), options.shiftwidth, right);
+            line_start(content_buffer, range.start.0)
+        }
+        Operator::Indent => {
+            ops::reindent_lines(content_buffer, range.lines(), options.shiftwidth);
+            line_start(content_buffer, range.start.0)
+        }
+        Operator::ToggleCase => {
+            ops::map_chars(content_buffer, &range, ops::toggle_case);
+            range.start
+        }
+        Operator::Lowercase => {
+            ops::map_chars(content_buffer, &range, |c| {
+                c.to_lowercase().next().unwrap_or(c)
+            });
+            range.start
+        }
+        Operator::Uppercase => {
+            ops::map_chars(content_buffer, &range, |c| {
+                c.to_uppercase().next().unwrap_or(c)
+            });
+            range.start
+        }
+        Operator::Format => {
+            let width = match options.textwidth {
+                0 => DEFAULT_TEXT_WIDTH,
+                textwidth => textwidth,
+            };
+            let last = ops::format_lines(content_buffer, range.lines(), width);
+            line_start(content_buffer, last)
+        }
+        Operator::Join => {
+            // a range on a single line still joins it with the next one
+            let last_line = content_buffer.get_filled_items() - 1;
+            let last = range.end.0.max(range.start.0 + 1).min(last_line);
+            if last == range.start.0 {
+                range.start
+            } else {
+                ops::join_lines(content_buffer, range.start.0, last)
+            }
+        }
+    };
+    if changes {
+        editor_config.undo_tree.commit(&editor_config.gap_buffer);
+    }
+    place_cursor(&mut editor_config.gap_buffer, cursor);
+}
+
+/// puts the text a yank, delete or change is about to take in the register named for it, and
+/// passes it on to the system for "+ and "*
+fn store(
+    editor_config: &mut EditorConfig,
+    operator: Operator,
+    name: Option<char>,
+    range: &TextRange,
+) {
+    let register = Register {
+        text: ops::get_text(&editor_config.gap_buffer, range),
+        kind: range.kind,
+    };
+    let registers = &mut editor_config.registers;
+    match operator {
+        Operator::Yank => registers.yank(name, register),
+        _ => registers.delete(name, register),
+    }
+    let (name, register) = match name.zip(registers.get(name)) {
+        Some((name @ ('+' | '*'), register)) => (name, register),
+        _ => return,
+    };
+    let options = &editor_config.editor_state.options;
+    let path = env::var_os("PATH").unwrap_or_default();
+    if let Err(e) = clipboard::copy(name, &register, &options.clipcmd, options.osc52, &path) {
+        editor_config.editor_state.message = Some(e);
+    }
+}
+
+/// c, removes the text and starts insert mode where it was. a changed block gets what is typed
+/// on its first line repeated on every other line once insert mode is left
+fn change(editor_config: &mut EditorConfig, range: &TextRange) {
+    let content_buffer = &mut editor_config.gap_buffer;
+    let (line, col) = match range.kind {
+        RangeKind::Line => {
+            let lines = range.end.0 - range.start.0 + 1;
+            content_buffer.replace_lines(range.start.0, lines, &[String::new()]);
+            (range.start.0, 0)
+        }
+        RangeKind::Char => ops::delete(content_buffer, range),
+        RangeKind::Block => {
+            let cursor = ops::delete(content_buffer, range);
+            editor_config.editor_state.block_insert = Some(BlockInsert {
+                first: range.start.0,
+                last: range.end.0,
+                col: range.start.1,
+                len_before: ops::line_chars(content_buffer, range.start.0).len(),
+                line_count: content_buffer.get_filled_items(),
+            });
+            cursor
+        }
+    };
+    content_buffer.set_cursor_position((line + 1, col + 1));
+    editor_config
+        .editor_state
+        .update_editor_mode(EditorMode::Insert);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exclusive_motions_stop_before_the_next_line() {
+        let content_buffer = GapBuffer::build_nested("  one two\nthree\n\nfour\n");
+        let range = |cursor, to| motion_range(&content_buffer, cursor, to, MotionKind::Exclusive);
+
+        assert_eq!(range((0, 4), (0, 4)), None);
+        assert_eq!(
+            range((0, 4), (0, 6)).map(|range| (range.kind, range.end)),
+            Some((RangeKind::Char, (0, 5)))
+        );
+        // starting after the indent, the text up to the end of the line before is taken
+        assert_eq!(
+            range((0, 6), (2, 0)).map(|range| (range.kind, range.end)),
+            Some((RangeKind::Char, (1, 4)))
+        );
+        // starting at the indent, whole lines are
+        assert_eq!(
+            range((0, 2), (2, 0)).map(|range| (range.kind, range.end)),
+            Some((RangeKind::Line, (1, 0)))
+        );
+    }
+}
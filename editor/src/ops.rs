@@ -0,0 +1,595 @@
+use gap_buffer::GapBuffer;
+use std::ops::RangeInclusive;
+
+/// the shape of a piece of text, the same three vim has for selections and registers
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RangeKind {
+    /// runs from one character to another, across lines
+    Char,
+    /// whole lines
+    Line,
+    /// the same columns on each of a run of lines
+    Block,
+}
+
+/// what is done to the text an operator is given, by a motion or a selection
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operator {
+    Delete,
+    Yank,
+    Change,
+    ShiftRight,
+    ShiftLeft,
+    /// =, lines up the indent of each line with the lines around it
+    Indent,
+    ToggleCase,
+    Lowercase,
+    Uppercase,
+    /// gq, rewraps the lines to fit the text width
+    Format,
+    /// J, only reachable from visual mode
+    Join,
+}
+
+impl Operator {
+    /// the operator started by a key in normal mode, with the g before it if `g` is set
+    pub fn from_key(g: bool, key: char) -> Option<Operator> {
+        let operator = match (g, key) {
+            (false, 'd') => Operator::Delete,
+            (false, 'y') => Operator::Yank,
+            (false, 'c') => Operator::Change,
+            (false, '>') => Operator::ShiftRight,
+            (false, '<') => Operator::ShiftLeft,
+            (false, '=') => Operator::Indent,
+            (true, '~') => Operator::ToggleCase,
+            (true, 'u') => Operator::Lowercase,
+            (true, 'U') => Operator::Uppercase,
+            (true, 'q') => Operator::Format,
+            _ => return None,
+        };
+        Some(operator)
+    }
+    /// the key that, typed again, makes the operator work on whole lines, as in dd, >> or gUU
+    pub fn line_key(&self) -> char {
+        match self {
+            Operator::Delete => 'd',
+            Operator::Yank => 'y',
+            Operator::Change => 'c',
+            Operator::ShiftRight => '>',
+            Operator::ShiftLeft => '<',
+            Operator::Indent => '=',
+            Operator::ToggleCase => '~',
+            Operator::Lowercase => 'u',
+            Operator::Uppercase => 'U',
+            Operator::Format => 'q',
+            Operator::Join => 'J',
+        }
+    }
+    /// whether the operator is typed with a g in front of it
+    pub fn is_g(&self) -> bool {
+        matches!(
+            self,
+            Operator::ToggleCase | Operator::Lowercase | Operator::Uppercase | Operator::Format
+        )
+    }
+}
+
+/// a region of the buffer. positions are 0-indexed (line, col) and both ends are inclusive, with
+/// `start` never after `end`. for a block `start.1` and `end.1` are its left and right columns.
+/// a column past the end of a line stands for its newline
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextRange {
+    pub kind: RangeKind,
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+}
+
+impl TextRange {
+    /// the range between two positions given in either order, such as the anchor of a visual
+    /// selection and the cursor
+    pub fn between(kind: RangeKind, a: (usize, usize), b: (usize, usize)) -> TextRange {
+        let (start, end) = if a <= b { (a, b) } else { (b, a) };
+        match kind {
+            RangeKind::Block => TextRange {
+                kind,
+                start: (start.0, a.1.min(b.1)),
+                end: (end.0, a.1.max(b.1)),
+            },
+            _ => TextRange { kind, start, end },
+        }
+    }
+    pub fn lines(&self) -> RangeInclusive<usize> {
+        self.start.0..=self.end.0
+    }
+    /// the columns of `line` the range covers as [from, to). `to` is one past `line_len` when
+    /// the newline at the end of the line is covered too
+    pub fn cols_on(&self, line: usize, line_len: usize) -> Option<(usize, usize)> {
+        if !self.lines().contains(&line) {
+            return None;
+        }
+        let whole = (0, line_len + 1);
+        match self.kind {
+            RangeKind::Line => Some(whole),
+            RangeKind::Block => {
+                let from = self.start.1.min(line_len);
+                let to = (self.end.1 + 1).min(line_len);
+                Some((from, to))
+            }
+            RangeKind::Char => {
+                let from = if line == self.start.0 {
+                    self.start.1
+                } else {
+                    0
+                };
+                let to = if line == self.end.0 {
+                    (self.end.1 + 1).min(line_len + 1)
+                } else {
+                    whole.1
+                };
+                Some((from.min(line_len), to))
+            }
+        }
+    }
+}
+
+pub fn line_chars(content_buffer: &GapBuffer<GapBuffer<char>>, line: usize) -> Vec<char> {
+    match content_buffer.get(line) {
+        Some(line_buf) => line_buf.get_text().chars().collect(),
+        None => Vec::new(),
+    }
+}
+
+/// the column of the first char of `line` that isn't a blank, or of its last char when there is
+/// none
+pub fn first_non_blank(content_buffer: &GapBuffer<GapBuffer<char>>, line: usize) -> usize {
+    let chars = line_chars(content_buffer, line);
+    chars
+        .iter()
+        .position(|c| *c != ' ' && *c != '\t')
+        .unwrap_or(chars.len().saturating_sub(1))
+}
+
+fn indent_of(chars: &[char]) -> usize {
+    chars
+        .iter()
+        .take_while(|c| **c == ' ' || **c == '\t')
+        .count()
+}
+
+fn replace_line(content_buffer: &mut GapBuffer<GapBuffer<char>>, line: usize, chars: &[char]) {
+    content_buffer.replace_lines(line, 1, &[chars.iter().collect()]);
+}
+
+/// the text inside `range`, one string per line. charwise text that takes in a newline ends in
+/// an empty string, so joining the pieces with newlines always gives back the exact text
+pub fn get_text(content_buffer: &GapBuffer<GapBuffer<char>>, range: &TextRange) -> Vec<String> {
+    let mut text = Vec::new();
+    for line in range.lines() {
+        let chars = line_chars(content_buffer, line);
+        let (from, to) = range.cols_on(line, chars.len()).unwrap_or((0, 0));
+        text.push(chars[from..to.min(chars.len())].iter().collect());
+        if range.kind == RangeKind::Char && line == range.end.0 && to > chars.len() {
+            text.push(String::new());
+        }
+    }
+    text
+}
+
+/// removes the text inside `range`. returns where the cursor goes, which is where the text
+/// started. the buffer always keeps at least one line
+pub fn delete(
+    content_buffer: &mut GapBuffer<GapBuffer<char>>,
+    range: &TextRange,
+) -> (usize, usize) {
+    let (first, last) = (range.start.0, range.end.0);
+    match range.kind {
+        RangeKind::Line => {
+            content_buffer.replace_lines(first, last - first + 1, &[]);
+            if content_buffer.get_filled_items() == 0 {
+                content_buffer.replace_lines(0, 0, &[String::new()]);
+            }
+            let line = first.min(content_buffer.get_filled_items() - 1);
+            (line, 0)
+        }
+        RangeKind::Block => {
+            for line in range.lines() {
+                let mut chars = line_chars(content_buffer, line);
+                if let Some((from, to)) = range.cols_on(line, chars.len()) {
+                    chars.drain(from..to);
+                    replace_line(content_buffer, line, &chars);
+                }
+            }
+            (first, range.start.1)
+        }
+        RangeKind::Char => {
+            let first_chars = line_chars(content_buffer, first);
+            let last_chars = line_chars(content_buffer, last);
+            let from = range.start.1.min(first_chars.len());
+            // taking the newline of the last line pulls the line after it up as well
+            let (last, rest) = if range.end.1 >= last_chars.len()
+                && last + 1 < content_buffer.get_filled_items()
+            {
+                (last + 1, line_chars(content_buffer, last + 1))
+            } else {
+                let to = (range.end.1 + 1).min(last_chars.len());
+                (last, last_chars[to..].to_vec())
+            };
+            let mut joined = first_chars[..from].to_vec();
+            joined.extend(rest);
+            content_buffer.replace_lines(first, last - first + 1, &[joined.iter().collect()]);
+            (first, from)
+        }
+    }
+}
+
+/// runs every character inside `range` through `f`, for ~, u and U
+pub fn map_chars(
+    content_buffer: &mut GapBuffer<GapBuffer<char>>,
+    range: &TextRange,
+    f: impl Fn(char) -> char,
+) {
+    for line in range.lines() {
+        let mut chars = line_chars(content_buffer, line);
+        if let Some((from, to)) = range.cols_on(line, chars.len()) {
+            let to = to.min(chars.len());
+            for c in &mut chars[from..to] {
+                *c = f(*c);
+            }
+            replace_line(content_buffer, line, &chars);
+        }
+    }
+}
+
+/// flips the case of a character, for ~
+pub fn toggle_case(c: char) -> char {
+    if c.is_lowercase() {
+        c.to_uppercase().next().unwrap_or(c)
+    } else {
+        c.to_lowercase().next().unwrap_or(c)
+    }
+}
+
+/// moves the lines `shiftwidth` columns right or left, for > and <. empty lines are not
+/// indented, and a line is never shifted left past its start
+pub fn shift_lines(
+    content_buffer: &mut GapBuffer<GapBuffer<char>>,
+    lines: RangeInclusive<usize>,
+    shiftwidth: usize,
+    right: bool,
+) {
+    for line in lines {
+        let chars = line_chars(content_buffer, line);
+        if chars.is_empty() {
+            continue;
+        }
+        let shifted: Vec<char> = if right {
+            std::iter::repeat_n(' ', shiftwidth).chain(chars).collect()
+        } else {
+            let indent = chars
+                .iter()
+                .take(shiftwidth)
+                .take_while(|c| **c == ' ')
+                .count();
+            // a tab counts as a whole level of indent on its own
+            let indent = if indent == 0 && chars[0] == '\t' {
+                1
+            } else {
+                indent
+            };
+            chars[indent..].to_vec()
+        };
+        replace_line(content_buffer, line, &shifted);
+    }
+}
+
+/// =, gives every line the indent of the line above it, one level deeper after a line that
+/// opens a bracket and one level less for a line that starts by closing one. empty lines are
+/// left empty
+pub fn reindent_lines(
+    content_buffer: &mut GapBuffer<GapBuffer<char>>,
+    lines: RangeInclusive<usize>,
+    shiftwidth: usize,
+) {
+    let first = *lines.start();
+    // the indent carries on from the nearest line above the range that has any text
+    let mut above = (0..first)
+        .rev()
+        .map(|line| line_chars(content_buffer, line))
+        .find(|chars| !chars.is_empty());
+    for line in lines {
+        let chars = line_chars(content_buffer, line);
+        let text = &chars[indent_of(&chars)..];
+        if text.is_empty() {
+            replace_line(content_buffer, line, &[]);
+            continue;
+        }
+        let mut indent = match &above {
+            Some(above) => {
+                let opens = matches!(above.last(), Some('{' | '(' | '['));
+                indent_of(above) + if opens { shiftwidth } else { 0 }
+            }
+            None => 0,
+        };
+        if matches!(text.first(), Some('}' | ')' | ']')) {
+            indent = indent.saturating_sub(shiftwidth);
+        }
+        let indented: Vec<char> = std::iter::repeat_n(' ', indent)
+            .chain(text.iter().copied())
+            .collect();
+        replace_line(content_buffer, line, &indented);
+        above = Some(indented);
+    }
+}
+
+/// gq, rewraps each paragraph in `lines` so no line is longer than `width`, keeping the indent of
+/// the paragraph's first line. empty lines separate paragraphs and are kept. returns the last
+/// line of the formatted text
+pub fn format_lines(
+    content_buffer: &mut GapBuffer<GapBuffer<char>>,
+    lines: RangeInclusive<usize>,
+    width: usize,
+) -> usize {
+    let (first, last) = (*lines.start(), *lines.end());
+    let mut formatted: Vec<String> = Vec::new();
+    let mut paragraph: Vec<Vec<char>> = Vec::new();
+    let flush = |paragraph: &mut Vec<Vec<char>>, formatted: &mut Vec<String>| {
+        if paragraph.is_empty() {
+            return;
+        }
+        let indent: String = paragraph[0][..indent_of(&paragraph[0])].iter().collect();
+        let words: Vec<String> = paragraph
+            .iter()
+            .flat_map(|chars| {
+                let text: String = chars.iter().collect();
+                text.split_whitespace()
+                    .map(String::from)
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        let mut current = indent.clone();
+        for word in words {
+            let len = current.chars().count();
+            if len > indent.chars().count() && len + 1 + word.chars().count() > width {
+                formatted.push(std::mem::replace(&mut current, indent.clone()));
+            }
+            if current.chars().count() > indent.chars().count() {
+                current.push(' ');
+            }
+            current.push_str(&word);
+        }
+        formatted.push(current);
+        paragraph.clear();
+    };
+    for line in lines {
+        let chars = line_chars(content_buffer, line);
+        if chars.iter().all(|c| *c == ' ' || *c == '\t') {
+            flush(&mut paragraph, &mut formatted);
+            formatted.push(String::new());
+        } else {
+            paragraph.push(chars);
+        }
+    }
+    flush(&mut paragraph, &mut formatted);
+    content_buffer.replace_lines(first, last - first + 1, &formatted);
+    first + formatted.len() - 1
+}
+
+/// J, joins `first` through `last` into one line. the leading blanks of each joined line are
+/// dropped and replaced by a single space, unless the line is empty or the text before it
+/// already ends in a blank. returns where the cursor goes, at the last join
+pub fn join_lines(
+    content_buffer: &mut GapBuffer<GapBuffer<char>>,
+    first: usize,
+    last: usize,
+) -> (usize, usize) {
+    let mut joined = line_chars(content_buffer, first);
+    let mut cursor = joined.len().saturating_sub(1);
+    for line in first + 1..=last {
+        let chars = line_chars(content_buffer, line);
+        let rest: Vec<char> = chars
+            .iter()
+            .skip_while(|c| **c == ' ' || **c == '\t')
+            .copied()
+            .collect();
+        cursor = joined.len();
+        if !rest.is_empty() && joined.last().is_some_and(|c| *c != ' ' && *c != '\t') {
+            joined.push(' ');
+        }
+        joined.extend(rest);
+    }
+    content_buffer.replace_lines(first, last - first + 1, &[joined.iter().collect()]);
+    (first, cursor)
+}
+
+/// puts `text` of the given shape next to the 0-indexed `cursor`, after it or, when `before` is
+/// set, before it. returns where the cursor goes afterwards and the position just past the text
+/// that was put, which is where gp and gP leave it
+pub fn put(
+    content_buffer: &mut GapBuffer<GapBuffer<char>>,
+    cursor: (usize, usize),
+    text: &[String],
+    kind: RangeKind,
+    before: bool,
+) -> ((usize, usize), (usize, usize)) {
+    let (line, col) = cursor;
+    match kind {
+        RangeKind::Line => {
+            let at = if before { line } else { line + 1 };
+            content_buffer.replace_lines(at, 0, text);
+            ((at, 0), (at + text.len(), 0))
+        }
+        RangeKind::Char => {
+            let chars = line_chars(content_buffer, line);
+            let at = if before || chars.is_empty() {
+                col.min(chars.len())
+            } else {
+                (col + 1).min(chars.len())
+            };
+            let last = text.len() - 1;
+            let end_col = text[last].chars().count();
+            // text within a line goes straight into the line's own buffer
+            if last == 0 {
+                content_buffer.move_gap_to(line);
+                let line_buf = content_buffer.get_nested();
+                line_buf.move_to_col(at);
+                for c in text[0].chars() {
+                    line_buf.insert_left(c);
+                }
+                return (
+                    (line, (at + end_col).saturating_sub(1)),
+                    (line, at + end_col),
+                );
+            }
+            let head: String = chars[..at].iter().collect();
+            let tail: String = chars[at..].iter().collect();
+            let mut lines: Vec<String> = text.to_vec();
+            lines[0] = format!("{head}{}", lines[0]);
+            lines[last].push_str(&tail);
+            content_buffer.replace_lines(line, 1, &lines);
+            // more than one line lands on the first char put
+            ((line, at), (line + last, end_col))
+        }
+        RangeKind::Block => {
+            let at = {
+                let chars = line_chars(content_buffer, line);
+                if before || chars.is_empty() {
+                    col
+                } else {
+                    col + 1
+                }
+            };
+            let width = text.iter().map(|s| s.chars().count()).max().unwrap_or(0);
+            for (i, piece) in text.iter().enumerate() {
+                let target = line + i;
+                if target >= content_buffer.get_filled_items() {
+                    content_buffer.replace_lines(target, 0, &[String::new()]);
+                }
+                let mut chars = line_chars(content_buffer, target);
+                if chars.len() < at {
+                    chars.resize(at, ' ');
+                }
+                // pad every piece to the width of the block so the columns after it line up,
+                // unless nothing comes after it on the line
+                let mut piece: Vec<char> = piece.chars().collect();
+                if at < chars.len() {
+                    piece.resize(width, ' ');
+                }
+                chars.splice(at..at, piece);
+                replace_line(content_buffer, target, &chars);
+            }
+            ((line, at), (line + text.len() - 1, at + width))
+        }
+    }
+}
+
+/// `lines` shifted so the first of them starts with `indent`, for ]p. the lines after it keep
+/// whatever indent they had beyond the first one's, and blank lines are left alone
+pub fn reindented(lines: &[String], indent: &str) -> Vec<String> {
+    let indent_len = |line: &str| line.chars().take_while(|c| *c == ' ' || *c == '\t').count();
+    let first = lines.first().map_or(0, |line| indent_len(line));
+    lines
+        .iter()
+        .map(|line| {
+            if line.trim().is_empty() {
+                return line.clone();
+            }
+            let rest: String = line.chars().skip(indent_len(line).min(first)).collect();
+            format!("{indent}{rest}")
+        })
+        .collect()
+}
+
+/// inserts `text` into `line` at the 0-indexed `col`, for the block insert that repeats what was
+/// typed on the first line of a block on all the others. lines too short to reach `col` are left
+pub fn insert_at(
+    content_buffer: &mut GapBuffer<GapBuffer<char>>,
+    line: usize,
+    col: usize,
+    text: &str,
+) {
+    let mut chars = line_chars(content_buffer, line);
+    if chars.len() < col {
+        return;
+    }
+    chars.splice(col..col, text.chars());
+    replace_line(content_buffer, line, &chars);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(s: &str) -> GapBuffer<GapBuffer<char>> {
+        GapBuffer::build_nested(s)
+    }
+
+    #[test]
+    fn charwise_across_lines() {
+        let mut content_buffer = buffer("hello world\nsecond line\n");
+        let range = TextRange::between(RangeKind::Char, (1, 5), (0, 6));
+
+        assert_eq!(get_text(&content_buffer, &range), ["world", "second"]);
+        assert_eq!(delete(&mut content_buffer, &range), (0, 6));
+        assert_eq!(content_buffer.get_lines(), ["hello  line"]);
+    }
+
+    #[test]
+    fn blockwise_delete_and_put() {
+        let mut content_buffer = buffer("abcd\nefgh\nij\n");
+        let range = TextRange::between(RangeKind::Block, (0, 1), (2, 2));
+        let text = get_text(&content_buffer, &range);
+
+        assert_eq!(text, ["bc", "fg", "j"]);
+        delete(&mut content_buffer, &range);
+        assert_eq!(content_buffer.get_lines(), ["ad", "eh", "i"]);
+
+        put(&mut content_buffer, (0, 0), &text, RangeKind::Block, false);
+        assert_eq!(content_buffer.get_lines(), ["abcd", "efgh", "ij"]);
+    }
+
+    #[test]
+    fn linewise_put_and_join() {
+        let mut content_buffer = buffer("one\n  two\n");
+        put(
+            &mut content_buffer,
+            (0, 0),
+            &[String::from("x")],
+            RangeKind::Line,
+            false,
+        );
+        assert_eq!(content_buffer.get_lines(), ["one", "x", "  two"]);
+
+        assert_eq!(join_lines(&mut content_buffer, 0, 2), (0, 5));
+        assert_eq!(content_buffer.get_lines(), ["one x two"]);
+    }
+
+    #[test]
+    fn reindent_and_format() {
+        let mut content_buffer = buffer("fn main() {\nlet a = 1;\n      }\n");
+        reindent_lines(&mut content_buffer, 0..=2, 4);
+        assert_eq!(
+            content_buffer.get_lines(),
+            ["fn main() {", "    let a = 1;", "}"]
+        );
+
+        let mut content_buffer = buffer("  one two\nthree four five\n\nsix\n");
+        assert_eq!(format_lines(&mut content_buffer, 0..=3, 10), 5);
+        assert_eq!(
+            content_buffer.get_lines(),
+            ["  one two", "  three", "  four", "  five", "", "six"]
+        );
+    }
+
+    #[test]
+    fn shift_and_case() {
+        let mut content_buffer = buffer("ab\n\n  cd\n");
+        shift_lines(&mut content_buffer, 0..=2, 2, true);
+        assert_eq!(content_buffer.get_lines(), ["  ab", "", "    cd"]);
+        shift_lines(&mut content_buffer, 0..=2, 4, false);
+        assert_eq!(content_buffer.get_lines(), ["ab", "", "cd"]);
+
+        let range = TextRange::between(RangeKind::Char, (0, 1), (2, 0));
+        map_chars(&mut content_buffer, &range, toggle_case);
+        assert_eq!(content_buffer.get_lines(), ["aB", "", "Cd"]);
+    }
+}
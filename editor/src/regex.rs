@@ -0,0 +1,1066 @@
+use crate::ops::{RangeKind, TextRange};
+use gap_buffer::GapBuffer;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// a 0-indexed (line, col) in the content buffer. the newline of a line sits at the col one past
+/// its last char
+type Position = (usize, usize);
+
+/// the longest program a pattern may compile to, so \{99999} can't eat all the memory
+const MAX_PROGRAM: usize = 100_000;
+
+/// how much of a pattern means something without a backslash in front of it, changed with \v,
+/// \m, \M and \V part way through a pattern
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Magic {
+    Very,
+    /// \m, what a pattern starts out as
+    Normal,
+    No,
+    VeryNo,
+}
+
+/// the chars that are operators at some magic level
+const OPERATORS: &str = "^$.*[+?={()|<>%";
+
+impl Magic {
+    /// whether `c` is an operator on its own. with a backslash before it, it's the other way round
+    fn is_special(self, c: char) -> bool {
+        match self {
+            Magic::Very => OPERATORS.contains(c),
+            Magic::Normal => "^$.*[".contains(c),
+            Magic::No => "^$".contains(c),
+            Magic::VeryNo => false,
+        }
+    }
+}
+
+/// the named sets of chars that \s, \d and [:alpha:] and the rest stand for
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Named {
+    Space,
+    Digit,
+    Word,
+    Alpha,
+    Alnum,
+    Lower,
+    Upper,
+    Hex,
+    Octal,
+    /// the chars a word can start with, [A-Za-z_]
+    Head,
+    /// the same chars that w and \< count as a word, \k
+    Keyword {
+        digits: bool,
+    },
+    /// the chars of an identifier, \i, which are those of \w and the latin-1 letters
+    Ident {
+        digits: bool,
+    },
+    /// the chars of a file name, \f
+    FileName {
+        digits: bool,
+    },
+    Punct,
+    Cntrl,
+    /// \p and [:print:]
+    Print {
+        digits: bool,
+    },
+}
+
+impl Named {
+    fn matches(self, c: char) -> bool {
+        match self {
+            Named::Space => c == ' ' || c == '\t',
+            Named::Digit => c.is_ascii_digit(),
+            Named::Word => c.is_ascii_alphanumeric() || c == '_',
+            Named::Alpha => c.is_ascii_alphabetic(),
+            Named::Alnum => c.is_ascii_alphanumeric(),
+            Named::Lower => c.is_lowercase(),
+            Named::Upper => c.is_uppercase(),
+            Named::Hex => c.is_ascii_hexdigit(),
+            Named::Octal => ('0'..='7').contains(&c),
+            Named::Head => c.is_ascii_alphabetic() || c == '_',
+            Named::Keyword { digits } => (digits || !c.is_ascii_digit()) && is_keyword(c),
+            Named::Ident { digits } => {
+                (digits || !c.is_ascii_digit())
+                    && (c.is_ascii_alphanumeric() || c == '_' || ('\u{c0}'..='\u{ff}').contains(&c))
+            }
+            Named::FileName { digits } => {
+                (digits || !c.is_ascii_digit())
+                    && (c.is_alphanumeric() || "/.-_+,#$%~=".contains(c))
+            }
+            Named::Punct => c.is_ascii_punctuation(),
+            Named::Cntrl => c.is_control(),
+            Named::Print { digits } => (digits || !c.is_ascii_digit()) && !c.is_control(),
+        }
+    }
+    /// the set behind a backslash class such as \d, and whether it is the upper case one that
+    /// takes everything else. \I, \K, \F and \P are the exception, they are the sets of \i,
+    /// \k, \f and \p less the digits
+    fn from_escape(c: char) -> Option<(Named, bool)> {
+        let digits = c.is_ascii_lowercase();
+        let named = match c.to_ascii_lowercase() {
+            'i' => return Some((Named::Ident { digits }, false)),
+            'k' => return Some((Named::Keyword { digits }, false)),
+            'f' => return Some((Named::FileName { digits }, false)),
+            'p' => return Some((Named::Print { digits }, false)),
+            's' => Named::Space,
+            'd' => Named::Digit,
+            'w' => Named::Word,
+            'a' => Named::Alpha,
+            'l' => Named::Lower,
+            'u' => Named::Upper,
+            'x' => Named::Hex,
+            'o' => Named::Octal,
+            'h' => Named::Head,
+            _ => return None,
+        };
+        // \L and \U aren't upper case \l and \u, they are the chars that aren't lower or upper
+        Some((named, c.is_ascii_uppercase()))
+    }
+    /// the set for [:name:] inside brackets
+    fn from_name(name: &str) -> Option<Named> {
+        let named = match name {
+            "alpha" => Named::Alpha,
+            "alnum" => Named::Alnum,
+            "blank" | "space" => Named::Space,
+            "cntrl" => Named::Cntrl,
+            "digit" => Named::Digit,
+            "lower" => Named::Lower,
+            "upper" => Named::Upper,
+            "print" => Named::Print { digits: true },
+            "punct" => Named::Punct,
+            "xdigit" => Named::Hex,
+            _ => return None,
+        };
+        Some(named)
+    }
+}
+
+fn is_keyword(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum ClassItem {
+    Char(char),
+    Range(char, char),
+    Named(Named),
+}
+
+/// [abc], [^a-z] and the classes like \s, which are kept the same way
+#[derive(Clone, Debug, PartialEq)]
+struct Class {
+    items: Vec<ClassItem>,
+    negated: bool,
+    /// whether the end of a line matches too, as it does for \_s and \_[...]
+    newline: bool,
+}
+
+impl Class {
+    fn named(named: Named, negated: bool, newline: bool) -> Class {
+        Class {
+            items: vec![ClassItem::Named(named)],
+            negated,
+            newline,
+        }
+    }
+    /// whether `c` is in the class. ignoring case only widens the chars and ranges typed, \u
+    /// still means upper case
+    fn matches(&self, c: char, ignore_case: bool) -> bool {
+        let in_item = |item: &ClassItem, c: char| match item {
+            ClassItem::Char(item) => *item == c,
+            ClassItem::Range(from, to) => (*from..=*to).contains(&c),
+            ClassItem::Named(named) => named.matches(c),
+        };
+        let found = self.items.iter().any(|item| {
+            in_item(item, c)
+                || ignore_case
+                    && !matches!(item, ClassItem::Named(_))
+                    && c.to_lowercase()
+                        .chain(c.to_uppercase())
+                        .any(|c| in_item(item, c))
+        });
+        found != self.negated
+    }
+}
+
+/// the pattern once parsed, before it is compiled
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Char(char),
+    Any {
+        newline: bool,
+    },
+    Class(Class),
+    Newline,
+    LineStart,
+    LineEnd,
+    WordStart,
+    WordEnd,
+    /// \zs and \ze, where the match is said to start and end
+    MatchStart,
+    MatchEnd,
+    Backref(usize),
+    /// \(\) with its number, or \%(\) which isn't counted
+    Group {
+        index: Option<usize>,
+        branches: Vec<Vec<Node>>,
+    },
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Atom(Node),
+    /// ^ and $, which are only anchors at the start and the end of a branch
+    Caret,
+    Dollar,
+    /// *, \+, \=, \? and \{}, with the char they were typed as for when they follow nothing
+    Multi {
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+        typed: char,
+    },
+    Open {
+        capture: bool,
+    },
+    Close,
+    Alt,
+}
+
+/// what tokenizing a whole pattern gives, along with what it says about case
+struct Tokens {
+    tokens: Vec<Token>,
+    /// set by \c, cleared by \C
+    ignore_case: Option<bool>,
+    /// whether an upper case letter was typed, which turns ignorecase off with smartcase
+    has_upper: bool,
+}
+
+fn tokenize(pattern: &str) -> Result<Tokens, String> {
+    let mut chars = pattern.chars().peekable();
+    let mut magic = Magic::Normal;
+    let mut tokens = Vec::new();
+    let mut ignore_case = None;
+    let mut has_upper = false;
+    while let Some(c) = chars.next() {
+        let (c, escaped) = match c {
+            '\\' => match chars.next() {
+                Some(c) => (c, true),
+                // a backslash at the very end stands for itself
+                None => ('\\', false),
+            },
+            c => (c, false),
+        };
+        if OPERATORS.contains(c) && magic.is_special(c) != escaped {
+            tokens.push(operator(c, &mut chars, &mut has_upper)?);
+            continue;
+        }
+        if !escaped {
+            has_upper |= c.is_uppercase();
+            tokens.push(Token::Atom(Node::Char(c)));
+            continue;
+        }
+        let node = match c {
+            'v' | 'm' | 'M' | 'V' => {
+                magic = match c {
+                    'v' => Magic::Very,
+                    'm' => Magic::Normal,
+                    'M' => Magic::No,
+                    _ => Magic::VeryNo,
+                };
+                continue;
+            }
+            'c' | 'C' => {
+                ignore_case = Some(c == 'c');
+                continue;
+            }
+            'n' => Node::Newline,
+            't' => Node::Char('\t'),
+            'e' => Node::Char('\x1b'),
+            'r' => Node::Char('\r'),
+            '1'..='9' => Node::Backref(c.to_digit(10).unwrap_or(1) as usize),
+            'z' => match chars.next() {
+                Some('s') => Node::MatchStart,
+                Some('e') => Node::MatchEnd,
+                _ => return Err(String::from("E68: Invalid character after \\z")),
+            },
+            '_' => match chars.next() {
+                Some('.') => Node::Any { newline: true },
+                Some('^') => Node::LineStart,
+                Some('$') => Node::LineEnd,
+                Some('[') => match bracket(&mut chars, true, &mut has_upper) {
+                    Some(class) => Node::Class(class),
+                    None => return Err(String::from("E769: Missing ] after \\_[")),
+                },
+                Some(c) => match Named::from_escape(c) {
+                    Some((named, negated)) => Node::Class(Class::named(named, negated, true)),
+                    None => return Err(String::from("E63: Invalid use of \\_")),
+                },
+                None => return Err(String::from("E63: Invalid use of \\_")),
+            },
+            c => match Named::from_escape(c) {
+                Some((named, negated)) => Node::Class(Class::named(named, negated, false)),
+                None => Node::Char(c),
+            },
+        };
+        tokens.push(Token::Atom(node));
+    }
+    Ok(Tokens {
+        tokens,
+        ignore_case,
+        has_upper,
+    })
+}
+
+/// the token for an operator char `c`, reading whatever it takes after it
+fn operator(c: char, chars: &mut Peekable<Chars>, has_upper: &mut bool) -> Result<Token, String> {
+    let multi = |min, max| Token::Multi {
+        min,
+        max,
+        greedy: true,
+        typed: c,
+    };
+    let token = match c {
+        '^' => Token::Caret,
+        '$' => Token::Dollar,
+        '.' => Token::Atom(Node::Any { newline: false }),
+        '*' => multi(0, None),
+        '+' => multi(1, None),
+        '?' | '=' => multi(0, Some(1)),
+        '{' => brace(chars)?,
+        '(' => Token::Open { capture: true },
+        ')' => Token::Close,
+        '|' => Token::Alt,
+        '<' => Token::Atom(Node::WordStart),
+        '>' => Token::Atom(Node::WordEnd),
+        '%' => match chars.next() {
+            Some('(') => Token::Open { capture: false },
+            _ => return Err(String::from("E71: Invalid character after \\%")),
+        },
+        // a [ without its ] is just a [
+        _ => match bracket(chars, false, has_upper) {
+            Some(class) => Token::Atom(Node::Class(class)),
+            None => Token::Atom(Node::Char('[')),
+        },
+    };
+    Ok(token)
+}
+
+/// the counts of \{n,m}, read after the {. a - first makes it match as few as it can
+fn brace(chars: &mut Peekable<Chars>) -> Result<Token, String> {
+    let mut inside = String::new();
+    loop {
+        match chars.next() {
+            Some('}') => break,
+            Some('\\') if chars.peek() == Some(&'}') => {
+                chars.next();
+                break;
+            }
+            Some(c) => inside.push(c),
+            None => return Err(String::from("E554: Syntax error in \\{...}")),
+        }
+    }
+    let syntax = || String::from("E554: Syntax error in \\{...}");
+    let (greedy, counts) = match inside.strip_prefix('-') {
+        Some(counts) => (false, counts),
+        None => (true, inside.as_str()),
+    };
+    let number = |text: &str| match text {
+        "" => Ok(None),
+        text => text.parse().map(Some).map_err(|_| syntax()),
+    };
+    let (min, max) = match counts.split_once(',') {
+        Some((min, max)) => (number(min)?.unwrap_or(0), number(max)?),
+        None => match number(counts)? {
+            Some(exactly) => (exactly, Some(exactly)),
+            None => (0, None),
+        },
+    };
+    // vim takes \{3,1} to mean the same as \{1,3}
+    let (min, max) = match max {
+        Some(max) if max < min => (max, Some(min)),
+        max => (min, max),
+    };
+    Ok(Token::Multi {
+        min,
+        max,
+        greedy,
+        typed: '{',
+    })
+}
+
+/// the class of a [...], read after the [. None when there is no ] to end it, leaving `chars`
+/// where it was
+fn bracket(chars: &mut Peekable<Chars>, newline: bool, has_upper: &mut bool) -> Option<Class> {
+    let mut ahead = chars.clone();
+    let negated = ahead.next_if_eq(&'^').is_some();
+    let mut items = Vec::new();
+    let mut first = true;
+    loop {
+        let c = match ahead.next()? {
+            ']' if !first => break,
+            '[' if ahead.peek() == Some(&':') => {
+                let rest: String = ahead.clone().collect();
+                let named = rest[1..]
+                    .split_once(":]")
+                    .and_then(|(name, _)| Some((name.len(), Named::from_name(name)?)));
+                match named {
+                    Some((len, named)) => {
+                        // past the :name:]
+                        for _ in 0..len + 3 {
+                            ahead.next();
+                        }
+                        items.push(ClassItem::Named(named));
+                        first = false;
+                        continue;
+                    }
+                    None => '[',
+                }
+            }
+            '\\' => match ahead.next()? {
+                'e' => '\x1b',
+                't' => '\t',
+                'r' => '\r',
+                'n' => '\n',
+                c @ ('\\' | ']' | '^' | '-') => c,
+                // any other backslash is taken as it is, followed by the next char on its own
+                c => {
+                    items.push(ClassItem::Char('\\'));
+                    c
+                }
+            },
+            c => c,
+        };
+        first = false;
+        *has_upper |= c.is_uppercase();
+        let is_range = ahead.peek() == Some(&'-') && ahead.clone().nth(1).is_some_and(|c| c != ']');
+        if is_range {
+            ahead.next();
+            let to = ahead.next()?;
+            *has_upper |= to.is_uppercase();
+            items.push(ClassItem::Range(c, to));
+        } else {
+            items.push(ClassItem::Char(c));
+        }
+    }
+    *chars = ahead;
+    // a newline typed in the brackets works like \_[ does
+    let newline = newline || items.contains(&ClassItem::Char('\n'));
+    Some(Class {
+        items,
+        negated,
+        newline,
+    })
+}
+
+/// turns the tokens into the branches of the whole pattern
+struct Parser {
+    tokens: Vec<Token>,
+    at: usize,
+    groups: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at)
+    }
+    /// the branches separated by \| up to a \) or the end of the pattern
+    fn branches(&mut self) -> Result<Vec<Vec<Node>>, String> {
+        let mut branches = vec![self.branch()?];
+        while self.peek() == Some(&Token::Alt) {
+            self.at += 1;
+            branches.push(self.branch()?);
+        }
+        Ok(branches)
+    }
+    fn branch(&mut self) -> Result<Vec<Node>, String> {
+        let mut nodes: Vec<Node> = Vec::new();
+        while let Some(token) = self.peek().cloned() {
+            self.at += 1;
+            let node = match token {
+                Token::Alt | Token::Close => {
+                    self.at -= 1;
+                    break;
+                }
+                Token::Atom(Node::Backref(n)) if n > self.groups => {
+                    return Err(String::from("E65: Illegal back reference"))
+                }
+                Token::Atom(node) => node,
+                Token::Caret if nodes.is_empty() => Node::LineStart,
+                Token::Caret => Node::Char('^'),
+                Token::Dollar => match self.peek() {
+                    None | Some(Token::Alt | Token::Close) => Node::LineEnd,
+                    Some(_) => Node::Char('$'),
+                },
+                Token::Multi {
+                    min,
+                    max,
+                    greedy,
+                    typed,
+                } => match nodes.pop() {
+                    Some(Node::Repeat { .. }) => {
+                        return Err(format!("E61: Nested {typed}"));
+                    }
+                    Some(node) => Node::Repeat {
+                        node: Box::new(node),
+                        min,
+                        max,
+                        greedy,
+                    },
+                    // a multi with nothing before it is taken as the char it was typed as
+                    None => Node::Char(typed),
+                },
+                Token::Open { capture } => {
+                    let index = match capture {
+                        true if self.groups == 9 => return Err(String::from("E51: Too many \\(")),
+                        true => {
+                            self.groups += 1;
+                            Some(self.groups)
+                        }
+                        false => None,
+                    };
+                    let branches = self.branches()?;
+                    if self.peek() != Some(&Token::Close) {
+                        return Err(match capture {
+                            true => String::from("E54: Unmatched \\("),
+                            false => String::from("E53: Unmatched \\%("),
+                        });
+                    }
+                    self.at += 1;
+                    Node::Group { index, branches }
+                }
+            };
+            nodes.push(node);
+        }
+        Ok(nodes)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Inst {
+    Char(char),
+    Any {
+        newline: bool,
+    },
+    Class(Class),
+    Newline,
+    LineStart,
+    LineEnd,
+    WordStart,
+    WordEnd,
+    /// keeps the position in a slot. slots 0 and 1 are where the match starts and ends, 2n and
+    /// 2n + 1 where group n does, and the ones after that are for Progressed
+    Save(usize),
+    /// carries on at the first, coming back to try the second if that fails
+    Split(usize, usize),
+    Jump(usize),
+    /// fails when the position is the one saved in the slot, so a repeat of something that can
+    /// match nothing doesn't go round forever
+    Progressed(usize),
+    Backref(usize),
+    Match,
+}
+
+struct Compiler {
+    program: Vec<Inst>,
+    slots: usize,
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> usize {
+        self.program.push(inst);
+        self.program.len() - 1
+    }
+    fn branches(&mut self, branches: &[Vec<Node>]) -> Result<(), String> {
+        let mut jumps = Vec::new();
+        for (i, branch) in branches.iter().enumerate() {
+            let split = (i + 1 < branches.len()).then(|| self.push(Inst::Split(0, 0)));
+            for node in branch {
+                self.node(node)?;
+            }
+            if let Some(split) = split {
+                jumps.push(self.push(Inst::Jump(0)));
+                self.program[split] = Inst::Split(split + 1, self.program.len());
+            }
+        }
+        let end = self.program.len();
+        for jump in jumps {
+            self.program[jump] = Inst::Jump(end);
+        }
+        Ok(())
+    }
+    fn node(&mut self, node: &Node) -> Result<(), String> {
+        if self.program.len() > MAX_PROGRAM {
+            return Err(String::from(
+                "E363: pattern uses more memory than 'maxmempattern'",
+            ));
+        }
+        let inst = match node {
+            Node::Char(c) => Inst::Char(*c),
+            Node::Any { newline } => Inst::Any { newline: *newline },
+            Node::Class(class) => Inst::Class(class.clone()),
+            Node::Newline => Inst::Newline,
+            Node::LineStart => Inst::LineStart,
+            Node::LineEnd => Inst::LineEnd,
+            Node::WordStart => Inst::WordStart,
+            Node::WordEnd => Inst::WordEnd,
+            Node::MatchStart => Inst::Save(0),
+            Node::MatchEnd => Inst::Save(1),
+            Node::Backref(n) => Inst::Backref(*n),
+            Node::Group { index, branches } => {
+                if let Some(index) = index {
+                    self.push(Inst::Save(index * 2));
+                }
+                self.branches(branches)?;
+                if let Some(index) = index {
+                    self.push(Inst::Save(index * 2 + 1));
+                }
+                return Ok(());
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => return self.repeat(node, *min, *max, *greedy),
+        };
+        self.push(inst);
+        Ok(())
+    }
+    /// `node` at least `min` times, then as often again as `max` allows, or any number of times
+    /// without one
+    fn repeat(
+        &mut self,
+        node: &Node,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    ) -> Result<(), String> {
+        for _ in 0..min {
+            self.node(node)?;
+        }
+        let split = |at: usize, end: usize| match greedy {
+            true => Inst::Split(at + 1, end),
+            false => Inst::Split(end, at + 1),
+        };
+        match max {
+            None => {
+                let slot = self.slots;
+                self.slots += 1;
+                let start = self.push(Inst::Split(0, 0));
+                self.push(Inst::Save(slot));
+                self.node(node)?;
+                self.push(Inst::Progressed(slot));
+                self.push(Inst::Jump(start));
+                self.program[start] = split(start, self.program.len());
+            }
+            Some(max) => {
+                let splits: Vec<usize> = (min..max)
+                    .map(|_| {
+                        let at = self.push(Inst::Split(0, 0));
+                        self.node(node).map(|_| at)
+                    })
+                    .collect::<Result<_, _>>()?;
+                let end = self.program.len();
+                for at in splits {
+                    self.program[at] = split(at, end);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// where a pattern matched, 0-indexed. `end` is one past the last char matched, so a match that
+/// takes in the newline of a line ends at the start of the next one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Match {
+    pub start: Position,
+    pub end: Position,
+    /// the start and end of what \( \) groups 1 to 9 matched. 0 is the whole match
+    pub groups: [Option<(Position, Position)>; 10],
+}
+
+impl Match {
+    /// the chars matched, None for a match of nothing at all
+    pub fn range(&self, content_buffer: &GapBuffer<GapBuffer<char>>) -> Option<TextRange> {
+        if self.end <= self.start {
+            return None;
+        }
+        Some(TextRange {
+            kind: RangeKind::Char,
+            start: self.start,
+            end: content_buffer.prev_position(self.end)?,
+        })
+    }
+}
+
+/// the content buffer as the matcher reads it, a char at a time straight out of the lines
+struct Text<'a> {
+    content_buffer: &'a GapBuffer<GapBuffer<char>>,
+}
+
+impl Text<'_> {
+    /// the length of `line` without its newline
+    fn line_len(&self, line: usize) -> usize {
+        match self.content_buffer.get(line) {
+            Some(line_buf) => {
+                let len = line_buf.get_filled_items();
+                match line_buf.get(len.wrapping_sub(1)) {
+                    Some('\n') => len - 1,
+                    _ => len,
+                }
+            }
+            None => 0,
+        }
+    }
+    /// the char at `at` and the position after it. the newline at the end of a line is only
+    /// there when another line follows it
+    fn step(&self, (line, col): Position) -> Option<(char, Position)> {
+        if col < self.line_len(line) {
+            let c = self.content_buffer.char_at((line, col))?;
+            Some((c, (line, col + 1)))
+        } else if line + 1 < self.content_buffer.get_filled_items() {
+            Some(('\n', (line + 1, 0)))
+        } else {
+            None
+        }
+    }
+    /// the char on the line before `at`, None at the start of a line
+    fn before(&self, (line, col): Position) -> Option<char> {
+        let col = col.checked_sub(1)?;
+        self.content_buffer.char_at((line, col))
+    }
+    fn after(&self, at: Position) -> Option<char> {
+        self.step(at).map(|(c, _)| c).filter(|c| *c != '\n')
+    }
+}
+
+/// the place to carry on from when the path being tried fails
+enum Job {
+    Try(usize, Position),
+    /// puts a slot back as it was before the failed path saved over it
+    Restore(usize, Option<Position>),
+}
+
+/// a compiled pattern in vim's syntax, matched against the content buffer where it is
+#[derive(Clone, Debug, PartialEq)]
+pub struct Regex {
+    program: Vec<Inst>,
+    slots: usize,
+    ignore_case: bool,
+}
+
+impl Regex {
+    /// compiles `pattern`. \c and \C in it win over `ignorecase`, and with `smartcase` an
+    /// upper case letter in the pattern turns `ignorecase` off
+    pub fn new(pattern: &str, ignorecase: bool, smartcase: bool) -> Result<Regex, String> {
+        let tokens = tokenize(pattern)?;
+        let ignore_case = tokens
+            .ignore_case
+            .unwrap_or(ignorecase && !(smartcase && tokens.has_upper));
+        let mut parser = Parser {
+            tokens: tokens.tokens,
+            at: 0,
+            groups: 0,
+        };
+        let branches = parser.branches()?;
+        if parser.peek() == Some(&Token::Close) {
+            return Err(String::from("E55: Unmatched \\)"));
+        }
+        let mut compiler = Compiler {
+            program: vec![Inst::Save(0)],
+            slots: 20,
+        };
+        compiler.branches(&branches)?;
+        compiler.push(Inst::Match);
+        Ok(Regex {
+            program: compiler.program,
+            slots: compiler.slots,
+            ignore_case,
+        })
+    }
+
+    fn same(&self, a: char, b: char) -> bool {
+        a == b || self.ignore_case && a.to_lowercase().eq(b.to_lowercase())
+    }
+
+    /// the match that the pattern makes starting right at `at`, trying the alternatives in
+    /// order and taking the first that works the way vim does
+    pub fn match_at(
+        &self,
+        content_buffer: &GapBuffer<GapBuffer<char>>,
+        at: Position,
+    ) -> Option<Match> {
+        let text = Text { content_buffer };
+        let mut slots: Vec<Option<Position>> = vec![None; self.slots];
+        let mut jobs = vec![Job::Try(0, at)];
+        while let Some(job) = jobs.pop() {
+            let (mut pc, mut at) = match job {
+                Job::Restore(slot, old) => {
+                    slots[slot] = old;
+                    continue;
+                }
+                Job::Try(pc, at) => (pc, at),
+            };
+            loop {
+                let next = match &self.program[pc] {
+                    Inst::Char(c) => match text.step(at) {
+                        Some((found, after)) if found != '\n' && self.same(*c, found) => {
+                            Some(after)
+                        }
+                        _ => None,
+                    },
+                    Inst::Any { newline } => match text.step(at) {
+                        Some(('\n', _)) if !newline => None,
+                        step => step.map(|(_, after)| after),
+                    },
+                    Inst::Class(class) => match text.step(at) {
+                        Some(('\n', after)) => class.newline.then_some(after),
+                        Some((c, after)) => class.matches(c, self.ignore_case).then_some(after),
+                        None => None,
+                    },
+                    Inst::Newline => match text.step(at) {
+                        Some(('\n', after)) => Some(after),
+                        _ => None,
+                    },
+                    Inst::LineStart => (at.1 == 0).then_some(at),
+                    Inst::LineEnd => (at.1 >= text.line_len(at.0)).then_some(at),
+                    Inst::WordStart => {
+                        let starts = text.after(at).is_some_and(is_keyword)
+                            && !text.before(at).is_some_and(is_keyword);
+                        starts.then_some(at)
+                    }
+                    Inst::WordEnd => {
+                        let ends = text.before(at).is_some_and(is_keyword)
+                            && !text.after(at).is_some_and(is_keyword);
+                        ends.then_some(at)
+                    }
+                    Inst::Save(slot) => {
+                        jobs.push(Job::Restore(*slot, slots[*slot]));
+                        slots[*slot] = Some(at);
+                        Some(at)
+                    }
+                    Inst::Split(first, second) => {
+                        jobs.push(Job::Try(*second, at));
+                        pc = *first;
+                        continue;
+                    }
+                    Inst::Jump(to) => {
+                        pc = *to;
+                        continue;
+                    }
+                    Inst::Progressed(slot) => (slots[*slot] != Some(at)).then_some(at),
+                    Inst::Backref(n) => self.backref(&text, &slots, *n, at),
+                    Inst::Match => return Some(self.found(&slots, at)),
+                };
+                match next {
+                    Some(next) => {
+                        at = next;
+                        pc += 1;
+                    }
+                    None => break,
+                }
+            }
+        }
+        None
+    }
+
+    /// where \n matching the text group `n` matched again would end, if it does. a group that
+    /// didn't match anything matches nothing
+    fn backref(
+        &self,
+        text: &Text,
+        slots: &[Option<Position>],
+        n: usize,
+        mut at: Position,
+    ) -> Option<Position> {
+        let (mut from, to) = match (slots[n * 2], slots[n * 2 + 1]) {
+            (Some(from), Some(to)) => (from, to),
+            _ => return Some(at),
+        };
+        while from < to {
+            let (want, after_group) = text.step(from)?;
+            let (found, after) = text.step(at)?;
+            if !self.same(want, found) {
+                return None;
+            }
+            from = after_group;
+            at = after;
+        }
+        Some(at)
+    }
+
+    fn found(&self, slots: &[Option<Position>], at: Position) -> Match {
+        let start = slots[0].unwrap_or(at);
+        // \ze before \zs makes a match of nothing
+        let end = slots[1].unwrap_or(at).max(start);
+        let mut groups = [None; 10];
+        groups[0] = Some((start, end));
+        for (n, group) in groups.iter_mut().enumerate().skip(1) {
+            if let (Some(from), Some(to)) = (slots[n * 2], slots[n * 2 + 1]) {
+                *group = Some((from, to.max(from)));
+            }
+        }
+        Match { start, end, groups }
+    }
+
+    /// the first match starting on the line of `from`, at `from` or after it
+    pub fn next_match(
+        &self,
+        content_buffer: &GapBuffer<GapBuffer<char>>,
+        (line, col): Position,
+    ) -> Option<Match> {
+        let len = Text { content_buffer }.line_len(line);
+        (col..=len).find_map(|col| self.match_at(content_buffer, (line, col)))
+    }
+
+    /// every match that starts on `line`, left to right. each one is looked for after the last
+    /// one ends, or after the col it started at for a match of nothing
+    pub fn line_matches(
+        &self,
+        content_buffer: &GapBuffer<GapBuffer<char>>,
+        line: usize,
+    ) -> Vec<Match> {
+        let mut matches = Vec::new();
+        let mut col = 0;
+        while let Some(found) = self.next_match(content_buffer, (line, col)) {
+            matches.push(found);
+            // a match that runs on to the next line takes the rest of this one with it
+            if found.end.0 > line {
+                break;
+            }
+            let after = match found.end > found.start {
+                true => found.end.1,
+                false => found.start.1 + 1,
+            };
+            col = after.max(col + 1);
+        }
+        matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the text of every match of `pattern` in `text`
+    fn found(text: &str, pattern: &str) -> Vec<String> {
+        let content_buffer = GapBuffer::build_nested(text);
+        let regex = Regex::new(pattern, false, false).unwrap();
+        let lines: Vec<Vec<char>> = text.lines().map(|line| line.chars().collect()).collect();
+        (0..content_buffer.get_filled_items())
+            .flat_map(|line| regex.line_matches(&content_buffer, line))
+            .map(|found| {
+                let mut matched = String::new();
+                let mut at = found.start;
+                while at < found.end {
+                    match lines[at.0].get(at.1) {
+                        Some(c) => {
+                            matched.push(*c);
+                            at.1 += 1;
+                        }
+                        None => {
+                            matched.push('\n');
+                            at = (at.0 + 1, 0);
+                        }
+                    }
+                }
+                matched
+            })
+            .collect()
+    }
+
+    #[test]
+    fn magic_levels() {
+        assert_eq!(found("a.c abc", "a.c"), ["a.c", "abc"]);
+        assert_eq!(found("a.c abc", "a\\.c"), ["a.c"]);
+        assert_eq!(found("a.c abc", "\\Va.c"), ["a.c"]);
+        assert_eq!(found("a.c abc", "\\Va\\.c"), ["a.c", "abc"]);
+        assert_eq!(found("foobar foofoo", "\\v(foo)+"), ["foo", "foofoo"]);
+        assert_eq!(found("foobar foofoo", "\\(foo\\)\\+"), ["foo", "foofoo"]);
+        assert_eq!(found("a+ aa", "a+"), ["a+"]);
+        assert_eq!(found("cat dog cow", "\\vc(at|ow)"), ["cat", "cow"]);
+        assert_eq!(found("x*y", "*"), ["*"]);
+        assert_eq!(found("ab$ ab", "ab$"), ["ab"]);
+        assert_eq!(found("^ab", "^ab"), Vec::<String>::new());
+        assert_eq!(found("a\\b a", "a\\"), ["a\\"]);
+    }
+
+    #[test]
+    fn classes_and_counts() {
+        assert_eq!(found("a1 b22 c333", "\\d\\{2}"), ["22", "33"]);
+        assert_eq!(found("a1 b22 c333", "\\a\\d\\{2,}"), ["b22", "c333"]);
+        assert_eq!(found("aaaa", "a\\{-1,}"), ["a", "a", "a", "a"]);
+        assert_eq!(found("x-y_z", "[-_]"), ["-", "_"]);
+        assert_eq!(found("abc ABC", "[^a-c ]\\+"), ["ABC"]);
+        assert_eq!(found("ab1 c2", "[[:alpha:]]\\+\\d"), ["ab1", "c2"]);
+        assert_eq!(found("foo  bar", "\\s\\+"), ["  "]);
+        assert_eq!(found("one two", "\\<\\w"), ["o", "t"]);
+        assert_eq!(found("a1 _2 3", "\\K\\k*"), ["a1", "_2"]);
+        assert_eq!(found("x.y-1 (z)", "\\f\\+"), ["x.y-1", "z"]);
+        assert_eq!(found("é9 a", "\\I\\i"), ["é9"]);
+        assert_eq!(found("ab1", "\\P\\+"), ["ab"]);
+        assert!(Regex::new("a\\{1,x}", false, false).is_err());
+        assert!(Regex::new("\\(a", false, false).is_err());
+        assert!(Regex::new("a\\)", false, false).is_err());
+    }
+
+    #[test]
+    fn across_lines() {
+        assert_eq!(found("foo\nbar\n", "o\\nb"), ["o\nb"]);
+        assert_eq!(found("foo\nbar\n", "o\\_s*bar"), ["o\nbar"]);
+        assert_eq!(found("a\nb\nc\n", "a\\_.*c"), ["a\nb\nc"]);
+        assert_eq!(found("a\nb\n", "b\\n"), Vec::<String>::new());
+
+        let content_buffer = GapBuffer::build_nested("foo\nbar\n");
+        let regex = Regex::new("o\\nba", false, false).unwrap();
+        let found = regex.line_matches(&content_buffer, 0)[0];
+        assert_eq!((found.start, found.end), ((0, 2), (1, 2)));
+        let range = found.range(&content_buffer).unwrap();
+        assert_eq!((range.start, range.end), ((0, 2), (1, 1)));
+    }
+
+    #[test]
+    fn match_start_end_and_groups() {
+        assert_eq!(found("foobar foobaz", "foo\\zsba."), ["bar", "baz"]);
+        assert_eq!(found("foobar foobaz", "foo\\zebaz"), ["foo"]);
+        assert_eq!(found("abab cdcd abcd", "\\(..\\)\\1"), ["abab", "cdcd"]);
+
+        let content_buffer = GapBuffer::build_nested("key = value\n");
+        let regex = Regex::new("\\(\\w\\+\\) = \\(\\w\\+\\)", false, false).unwrap();
+        let found = regex.match_at(&content_buffer, (0, 0)).unwrap();
+        assert_eq!(found.groups[1], Some(((0, 0), (0, 3))));
+        assert_eq!(found.groups[2], Some(((0, 6), (0, 11))));
+        assert_eq!(found.groups[3], None);
+    }
+
+    #[test]
+    fn case() {
+        let content_buffer = GapBuffer::build_nested("Foo foo FOO\n");
+        let count = |pattern: &str, ignorecase, smartcase| {
+            Regex::new(pattern, ignorecase, smartcase)
+                .unwrap()
+                .line_matches(&content_buffer, 0)
+                .len()
+        };
+        assert_eq!(count("foo", false, false), 1);
+        assert_eq!(count("foo", true, false), 3);
+        assert_eq!(count("foo\\c", false, false), 3);
+        assert_eq!(count("\\Cfoo", true, false), 1);
+        assert_eq!(count("foo", true, true), 3);
+        assert_eq!(count("Foo", true, true), 1);
+        assert_eq!(count("Foo", true, false), 3);
+    }
+}
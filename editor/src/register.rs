@@ -0,0 +1,299 @@
+use crate::ops::RangeKind;
+use std::collections::HashMap;
+
+/// text that was yanked or deleted, along with its shape so a put knows whether to insert it
+/// into the line, as lines of its own or as a block
+#[derive(Clone, Debug, PartialEq)]
+pub struct Register {
+    pub text: Vec<String>,
+    pub kind: RangeKind,
+}
+
+impl Register {
+    /// text typed or run rather than yanked, which is always put into the line
+    pub fn chars(text: &str) -> Register {
+        Register {
+            text: text.split('\n').map(String::from).collect(),
+            kind: RangeKind::Char,
+        }
+    }
+
+    /// the text `count` times over, for 3p. lines follow each other, chars run on from the end
+    /// of the last copy and the rows of a block are repeated side by side
+    pub fn repeated(&self, count: usize) -> Register {
+        let mut repeated = self.clone();
+        for copies in 1..count {
+            repeated = match self.kind {
+                RangeKind::Block => {
+                    let width = self.width() * copies;
+                    let text = repeated
+                        .text
+                        .iter()
+                        .zip(&self.text)
+                        .map(|(row, piece)| format!("{row:<width$}{piece}"))
+                        .collect();
+                    Register {
+                        text,
+                        kind: RangeKind::Block,
+                    }
+                }
+                _ => repeated.appended(self.clone()),
+            };
+        }
+        repeated
+    }
+
+    /// `other` added to the end, for "Ayw and the like. appending whole lines to text that isn't,
+    /// or the other way around, makes lines of the lot
+    fn appended(mut self, other: Register) -> Register {
+        match (self.kind, other.kind) {
+            (RangeKind::Char, RangeKind::Char) => {
+                let mut rest = other.text.into_iter();
+                if let (Some(last), Some(first)) = (self.text.last_mut(), rest.next()) {
+                    last.push_str(&first);
+                }
+                self.text.extend(rest);
+            }
+            (RangeKind::Line, _) | (_, RangeKind::Line) => {
+                self.text.extend(other.text);
+                self.kind = RangeKind::Line;
+            }
+            _ => self.text.extend(other.text),
+        }
+        self
+    }
+
+    /// the widest row of a block
+    pub fn width(&self) -> usize {
+        self.text
+            .iter()
+            .map(|s| s.chars().count())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// where yanks and deletes end up, the same registers as vim has:
+///
+/// "" whichever register was written last, what p and P put when no register is named
+/// "0 the last yank, and "1 to "9 the last deletes of a line or more, newest first
+/// "- the last delete within a line
+/// "a to "z named with "x before the command, and appended to by naming them in uppercase
+/// ". ": the last text typed in insert mode and the last command line, which can't be written
+/// "+ "* the system clipboard and primary selection, a copy of what was last sent there
+/// "_ throws away whatever is written to it
+///
+/// "% for the file name isn't kept here since it's always that of the buffer
+pub struct Registers {
+    unnamed: Option<Register>,
+    numbered: [Option<Register>; 10],
+    small_delete: Option<Register>,
+    named: HashMap<char, Register>,
+    clipboard: HashMap<char, Register>,
+    pub last_insert: Option<String>,
+    pub last_command: Option<String>,
+}
+
+impl Default for Registers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Registers {
+    pub fn new() -> Registers {
+        Registers {
+            unnamed: None,
+            numbered: Default::default(),
+            small_delete: None,
+            named: HashMap::new(),
+            clipboard: HashMap::new(),
+            last_insert: None,
+            last_command: None,
+        }
+    }
+
+    /// whether a yank or a delete can go to the register called `name`
+    pub fn is_writable(name: Option<char>) -> bool {
+        match name {
+            None => true,
+            Some(name) => {
+                name.is_ascii_alphanumeric() || matches!(name, '"' | '-' | '_' | '+' | '*')
+            }
+        }
+    }
+
+    /// a yank goes to "0 as well, unless some other register was named for it
+    pub fn yank(&mut self, name: Option<char>, register: Register) {
+        if self.write(name, register.clone()) {
+            self.numbered[0] = Some(register);
+        }
+    }
+
+    /// a delete of a line or more pushes the older ones down through "1 to "9, even when it
+    /// also goes to a named register. a smaller one goes to "- instead, unless a register was
+    /// named for it
+    pub fn delete(&mut self, name: Option<char>, register: Register) {
+        if name == Some('_') {
+            return;
+        }
+        let small = register.kind == RangeKind::Char && register.text.len() == 1;
+        let unnamed = self.write(name, register.clone());
+        if !small {
+            self.numbered[1..].rotate_right(1);
+            self.numbered[1] = Some(register);
+        } else if unnamed {
+            self.small_delete = Some(register);
+        }
+    }
+
+    /// writes `register` to the one called `name` and to the unnamed one, or to nothing at all
+    /// for "_. true when no register was named
+    fn write(&mut self, name: Option<char>, register: Register) -> bool {
+        let register = match name {
+            None | Some('"') => {
+                self.unnamed = Some(register);
+                return true;
+            }
+            Some('a'..='z') => register,
+            Some(name @ 'A'..='Z') => match self.named.remove(&name.to_ascii_lowercase()) {
+                Some(old) => old.appended(register),
+                None => register,
+            },
+            Some(digit @ '0'..='9') => {
+                let index = digit.to_digit(10).unwrap_or(0) as usize;
+                self.numbered[index] = Some(register.clone());
+                register
+            }
+            Some('-') => {
+                self.small_delete = Some(register.clone());
+                register
+            }
+            Some(name @ ('+' | '*')) => {
+                self.clipboard.insert(name, register.clone());
+                register
+            }
+            Some(_) => return false,
+        };
+        if let Some(name @ ('a'..='z' | 'A'..='Z')) = name {
+            self.named
+                .insert(name.to_ascii_lowercase(), register.clone());
+        }
+        self.unnamed = Some(register);
+        false
+    }
+
+    /// keys recorded with q{reg} go to that register alone, the unnamed one keeps what was last
+    /// yanked or deleted. an uppercase name adds them to the end of the lowercase register
+    pub fn record(&mut self, name: char, register: Register) {
+        match name {
+            'a'..='z' => {
+                self.named.insert(name, register);
+            }
+            'A'..='Z' => {
+                let name = name.to_ascii_lowercase();
+                let register = match self.named.remove(&name) {
+                    Some(old) => old.appended(register),
+                    None => register,
+                };
+                self.named.insert(name, register);
+            }
+            '0'..='9' => {
+                self.numbered[name.to_digit(10).unwrap_or(0) as usize] = Some(register);
+            }
+            _ => self.unnamed = Some(register),
+        }
+    }
+
+    /// the register called `name`, or the unnamed one for None or "
+    pub fn get(&self, name: Option<char>) -> Option<Register> {
+        match name {
+            None | Some('"') => self.unnamed.clone(),
+            Some(name @ ('a'..='z' | 'A'..='Z')) => {
+                self.named.get(&name.to_ascii_lowercase()).cloned()
+            }
+            Some(digit @ '0'..='9') => {
+                self.numbered[digit.to_digit(10).unwrap_or(0) as usize].clone()
+            }
+            Some('-') => self.small_delete.clone(),
+            Some(name @ ('+' | '*')) => self.clipboard.get(&name).cloned(),
+            Some('.') => self.last_insert.as_deref().map(Register::chars),
+            Some(':') => self.last_command.as_deref().map(Register::chars),
+            Some(_) => None,
+        }
+    }
+
+    /// every register with something in it, in the order :registers lists them
+    pub fn list(&self) -> Vec<(char, Register)> {
+        let names = ['"']
+            .into_iter()
+            .chain('0'..='9')
+            .chain('a'..='z')
+            .chain(['-', '*', '+', '.', ':']);
+        names
+            .filter_map(|name| self.get(Some(name)).map(|register| (name, register)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(text: &str) -> Register {
+        Register::chars(text)
+    }
+
+    fn lines(text: &[&str]) -> Register {
+        Register {
+            text: text.iter().map(|s| String::from(*s)).collect(),
+            kind: RangeKind::Line,
+        }
+    }
+
+    #[test]
+    fn deletes_shift_through_the_numbered_registers() {
+        let mut registers = Registers::new();
+        registers.yank(None, chars("yanked"));
+        registers.delete(None, lines(&["first"]));
+        registers.delete(None, chars("word"));
+        registers.delete(None, lines(&["second"]));
+
+        assert_eq!(registers.get(Some('0')), Some(chars("yanked")));
+        assert_eq!(registers.get(Some('1')), Some(lines(&["second"])));
+        assert_eq!(registers.get(Some('2')), Some(lines(&["first"])));
+        assert_eq!(registers.get(Some('-')), Some(chars("word")));
+        assert_eq!(registers.get(None), Some(lines(&["second"])));
+
+        // the black hole leaves everything as it was
+        registers.delete(Some('_'), lines(&["gone"]));
+        assert_eq!(registers.get(None), Some(lines(&["second"])));
+        assert_eq!(registers.get(Some('1')), Some(lines(&["second"])));
+    }
+
+    #[test]
+    fn named_registers_and_appending() {
+        let mut registers = Registers::new();
+        registers.yank(Some('a'), chars("foo"));
+        registers.yank(Some('A'), chars("bar"));
+        assert_eq!(registers.get(Some('a')), Some(chars("foobar")));
+        assert_eq!(registers.get(Some('0')), None);
+
+        registers.delete(Some('A'), lines(&["line"]));
+        assert_eq!(registers.get(Some('a')), Some(lines(&["foobar", "line"])));
+        assert_eq!(registers.get(Some('1')), Some(lines(&["line"])));
+        assert_eq!(registers.get(None), registers.get(Some('a')));
+        assert!(!Registers::is_writable(Some('%')));
+    }
+
+    #[test]
+    fn repeated_text() {
+        assert_eq!(chars("ab").repeated(3), chars("ababab"));
+        assert_eq!(lines(&["x"]).repeated(2), lines(&["x", "x"]));
+        let block = Register {
+            text: vec![String::from("a"), String::from("bcd")],
+            kind: RangeKind::Block,
+        };
+        assert_eq!(block.repeated(3).text, ["a  a  a", "bcdbcdbcd"]);
+    }
+}
@@ -0,0 +1,172 @@
+use crate::config::{EditorMode, PendingCommand};
+use crate::{handle_key, EditorConfig};
+use terminol::{Key, KeyCode, Modifiers};
+
+/// a change as it was typed, which . types again
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    /// the keys of the command, without its count or register name
+    pub keys: Vec<Key>,
+    pub count: Option<usize>,
+    pub register: Option<char>,
+}
+
+/// keeps the keys of the command being typed, from its first key until the editor is back in
+/// normal mode with nothing pending. a command that changed the buffer becomes the last change
+#[derive(Debug, Default)]
+pub struct Recorder {
+    keys: Vec<Key>,
+    count: Option<usize>,
+    register: Option<char>,
+    /// the changedtick of the buffer when the command started
+    changedtick: u64,
+    /// set while . types a change, whose keys aren't recorded a second time
+    replaying: bool,
+    pub last: Option<Change>,
+}
+
+/// nothing of a command has been typed, the next key starts a new one
+fn idle(editor_config: &EditorConfig) -> bool {
+    let editor_state = &editor_config.editor_state;
+    editor_state.editor_mode == EditorMode::Normal
+        && editor_state.pending_key.is_none()
+        && editor_state.pending == PendingCommand::default()
+}
+
+/// undo, redo, . itself and the command line change the buffer but are never repeated, and
+/// neither are the window and tab page commands, which only look like a change when they go to
+/// another buffer
+fn repeatable(keys: &[Key]) -> bool {
+    let first = match keys.first() {
+        Some(key) => (key.code, key.modifiers),
+        None => return false,
+    };
+    let not_a_change = matches!(
+        keys.get(..2),
+        Some([g, step]) if g.code == KeyCode::Char('g')
+            && matches!(step.code, KeyCode::Char('-' | '+' | 't' | 'T'))
+    );
+    !not_a_change
+        && !matches!(
+            first,
+            (KeyCode::Char('.' | 'u' | 'U' | ':'), Modifiers::NONE)
+                | (KeyCode::Char('r' | 'w'), Modifiers::CTRL)
+        )
+}
+
+/// records `key` before it is handled. counts and register names are kept apart from the keys,
+/// so . can be given a new count and step through the numbered registers
+pub fn before_key(key: &Key, editor_config: &mut EditorConfig) {
+    if editor_config.repeat.replaying {
+        return;
+    }
+    if idle(editor_config) {
+        let recorder = &mut editor_config.repeat;
+        recorder.keys.clear();
+        recorder.count = None;
+        recorder.register = None;
+        recorder.changedtick = editor_config.gap_buffer.changedtick();
+    }
+    let editor_state = &editor_config.editor_state;
+    let mode = editor_state.editor_mode;
+    let waiting = editor_state.pending_key;
+    let counting = matches!(mode, EditorMode::Normal | EditorMode::OperatorPending)
+        && waiting.is_none()
+        && match (key.code, key.modifiers) {
+            (KeyCode::Char('1'..='9'), Modifiers::NONE) => true,
+            (KeyCode::Char('0'), Modifiers::NONE) => editor_state.pending.count.is_some(),
+            _ => false,
+        };
+    let naming = mode == EditorMode::Normal
+        && (waiting == Some('"')
+            || waiting.is_none() && key.code == KeyCode::Char('"') && key.modifiers.is_empty());
+    if !counting && !naming {
+        editor_config.repeat.keys.push(*key);
+    }
+}
+
+/// picks up the count and register of the command once the key has been handled, and keeps the
+/// command as the last change when it is finished and changed something
+pub fn after_key(editor_config: &mut EditorConfig) {
+    if editor_config.repeat.replaying {
+        return;
+    }
+    let pending = editor_config.editor_state.pending;
+    let recorder = &mut editor_config.repeat;
+    if pending != PendingCommand::default() {
+        recorder.count = pending.total_count();
+        recorder.register = pending.register;
+    }
+    if !idle(editor_config) {
+        return;
+    }
+    let recorder = &mut editor_config.repeat;
+    let keys = std::mem::take(&mut recorder.keys);
+    if editor_config.gap_buffer.changedtick() != recorder.changedtick && repeatable(&keys) {
+        recorder.last = Some(Change {
+            keys,
+            count: recorder.count,
+            register: recorder.register,
+        });
+    }
+}
+
+/// ., types the last change again. a count replaces the one it was typed with, and a numbered
+/// register goes on to the next one so "1p... puts the last four deletes
+pub fn replay(editor_config: &mut EditorConfig) {
+    let editor_state = &mut editor_config.editor_state;
+    let count = editor_state.pending.total_count();
+    editor_state.pending = PendingCommand::default();
+    let Some(mut change) = editor_config.repeat.last.clone() else {
+        return;
+    };
+    if count.is_some() {
+        change.count = count;
+    }
+    if let Some(digit @ '1'..='8') = change.register {
+        change.register = char::from_digit(digit.to_digit(10).unwrap_or(0) + 1, 10);
+    }
+    editor_config.repeat.last = Some(change.clone());
+
+    let mut keys = Vec::new();
+    if let Some(register) = change.register {
+        keys.push(Key::plain(KeyCode::Char('"')));
+        keys.push(Key::plain(KeyCode::Char(register)));
+    }
+    if let Some(count) = change.count {
+        keys.extend(
+            count
+                .to_string()
+                .chars()
+                .map(|c| Key::plain(KeyCode::Char(c))),
+        );
+    }
+    keys.extend(change.keys);
+
+    editor_config.repeat.replaying = true;
+    for key in &keys {
+        handle_key(key, editor_config);
+    }
+    editor_config.repeat.replaying = false;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(text: &str) -> Vec<Key> {
+        text.chars().map(|c| Key::plain(KeyCode::Char(c))).collect()
+    }
+
+    #[test]
+    fn what_is_repeatable() {
+        assert!(repeatable(&keys("dw")));
+        assert!(repeatable(&keys("ihello")));
+        assert!(repeatable(&keys("gUw")));
+        assert!(!repeatable(&keys("u")));
+        assert!(!repeatable(&keys(".")));
+        assert!(!repeatable(&keys("g-")));
+        assert!(!repeatable(&[Key::ctrl('r')]));
+        assert!(!repeatable(&[]));
+    }
+}
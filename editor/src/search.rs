@@ -0,0 +1,368 @@
+use crate::config::{EditorMode, EditorState, Options, PendingCommand};
+use crate::motion::Motion;
+use crate::operator;
+use crate::ops::{line_chars, TextRange};
+use crate::regex::{Match, Regex};
+use crate::{place_cursor, EditorConfig};
+use gap_buffer::GapBuffer;
+use terminol::{Key, KeyCode, Modifiers};
+
+fn is_word_char(c: Option<&char>) -> bool {
+    c.is_some_and(|c| c.is_alphanumeric() || *c == '_')
+}
+
+/// compiles a pattern typed after / or ? with the ignorecase and smartcase options
+pub fn compile(text: &str, options: &Options) -> Result<Regex, String> {
+    Regex::new(text, options.ignorecase, options.smartcase)
+}
+
+/// the first match after `from` going forward, or the last one before it going backward, and
+/// whether the search had to go round the end of the buffer to find it. it only ever does with
+/// `wrapscan` set, and then comes back round to `from` itself last
+pub fn find(
+    content_buffer: &GapBuffer<GapBuffer<char>>,
+    regex: &Regex,
+    from: (usize, usize),
+    forward: bool,
+    wrapscan: bool,
+) -> Option<(Match, bool)> {
+    let last = content_buffer.get_filled_items();
+    let (line, col) = from;
+    for step in 0..=last {
+        let wrapped = if forward {
+            line + step >= last
+        } else {
+            step > line
+        };
+        if wrapped && !wrapscan {
+            return None;
+        }
+        let current = if forward {
+            (line + step) % last
+        } else {
+            (line + last - step) % last
+        };
+        // going forward, a match may start anywhere after the cursor even if it overlaps one
+        // that starts before it
+        if step == 0 && forward {
+            match regex.next_match(content_buffer, (line, col + 1)) {
+                Some(found) => return Some((found, false)),
+                None => continue,
+            }
+        }
+        // the line searched from is looked at twice, after the cursor first and the rest of it
+        // once the search has been round the whole buffer
+        let allowed = |start: (usize, usize)| match (step, forward) {
+            (0, _) => start < from,
+            (_, true) if step == last => start <= from,
+            (_, false) if step == last => start >= from,
+            _ => true,
+        };
+        let matches = regex.line_matches(content_buffer, current);
+        let found = match forward {
+            true => matches.into_iter().find(|m| allowed(m.start)),
+            false => matches.into_iter().rev().find(|m| allowed(m.start)),
+        };
+        if let Some(found) = found {
+            return Some((found, wrapped));
+        }
+    }
+    None
+}
+
+/// the matches of `regex` on the lines from `first` to `last`, to be highlighted
+pub fn matches_between(
+    content_buffer: &GapBuffer<GapBuffer<char>>,
+    regex: &Regex,
+    first: usize,
+    last: usize,
+) -> Vec<TextRange> {
+    let last = last.min(content_buffer.get_filled_items().saturating_sub(1));
+    (first..=last)
+        .flat_map(|line| regex.line_matches(content_buffer, line))
+        .filter_map(|found| found.range(content_buffer))
+        .collect()
+}
+
+/// the last search as its pattern and whether it went forward, for n and N
+pub fn last_search(editor_state: &EditorState) -> Option<(Regex, bool)> {
+    let search = &editor_state.search;
+    let regex = compile(search.pattern.as_deref()?, &editor_state.options).ok()?;
+    Some((regex, search.forward))
+}
+
+/// / and ?, opens the prompt in the command row that a pattern is typed at
+pub fn open_prompt(editor_config: &mut EditorConfig, forward: bool) {
+    let editor_state = &mut editor_config.editor_state;
+    let search = &mut editor_state.search;
+    search.prompt_forward = forward;
+    search.origin = editor_config.gap_buffer.cursor_position();
+    search.return_mode = editor_state.editor_mode;
+    search.current = None;
+    editor_state.command.clear();
+    editor_state.update_editor_mode(EditorMode::Search);
+}
+
+/// handles the keys typed at the search prompt. with incsearch on, the cursor is shown on the
+/// first match of what has been typed so far and goes back where it was if the search is dropped
+pub fn search_prompt_handler(key: &Key, editor_config: &mut EditorConfig) {
+    let editor_state = &mut editor_config.editor_state;
+    match (key.code, key.modifiers) {
+        (KeyCode::Enter, _) => return finish(editor_config),
+        (KeyCode::Esc, _) | (KeyCode::Char('c'), Modifiers::CTRL) => return leave(editor_config),
+        (KeyCode::Backspace, _) | (KeyCode::Char('h'), Modifiers::CTRL) => {
+            if editor_state.command.pop().is_none() {
+                return leave(editor_config);
+            }
+        }
+        (KeyCode::Char(c), Modifiers::NONE | Modifiers::SHIFT) => editor_state.command.push(c),
+        (KeyCode::Tab, _) => editor_state.command.push('\t'),
+        _ => return,
+    }
+    if editor_state.options.incsearch {
+        preview(editor_config);
+    }
+}
+
+/// moves the cursor to the first match of the pattern typed so far, or back where it started
+fn preview(editor_config: &mut EditorConfig) {
+    let editor_state = &mut editor_config.editor_state;
+    let content_buffer = &mut editor_config.gap_buffer;
+    let regex = typed_pattern(editor_state).map(|typed| compile(typed, &editor_state.options));
+    let wrapscan = editor_state.options.wrapscan;
+    let search = &mut editor_state.search;
+    let (line, col) = search.origin;
+    content_buffer.set_cursor_position(search.origin);
+    search.current = None;
+    let regex = match regex {
+        Some(Ok(regex)) => regex,
+        _ => return,
+    };
+    let from = (line - 1, col - 1);
+    if let Some((found, _)) = find(
+        content_buffer,
+        &regex,
+        from,
+        search.prompt_forward,
+        wrapscan,
+    ) {
+        place_cursor(content_buffer, found.start);
+        search.current = found.range(content_buffer);
+    }
+}
+
+/// drops the search and goes back to the mode the prompt was opened from
+fn leave(editor_config: &mut EditorConfig) {
+    let editor_state = &mut editor_config.editor_state;
+    let search = &mut editor_state.search;
+    editor_config.gap_buffer.set_cursor_position(search.origin);
+    search.current = None;
+    editor_state.command.clear();
+    match search.return_mode {
+        EditorMode::OperatorPending => operator::cancel(editor_config),
+        mode => {
+            editor_state.pending = PendingCommand::default();
+            editor_state.update_editor_mode(mode);
+        }
+    }
+}
+
+/// the pattern typed at the prompt so far, None while there is none
+pub fn typed_pattern(editor_state: &EditorState) -> Option<&str> {
+    let delimiter = if editor_state.search.prompt_forward {
+        '/'
+    } else {
+        '?'
+    };
+    Some(until_delimiter(&editor_state.command, delimiter)).filter(|typed| !typed.is_empty())
+}
+
+/// the pattern in what was typed at the prompt, which ends at a `delimiter` that isn't escaped
+/// with a \ so that // searches for the last pattern again
+fn until_delimiter(typed: &str, delimiter: char) -> &str {
+    let mut escaped = false;
+    for (i, c) in typed.char_indices() {
+        if c == delimiter && !escaped {
+            return &typed[..i];
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    typed
+}
+
+/// <CR> at the prompt. an empty pattern searches for the last one again. an operator waiting
+/// for the search works on the text up to the match, as in d/foo
+fn finish(editor_config: &mut EditorConfig) {
+    let editor_state = &mut editor_config.editor_state;
+    let search = &mut editor_state.search;
+    let typed = std::mem::take(&mut editor_state.command);
+    editor_config.gap_buffer.set_cursor_position(search.origin);
+    search.current = None;
+    let return_mode = search.return_mode;
+    let delimiter = if search.prompt_forward { '/' } else { '?' };
+    let typed = until_delimiter(&typed, delimiter);
+    if !typed.is_empty() {
+        search.pattern = Some(String::from(typed));
+    }
+    search.forward = search.prompt_forward;
+    match return_mode {
+        EditorMode::OperatorPending => {
+            editor_state.update_editor_mode(EditorMode::OperatorPending);
+            match editor_state.pending.operator {
+                Some(operator) if editor_state.search.pattern.is_some() => {
+                    editor_state.search.highlight = true;
+                    operator::on_motion(
+                        editor_config,
+                        operator,
+                        Motion::SearchNext { reverse: false },
+                    )
+                }
+                _ => operator::cancel(editor_config),
+            }
+        }
+        mode => {
+            editor_state.update_editor_mode(mode);
+            jump(editor_config, false);
+            editor_config.editor_state.pending = PendingCommand::default();
+        }
+    }
+}
+
+/// n and N, the count'th next match of the last search, the other way round for N. the pattern
+/// is shown in the command row, or a message when the search went round the end of the buffer
+pub fn jump(editor_config: &mut EditorConfig, reverse: bool) {
+    let editor_state = &mut editor_config.editor_state;
+    let content_buffer = &mut editor_config.gap_buffer;
+    let count = editor_state.pending.total_count().unwrap_or(1);
+    let text = match editor_state.search.pattern.clone() {
+        Some(text) => text,
+        None => {
+            editor_state.message = Some(String::from("E35: No previous regular expression"));
+            return;
+        }
+    };
+    let regex = match compile(&text, &editor_state.options) {
+        Ok(regex) => regex,
+        Err(e) => {
+            editor_state.message = Some(e);
+            return;
+        }
+    };
+    let forward = editor_state.search.forward != reverse;
+    let wrapscan = editor_state.options.wrapscan;
+    editor_state.search.highlight = true;
+
+    let (line, col) = content_buffer.cursor_position();
+    let mut at = (line - 1, col - 1);
+    let mut wrapped = false;
+    for _ in 0..count {
+        match find(content_buffer, &regex, at, forward, wrapscan) {
+            Some((found, went_round)) => {
+                at = found.start;
+                wrapped |= went_round;
+            }
+            None => {
+                editor_state.message = Some(match (wrapscan, forward) {
+                    (true, _) => format!("E486: Pattern not found: {text}"),
+                    (false, true) => format!("E385: Search hit BOTTOM without match for: {text}"),
+                    (false, false) => format!("E384: Search hit TOP without match for: {text}"),
+                });
+                return;
+            }
+        }
+    }
+    place_cursor(content_buffer, at);
+    editor_state.message = Some(match (wrapped, forward) {
+        (true, true) => String::from("search hit BOTTOM, continuing at TOP"),
+        (true, false) => String::from("search hit TOP, continuing at BOTTOM"),
+        (false, true) => format!("/{text}"),
+        (false, false) => format!("?{text}"),
+    });
+}
+
+/// searches for the word under the cursor, or the first one after it on the line. this is * and
+/// # and also g* and g#, which don't stop at the ends of words
+pub fn search_word(editor_config: &mut EditorConfig, forward: bool, whole_word: bool) {
+    let content_buffer = &mut editor_config.gap_buffer;
+    let (line, col) = content_buffer.cursor_position();
+    let (line, col) = (line - 1, col - 1);
+    let chars = line_chars(content_buffer, line);
+    let is_word = |i: usize| is_word_char(chars.get(i));
+    let mut start = match (col..chars.len()).find(|i| is_word(*i)) {
+        Some(start) => start,
+        None => {
+            editor_config.editor_state.message = Some(String::from("E348: No string under cursor"));
+            return;
+        }
+    };
+    while start > 0 && is_word(start - 1) {
+        start -= 1;
+    }
+    let end = (start..chars.len())
+        .find(|i| !is_word(*i))
+        .unwrap_or(chars.len());
+    let word: String = chars[start..end].iter().collect();
+
+    let search = &mut editor_config.editor_state.search;
+    search.pattern = Some(match whole_word {
+        true => format!("\\<{word}\\>"),
+        false => word,
+    });
+    search.forward = forward;
+    // searching from the start of the word skips over the word itself
+    content_buffer.set_cursor_position((line + 1, start + 1));
+    jump(editor_config, false);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// where the match found starts and ends, and whether the search went round the end
+    #[allow(clippy::type_complexity)]
+    fn search(
+        text: &str,
+        pattern: &str,
+        from: (usize, usize),
+        forward: bool,
+    ) -> Option<((usize, usize), (usize, usize), bool)> {
+        let content_buffer = GapBuffer::build_nested(text);
+        let regex = Regex::new(pattern, false, false).unwrap();
+        let (found, wrapped) = find(&content_buffer, &regex, from, forward, true)?;
+        Some((found.start, found.end, wrapped))
+    }
+
+    #[test]
+    fn finds_the_next_and_previous_match() {
+        let text = "foo bar\nbarfoo\nfoo\n";
+        let at = |line, col, wrapped| Some(((line, col), (line, col + 3), wrapped));
+        assert_eq!(search(text, "foo", (0, 0), true), at(1, 3, false));
+        assert_eq!(search(text, "foo", (1, 3), false), at(0, 0, false));
+        // round the end of the buffer and back again
+        assert_eq!(search(text, "foo", (2, 0), true), at(0, 0, true));
+        assert_eq!(search(text, "bar", (0, 2), false), at(1, 0, true));
+        assert_eq!(
+            search(text, "foo bar", (0, 0), true),
+            Some(((0, 0), (0, 7), true))
+        );
+        assert_eq!(search(text, "baz", (0, 0), true), None);
+        // overlapping matches are found one char on
+        assert_eq!(
+            search("aaaa\n", "aa", (0, 0), true),
+            Some(((0, 1), (0, 3), false))
+        );
+
+        let content_buffer = GapBuffer::build_nested(text);
+        let regex = Regex::new("foo", false, false).unwrap();
+        assert_eq!(find(&content_buffer, &regex, (2, 0), true, false), None);
+    }
+
+    #[test]
+    fn word_boundaries() {
+        let text = "barfoo foo_x foo\n";
+        let found = search(text, "\\<foo\\>", (0, 0), true).unwrap();
+        assert_eq!(found.0, (0, 13));
+        let found = search(text, "\\<foo", (0, 0), true).unwrap();
+        assert_eq!(found.0, (0, 7));
+    }
+}
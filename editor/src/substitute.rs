@@ -0,0 +1,558 @@
+use crate::config::EditorMode;
+use crate::ex;
+use crate::ops::{first_non_blank, line_chars};
+use crate::regex::{Match, Regex};
+use crate::search;
+use crate::{place_cursor, EditorConfig};
+use gap_buffer::GapBuffer;
+use terminol::{Key, KeyCode, Modifiers};
+
+/// the letters after the replacement of a :s
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Flags {
+    /// g, every match on a line rather than only the first
+    pub global: bool,
+    /// c, ask before each substitution
+    pub confirm: bool,
+    /// i or I, overriding the ignorecase and smartcase options
+    pub ignore_case: Option<bool>,
+    /// n, count the matches without changing anything
+    pub count_only: bool,
+    /// e, no error when the pattern isn't found
+    pub no_error: bool,
+}
+
+impl Flags {
+    /// adds the flag letters typed to these. `&` is left to the caller, it has to come first
+    fn add(&mut self, letters: &str) {
+        for letter in letters.chars() {
+            match letter {
+                'g' => self.global = true,
+                'c' => self.confirm = true,
+                'i' => self.ignore_case = Some(true),
+                'I' => self.ignore_case = Some(false),
+                'n' => self.count_only = true,
+                'e' => self.no_error = true,
+                _ => (),
+            }
+        }
+    }
+}
+
+/// the last :s, for :&, :&& and g&
+#[derive(Clone, Debug, PartialEq)]
+pub struct LastSubstitute {
+    pub pattern: String,
+    pub replacement: String,
+    pub flags: Flags,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Case {
+    Upper,
+    Lower,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Piece {
+    Char(char),
+    /// what a group matched, 0 and & for the whole match
+    Group(usize),
+    /// \r, the line is split here
+    LineBreak,
+    /// \u and \l, the case of the next char only
+    NextCase(Case),
+    /// \U and \L, the case of everything up to \e or \E
+    AllCase(Case),
+    EndCase,
+}
+
+/// the replacement of a :s, parsed once and expanded for every match
+#[derive(Clone, Debug, PartialEq)]
+struct Replacement {
+    pieces: Vec<Piece>,
+}
+
+impl Replacement {
+    fn parse(text: &str) -> Replacement {
+        let mut pieces = Vec::new();
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            let piece = match c {
+                '&' => Piece::Group(0),
+                '\\' => match chars.next() {
+                    Some(digit @ '0'..='9') => Piece::Group(digit as usize - '0' as usize),
+                    // there is no nul char in a line to put, so \n splits it like \r
+                    Some('r' | 'n') => Piece::LineBreak,
+                    Some('t') => Piece::Char('\t'),
+                    Some('u') => Piece::NextCase(Case::Upper),
+                    Some('l') => Piece::NextCase(Case::Lower),
+                    Some('U') => Piece::AllCase(Case::Upper),
+                    Some('L') => Piece::AllCase(Case::Lower),
+                    Some('e' | 'E') => Piece::EndCase,
+                    Some(other) => Piece::Char(other),
+                    None => Piece::Char('\\'),
+                },
+                c => Piece::Char(c),
+            };
+            pieces.push(piece);
+        }
+        Replacement { pieces }
+    }
+
+    /// the text to put in place of `found`. a line break is a '\n' in it
+    fn expand(&self, content_buffer: &GapBuffer<GapBuffer<char>>, found: &Match) -> String {
+        let mut text = String::new();
+        let mut next_case = None;
+        let mut all_case = None;
+        let push = |text: &mut String, c: char, case: Option<Case>| match case {
+            Some(Case::Upper) => text.extend(c.to_uppercase()),
+            Some(Case::Lower) => text.extend(c.to_lowercase()),
+            None => text.push(c),
+        };
+        for piece in &self.pieces {
+            match *piece {
+                Piece::Char(c) => push(&mut text, c, next_case.take().or(all_case)),
+                Piece::Group(n) => {
+                    let (from, to) = match n {
+                        0 => (found.start, found.end),
+                        n => match found.groups[n] {
+                            Some(group) => group,
+                            None => continue,
+                        },
+                    };
+                    for c in text_between(content_buffer, from, to).chars() {
+                        push(&mut text, c, next_case.take().or(all_case));
+                    }
+                }
+                Piece::LineBreak => text.push('\n'),
+                Piece::NextCase(case) => next_case = Some(case),
+                Piece::AllCase(case) => all_case = Some(case),
+                Piece::EndCase => all_case = None,
+            }
+        }
+        text
+    }
+}
+
+/// the chars from `from` up to `to`, newlines included
+fn text_between(
+    content_buffer: &GapBuffer<GapBuffer<char>>,
+    from: (usize, usize),
+    to: (usize, usize),
+) -> String {
+    let mut text = String::new();
+    for line in from.0..=to.0 {
+        let chars = line_chars(content_buffer, line);
+        let start = if line == from.0 { from.1 } else { 0 };
+        let end = if line == to.0 { to.1 } else { chars.len() };
+        text.extend(&chars[start.min(chars.len())..end.min(chars.len())]);
+    }
+    text
+}
+
+/// a :s going through its lines one match at a time, so the c flag can stop between them
+#[derive(Clone, Debug)]
+pub struct Substitution {
+    regex: Regex,
+    /// the pattern as typed, for the error when it isn't found
+    pattern: String,
+    replacement: Replacement,
+    /// the replacement as typed, for the confirm prompt
+    typed: String,
+    flags: Flags,
+    /// where the next match is looked for, 0-indexed
+    at: (usize, usize),
+    /// the last line of the range, moved along as lines are split and joined
+    last: usize,
+    /// where the last match handled ended, an empty match right there is passed over
+    previous_end: Option<(usize, usize)>,
+    /// the match the confirm prompt is asking about
+    current: Option<Match>,
+    count: usize,
+    lines: usize,
+    /// the line of the last match counted, as it was numbered before any lines were split
+    last_line: Option<usize>,
+    /// lines added by the substitutions so far, less the ones taken away
+    added: isize,
+    /// the line the last substitution ended on, where the cursor is left
+    cursor_line: Option<usize>,
+}
+
+impl Substitution {
+    /// the next match in the range, None once there are no more
+    fn next(&mut self, content_buffer: &GapBuffer<GapBuffer<char>>) -> Option<Match> {
+        while self.at.0 <= self.last && self.at.0 < content_buffer.get_filled_items() {
+            match self.regex.next_match(content_buffer, self.at) {
+                Some(found)
+                    if found.start == found.end && Some(found.start) == self.previous_end =>
+                {
+                    self.at = (found.start.0, found.start.1 + 1);
+                }
+                Some(found) => return Some(found),
+                None => self.at = (self.at.0 + 1, 0),
+            }
+        }
+        None
+    }
+
+    fn count(&mut self, found: &Match) {
+        let line = (found.start.0 as isize - self.added) as usize;
+        if self.last_line != Some(line) {
+            self.lines += 1;
+            self.last_line = Some(line);
+        }
+        self.count += 1;
+    }
+
+    /// carries on after a match that ends at `end`, on the same line only with the g flag or
+    /// when the match took in a line break, as what follows it came from the next line
+    fn move_past(&mut self, found: &Match, end: (usize, usize)) {
+        self.previous_end = Some(end);
+        let joined = found.end.0 > found.start.0;
+        self.at = match (self.flags.global || joined, found.start == found.end) {
+            (false, _) => (end.0 + 1, 0),
+            (true, false) => end,
+            (true, true) => (end.0, end.1 + 1),
+        };
+    }
+
+    /// puts the replacement in place of the match, splitting lines at its line breaks
+    fn replace(&mut self, content_buffer: &mut GapBuffer<GapBuffer<char>>, found: &Match) {
+        let text = self.replacement.expand(content_buffer, found);
+        let first = line_chars(content_buffer, found.start.0);
+        let last = line_chars(content_buffer, found.end.0);
+        let before: String = first[..found.start.1.min(first.len())].iter().collect();
+        let after: String = last[found.end.1.min(last.len())..]
+            .iter()
+            .filter(|c| **c != '\n')
+            .collect();
+
+        let lines: Vec<String> = format!("{before}{text}{after}")
+            .split('\n')
+            .map(String::from)
+            .collect();
+        let removed = found.end.0 - found.start.0 + 1;
+        content_buffer.replace_lines(found.start.0, removed, &lines);
+
+        // counted on the line it was found on, before it was split or joined
+        self.count(found);
+        let change = lines.len() as isize - removed as isize;
+        self.added += change;
+        self.last = (self.last as isize + change).max(0) as usize;
+        let end = match text.rsplit_once('\n') {
+            Some((_, tail)) => (
+                found.start.0 + text.matches('\n').count(),
+                tail.chars().count(),
+            ),
+            None => (found.start.0, found.start.1 + text.chars().count()),
+        };
+        self.cursor_line = Some(end.0);
+        self.move_past(found, end);
+    }
+
+    /// every match left, substituted or only counted with the n flag
+    fn run(&mut self, content_buffer: &mut GapBuffer<GapBuffer<char>>) {
+        while let Some(found) = self.next(content_buffer) {
+            match self.flags.count_only {
+                true => {
+                    self.count(&found);
+                    self.move_past(&found, found.end);
+                }
+                false => self.replace(content_buffer, &found),
+            }
+        }
+    }
+
+    /// "3 substitutions on 2 lines", or the matches counted with the n flag
+    fn report(&self) -> String {
+        let what = match (self.flags.count_only, self.count) {
+            (true, 1) => "match",
+            (true, _) => "matches",
+            (false, 1) => "substitution",
+            (false, _) => "substitutions",
+        };
+        let lines = match self.lines {
+            1 => "line",
+            _ => "lines",
+        };
+        format!("{} {what} on {} {lines}", self.count, self.lines)
+    }
+}
+
+/// :s, :& and :&& over the lines from `start` to `end`, 1-indexed
+pub fn substitute(
+    editor_config: &mut EditorConfig,
+    command: ex::Substitute,
+    (start, end): (usize, usize),
+) {
+    let editor_state = &mut editor_config.editor_state;
+    let last_substitute = editor_state.last_substitute.clone();
+    let (pattern, typed) = match (command.parts, &last_substitute) {
+        (Some((pattern, typed)), _) if pattern.is_empty() => match &editor_state.search.pattern {
+            Some(pattern) => (pattern.clone(), typed),
+            None => {
+                editor_state.message = Some(String::from("E35: No previous regular expression"));
+                return;
+            }
+        },
+        (Some(parts), _) => parts,
+        (None, Some(last)) => (last.pattern.clone(), last.replacement.clone()),
+        (None, None) => {
+            editor_state.message = Some(String::from("E35: No previous regular expression"));
+            return;
+        }
+    };
+    let mut flags = match (command.flags.starts_with('&'), &last_substitute) {
+        (true, Some(last)) => last.flags,
+        _ => Flags::default(),
+    };
+    flags.add(&command.flags);
+
+    let regex = match flags.ignore_case {
+        Some(ignore_case) => Regex::new(&pattern, ignore_case, false),
+        None => search::compile(&pattern, &editor_state.options),
+    };
+    let regex = match regex {
+        Ok(regex) => regex,
+        Err(e) => {
+            editor_state.message = Some(e);
+            return;
+        }
+    };
+    editor_state.last_substitute = Some(LastSubstitute {
+        pattern: pattern.clone(),
+        replacement: typed.clone(),
+        flags,
+    });
+    editor_state.search.pattern = Some(pattern.clone());
+    editor_state.search.highlight = true;
+
+    let content_buffer = &mut editor_config.gap_buffer;
+    let (start, end) = match command.count {
+        Some(count) => (
+            end,
+            (end + count - 1).min(content_buffer.get_filled_items()),
+        ),
+        None => (start, end),
+    };
+    let mut substitution = Substitution {
+        regex,
+        pattern,
+        replacement: Replacement::parse(&typed),
+        typed,
+        flags,
+        at: (start - 1, 0),
+        last: end - 1,
+        previous_end: None,
+        current: None,
+        count: 0,
+        lines: 0,
+        last_line: None,
+        added: 0,
+        cursor_line: None,
+    };
+
+    editor_config.undo_tree.begin(content_buffer);
+    // there is no prompt to come back to in the middle of a :g, every match is substituted
+    if flags.confirm && !flags.count_only && !editor_state.in_global {
+        if let Some(found) = substitution.next(content_buffer) {
+            editor_state.confirm = Some(substitution);
+            return ask(editor_config, found);
+        }
+        // nothing to ask about, which is the same error as without the c flag
+        substitution.flags.confirm = false;
+    }
+    substitution.run(content_buffer);
+    finish(editor_config, substitution);
+}
+
+/// g&, the last :s again on every line and with its flags, but for the last search pattern
+/// rather than its own, the way :%s//~/& does
+pub fn repeat_everywhere(editor_config: &mut EditorConfig) {
+    let last_substitute = editor_config.editor_state.last_substitute.as_ref();
+    let command = ex::Substitute {
+        parts: Some((
+            String::new(),
+            last_substitute.map_or_else(String::new, |last| last.replacement.clone()),
+        )),
+        flags: String::from("&"),
+        count: None,
+    };
+    let lines = editor_config.gap_buffer.get_filled_items();
+    substitute(editor_config, command, (1, lines));
+}
+
+/// closes the change and reports how it went, leaving the cursor on the last line substituted
+fn finish(editor_config: &mut EditorConfig, substitution: Substitution) {
+    let content_buffer = &mut editor_config.gap_buffer;
+    let editor_state = &mut editor_config.editor_state;
+    editor_config.undo_tree.commit(content_buffer);
+    // skipping every match at the confirm prompt isn't an error
+    if substitution.count == 0 {
+        if !substitution.flags.confirm && !substitution.flags.no_error {
+            let pattern = &substitution.pattern;
+            editor_state.message = Some(format!("E486: Pattern not found: {pattern}"));
+        }
+        return;
+    }
+    if let Some(line) = substitution.cursor_line {
+        place_cursor(
+            content_buffer,
+            (line, first_non_blank(content_buffer, line)),
+        );
+    }
+    editor_state.message = Some(substitution.report());
+}
+
+/// shows the next match of a :s with the c flag and asks what to do with it
+fn prompt(editor_config: &mut EditorConfig) {
+    let content_buffer = &editor_config.gap_buffer;
+    let Some(substitution) = editor_config.editor_state.confirm.as_mut() else {
+        return;
+    };
+    match substitution.next(content_buffer) {
+        Some(found) => ask(editor_config, found),
+        None => stop(editor_config),
+    }
+}
+
+/// highlights `found` and puts the question in the command row
+fn ask(editor_config: &mut EditorConfig, found: Match) {
+    let content_buffer = &mut editor_config.gap_buffer;
+    let editor_state = &mut editor_config.editor_state;
+    let Some(substitution) = editor_state.confirm.as_mut() else {
+        return;
+    };
+    substitution.current = Some(found);
+    editor_state.message = Some(format!(
+        "replace with {} (y/n/a/q/l/^E/^Y)?",
+        substitution.typed
+    ));
+    editor_state.search.current = found.range(content_buffer);
+    place_cursor(content_buffer, found.start);
+    editor_state.update_editor_mode(EditorMode::Confirm);
+}
+
+/// leaves the confirm prompt, whatever matches are left are not substituted
+fn stop(editor_config: &mut EditorConfig) {
+    let editor_state = &mut editor_config.editor_state;
+    editor_state.search.current = None;
+    editor_state.update_editor_mode(EditorMode::Normal);
+    if let Some(substitution) = editor_state.confirm.take() {
+        finish(editor_config, substitution);
+    }
+}
+
+/// the keys of the confirm prompt. y substitutes the match, l substitutes it and stops, n skips
+/// it, a substitutes it and every match after it, and q or Esc stops. ^E and ^Y scroll
+pub fn confirm_handler(key: &Key, editor_config: &mut EditorConfig) {
+    let content_buffer = &mut editor_config.gap_buffer;
+    let editor_state = &mut editor_config.editor_state;
+    let Some(found) = editor_state.confirm.as_mut().and_then(|s| s.current.take()) else {
+        return stop(editor_config);
+    };
+    let Some(substitution) = editor_state.confirm.as_mut() else {
+        return stop(editor_config);
+    };
+    match (key.code, key.modifiers) {
+        (KeyCode::Char('y'), Modifiers::NONE) => substitution.replace(content_buffer, &found),
+        (KeyCode::Char('l'), Modifiers::NONE) => {
+            substitution.replace(content_buffer, &found);
+            return stop(editor_config);
+        }
+        (KeyCode::Char('n'), Modifiers::NONE) => substitution.move_past(&found, found.end),
+        (KeyCode::Char('a'), Modifiers::NONE) => {
+            substitution.replace(content_buffer, &found);
+            substitution.run(content_buffer);
+            return stop(editor_config);
+        }
+        (KeyCode::Char('q'), Modifiers::NONE)
+        | (KeyCode::Esc, _)
+        | (KeyCode::Char('c'), Modifiers::CTRL) => return stop(editor_config),
+        (KeyCode::Char(c @ ('e' | 'y')), Modifiers::CTRL) => {
+            let viewport = &mut editor_config.viewport;
+            let last_line = content_buffer.get_filled_items() - 1;
+            viewport.top = match c {
+                'e' => (viewport.top + 1).min(last_line),
+                _ => viewport.top.saturating_sub(1),
+            };
+            return ask(editor_config, found);
+        }
+        // anything else asks about the same match again
+        _ => return ask(editor_config, found),
+    }
+    prompt(editor_config);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// runs a :s over every line of `text` and gives back the lines and the report
+    fn substitute(text: &str, pattern: &str, replacement: &str, global: bool) -> (String, String) {
+        let mut content_buffer = GapBuffer::build_nested(text);
+        let mut substitution = Substitution {
+            regex: Regex::new(pattern, false, false).unwrap(),
+            pattern: String::from(pattern),
+            replacement: Replacement::parse(replacement),
+            typed: String::from(replacement),
+            flags: Flags {
+                global,
+                ..Flags::default()
+            },
+            at: (0, 0),
+            last: content_buffer.get_filled_items() - 1,
+            previous_end: None,
+            current: None,
+            count: 0,
+            lines: 0,
+            last_line: None,
+            added: 0,
+            cursor_line: None,
+        };
+        substitution.run(&mut content_buffer);
+        let lines = content_buffer.get_lines().join("\n");
+        (lines, substitution.report())
+    }
+
+    #[test]
+    fn groups_and_case() {
+        let (lines, report) = substitute("one two\nthree", r"\(\w\+\) \(\w\+\)", r"\2 \1", false);
+        assert_eq!(lines, "two one\nthree");
+        assert_eq!(report, "1 substitution on 1 line");
+
+        let (lines, _) = substitute("hello world", r"\w\+", r"\u&", true);
+        assert_eq!(lines, "Hello World");
+        let (lines, _) = substitute("hello world", r"hello", r"\U&\E!", false);
+        assert_eq!(lines, "HELLO! world");
+        let (lines, _) = substitute("Hello", r".*", r"\L\u&", false);
+        assert_eq!(lines, "Hello");
+    }
+
+    #[test]
+    fn global_and_empty_matches() {
+        let (lines, report) = substitute("a a a\nb a", "a", "x", true);
+        assert_eq!(lines, "x x x\nb x");
+        assert_eq!(report, "4 substitutions on 2 lines");
+
+        let (lines, _) = substitute("a a a\nb a", "a", "x", false);
+        assert_eq!(lines, "x a a\nb x");
+
+        let (lines, _) = substitute("baaac", "a*", "-", true);
+        assert_eq!(lines, "-b-c-");
+        let (lines, _) = substitute("abc", "a", "&&", true);
+        assert_eq!(lines, "aabc");
+    }
+
+    #[test]
+    fn splitting_and_joining_lines() {
+        let (lines, report) = substitute("a,b,c\nd,e", ",", r"\r", true);
+        assert_eq!(lines, "a\nb\nc\nd\ne");
+        assert_eq!(report, "3 substitutions on 2 lines");
+
+        let (lines, report) = substitute("one\ntwo\nthree", r"\n", " ", false);
+        assert_eq!(lines, "one two three");
+        assert_eq!(report, "2 substitutions on 2 lines");
+    }
+}
@@ -0,0 +1,226 @@
+use crate::buffer::{self, display_name};
+use crate::tui::Tab;
+use crate::window::{self, Windows};
+use crate::EditorConfig;
+use std::mem;
+
+/// the tab pages, each with windows of its own. the one being edited keeps its windows in
+/// EditorConfig, switching swaps them with those of another tab page kept here
+pub struct TabPages {
+    /// the index of the tab page being edited, counting from 0
+    pub current: usize,
+    /// the windows of every other tab page, in order
+    others: Vec<Windows>,
+}
+
+impl TabPages {
+    /// a single tab page, the one being edited
+    pub fn new() -> TabPages {
+        TabPages {
+            current: 0,
+            others: Vec::new(),
+        }
+    }
+    pub fn count(&self) -> usize {
+        self.others.len() + 1
+    }
+    /// the tabline is only drawn once there is more than one tab page, like vim does
+    pub fn shown(&self) -> bool {
+        !self.others.is_empty()
+    }
+    /// closes every window on buffer `number` in the other tab pages, dropping those left empty
+    fn close_showing(&mut self, number: usize) {
+        let current = self.current;
+        let mut index = 0;
+        let mut removed_before = 0;
+        self.others.retain_mut(|windows| {
+            let kept = windows.close_showing(number, false);
+            // the tab pages before the one being edited count towards its index
+            if !kept && index < current {
+                removed_before += 1;
+            }
+            index += 1;
+            kept
+        });
+        self.current -= removed_before;
+    }
+}
+
+/// whether buffer `number` is shown in any window other than the one being edited, in this tab
+/// page or another
+pub fn shows_elsewhere(editor_config: &EditorConfig, number: usize) -> bool {
+    let other_tabs = editor_config.tabs.others.iter();
+    editor_config.windows.shows_elsewhere(number)
+        || other_tabs
+            .flat_map(|windows| &windows.list)
+            .any(|window| window.buffer == number)
+}
+
+/// closes every window on buffer `number` but the one being edited, for :bd. a tab page left
+/// without any windows goes too
+pub fn close_showing(editor_config: &mut EditorConfig, number: usize) {
+    editor_config.windows.close_showing(number, true);
+    editor_config.tabs.close_showing(number);
+}
+
+/// the name and modified state of the buffer in the window being edited on each tab page
+pub fn labels(editor_config: &EditorConfig) -> Vec<Tab> {
+    let tabs = &editor_config.tabs;
+    let current = Tab {
+        name: String::from(display_name(&editor_config.file_data)),
+        modified: editor_config.modified,
+        current: true,
+    };
+    let mut labels: Vec<Tab> = tabs
+        .others
+        .iter()
+        .map(|windows| {
+            let buffer = windows
+                .active_buffer()
+                .and_then(|number| editor_config.buffers.get(number));
+            match buffer {
+                Some(buffer) => Tab {
+                    name: String::from(display_name(&buffer.file_data)),
+                    modified: buffer.modified,
+                    current: false,
+                },
+                // a window on the buffer being edited
+                None => Tab {
+                    name: current.name.clone(),
+                    modified: current.modified,
+                    current: false,
+                },
+            }
+        })
+        .collect();
+    labels.insert(tabs.current, current);
+    labels
+}
+
+/// makes tab page `index` the one being edited, with the window that was edited on it
+fn go_to(editor_config: &mut EditorConfig, index: usize) {
+    let tabs = &mut editor_config.tabs;
+    let current = tabs.current;
+    if index == current || index >= tabs.count() {
+        return;
+    }
+    window::save_active(editor_config);
+    let tabs = &mut editor_config.tabs;
+    // `others` skips the tab page being edited, so the ones after it are one place earlier
+    let mut windows = tabs
+        .others
+        .remove(if index > current { index - 1 } else { index });
+    mem::swap(&mut windows, &mut editor_config.windows);
+    let tabs = &mut editor_config.tabs;
+    tabs.others.insert(
+        if current > index {
+            current - 1
+        } else {
+            current
+        },
+        windows,
+    );
+    tabs.current = index;
+    enter_active(editor_config);
+}
+
+/// edits the window the windows in EditorConfig were left in
+fn enter_active(editor_config: &mut EditorConfig) {
+    let active = editor_config.windows.active;
+    window::enter(editor_config, active);
+}
+
+/// :tabnew and :tabedit, opens a tab page after the one being edited with a single window on
+/// `file_name`, or on a new empty buffer
+pub fn new(editor_config: &mut EditorConfig, file_name: Option<String>) {
+    window::save_active(editor_config);
+    let windows = Windows::new(editor_config.buffers.current, editor_config.viewport);
+    let windows = mem::replace(&mut editor_config.windows, windows);
+    let tabs = &mut editor_config.tabs;
+    tabs.others.insert(tabs.current, windows);
+    tabs.current += 1;
+    match file_name {
+        Some(ref name) if editor_config.file_data.is_named(name) => (),
+        Some(_) => buffer::edit(editor_config, file_name, false),
+        None => window::new_buffer(editor_config),
+    }
+}
+
+/// gt, goes to the next tab page, or to tab page `count` when one is given. the last one is
+/// followed by the first
+pub fn next(editor_config: &mut EditorConfig, count: Option<usize>) {
+    let tabs = &editor_config.tabs;
+    let index = match count {
+        Some(count) => count - 1,
+        None => (tabs.current + 1) % tabs.count(),
+    };
+    go_to(editor_config, index);
+}
+
+/// gT, goes `count` tab pages back, coming round to the last one after the first
+pub fn previous(editor_config: &mut EditorConfig, count: Option<usize>) {
+    let tabs = &editor_config.tabs;
+    let back = count.unwrap_or(1) % tabs.count();
+    go_to(
+        editor_config,
+        (tabs.current + tabs.count() - back) % tabs.count(),
+    );
+}
+
+/// :tabclose, closes the tab page being edited with all of its windows. the buffers stay in the
+/// buffer list. the tab page after it is edited next, or the one before it when it was the last
+pub fn close(editor_config: &mut EditorConfig) {
+    let tabs = &mut editor_config.tabs;
+    if tabs.others.is_empty() {
+        editor_config.editor_state.message = Some(String::from("E784: Cannot close last tab page"));
+        return;
+    }
+    let index = tabs.current.min(tabs.others.len() - 1);
+    editor_config.windows = tabs.others.remove(index);
+    tabs.current = index;
+    enter_active(editor_config);
+}
+
+/// :tabonly, closes every tab page but the one being edited
+pub fn only(editor_config: &mut EditorConfig) {
+    let tabs = &mut editor_config.tabs;
+    tabs.others.clear();
+    tabs.current = 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tui::Viewport;
+
+    fn windows_on(buffer: usize) -> Windows {
+        let viewport = Viewport {
+            top: 0,
+            left: 0,
+            height: 20,
+            width: 80,
+        };
+        Windows::new(buffer, viewport)
+    }
+
+    #[test]
+    fn closing_tab_pages_around_the_current_one() {
+        // editing the third tab page, the two before it only show buffer 2
+        let mut tabs = TabPages {
+            current: 2,
+            others: vec![windows_on(2), windows_on(2)],
+        };
+        tabs.close_showing(2);
+        assert_eq!(tabs.current, 0);
+        assert!(!tabs.shown());
+
+        // only the tab pages before it move the current one back
+        let mut tabs = TabPages {
+            current: 1,
+            others: vec![windows_on(2), windows_on(3), windows_on(2)],
+        };
+        tabs.close_showing(2);
+        assert_eq!(tabs.current, 0);
+        assert_eq!(tabs.count(), 2);
+    }
+}
@@ -0,0 +1,447 @@
+use crate::motion;
+use crate::ops::{line_chars, RangeKind, TextRange};
+use gap_buffer::GapBuffer;
+
+/// what is picked out around the cursor by i or a and the key after it, as in ciw or da(
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextObject {
+    Word { big: bool },
+    Sentence,
+    Paragraph,
+    Brackets(char, char),
+    Quotes(char),
+    Tag,
+}
+
+impl TextObject {
+    pub fn from_key(key: char) -> Option<TextObject> {
+        let object = match key {
+            'w' => TextObject::Word { big: false },
+            'W' => TextObject::Word { big: true },
+            's' => TextObject::Sentence,
+            'p' => TextObject::Paragraph,
+            '(' | ')' | 'b' => TextObject::Brackets('(', ')'),
+            '[' | ']' => TextObject::Brackets('[', ']'),
+            '{' | '}' | 'B' => TextObject::Brackets('{', '}'),
+            '<' | '>' => TextObject::Brackets('<', '>'),
+            '"' | '\'' | '`' => TextObject::Quotes(key),
+            't' => TextObject::Tag,
+            _ => return None,
+        };
+        Some(object)
+    }
+}
+
+/// the text `object` covers around the 0-indexed `cursor`. `inner` is for the i objects, which
+/// leave out the white space or the delimiters that the a objects take in as well. a count
+/// takes in more words, sentences or paragraphs, or reaches further out for the ones that nest.
+/// None when there is no such object around the cursor or it would be empty
+pub fn select(
+    content_buffer: &GapBuffer<GapBuffer<char>>,
+    cursor: (usize, usize),
+    object: TextObject,
+    inner: bool,
+    count: usize,
+) -> Option<TextRange> {
+    let count = count.max(1);
+    match object {
+        TextObject::Word { big } => word(content_buffer, cursor, big, inner, count),
+        TextObject::Sentence => sentence(content_buffer, cursor, inner, count),
+        TextObject::Paragraph => paragraph(content_buffer, cursor.0, inner, count),
+        TextObject::Brackets(open, close) => {
+            brackets(content_buffer, cursor, open, close, inner, count)
+        }
+        TextObject::Quotes(quote) => quotes(content_buffer, cursor, quote, inner),
+        TextObject::Tag => tag(content_buffer, cursor, inner, count),
+    }
+}
+
+fn is_blank(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+fn chars_between(line: usize, start: usize, end: usize) -> TextRange {
+    TextRange {
+        kind: RangeKind::Char,
+        start: (line, start),
+        end: (line, end),
+    }
+}
+
+/// iw and aw. for iw every run of word chars, punctuation or white counts as one. aw takes a
+/// word with the white after it, or the white before it when there is none after
+fn word(
+    content_buffer: &GapBuffer<GapBuffer<char>>,
+    (line, col): (usize, usize),
+    big: bool,
+    inner: bool,
+    count: usize,
+) -> Option<TextRange> {
+    let chars = line_chars(content_buffer, line);
+    if chars.is_empty() {
+        return None;
+    }
+    let col = col.min(chars.len() - 1);
+    let class = |i: usize| match chars[i] {
+        c if is_blank(c) => 0,
+        _ if big => 1,
+        c if c.is_alphanumeric() || c == '_' => 1,
+        _ => 2,
+    };
+    let run_end = |i: usize| {
+        let mut end = i;
+        while end + 1 < chars.len() && class(end + 1) == class(i) {
+            end += 1;
+        }
+        end
+    };
+    let mut start = col;
+    while start > 0 && class(start - 1) == class(col) {
+        start -= 1;
+    }
+    let on_blank = class(col) == 0;
+    let mut end = run_end(col);
+
+    if inner {
+        for _ in 1..count {
+            if end + 1 >= chars.len() {
+                break;
+            }
+            end = run_end(end + 1);
+        }
+        return Some(chars_between(line, start, end));
+    }
+    let mut white_after = true;
+    for n in 0..count {
+        if n > 0 {
+            if end + 1 >= chars.len() {
+                break;
+            }
+            end = run_end(end + 1);
+        }
+        // a word goes with the white after it and white with the word after it
+        if end + 1 < chars.len() && (class(end + 1) == 0) != on_blank {
+            end = run_end(end + 1);
+        } else if !on_blank {
+            white_after = false;
+        }
+    }
+    if !white_after {
+        while start > 0 && class(start - 1) == 0 {
+            start -= 1;
+        }
+    }
+    Some(chars_between(line, start, end))
+}
+
+/// is and as. as takes in the white after the sentence too
+fn sentence(
+    content_buffer: &GapBuffer<GapBuffer<char>>,
+    cursor: (usize, usize),
+    inner: bool,
+    count: usize,
+) -> Option<TextRange> {
+    let (start, mut next) = motion::sentence_bounds(content_buffer, cursor);
+    for _ in 1..count {
+        next = motion::sentence_bounds(content_buffer, next).1;
+    }
+    let mut end = content_buffer
+        .prev_position(next)
+        .filter(|end| *end >= start)?;
+    // the newline before the next line isn't part of either sentence
+    while end > start {
+        match content_buffer.char_at(end) {
+            Some('\n') => (),
+            Some(c) if inner && is_blank(c) => (),
+            _ => break,
+        }
+        end = content_buffer.prev_position(end)?;
+    }
+    Some(TextRange {
+        kind: RangeKind::Char,
+        start,
+        end,
+    })
+}
+
+/// ip and ap, always whole lines. a run of blank lines counts as a paragraph of its own for ip,
+/// ap takes a paragraph with the blank lines after it, or before it when there are none after
+fn paragraph(
+    content_buffer: &GapBuffer<GapBuffer<char>>,
+    line: usize,
+    inner: bool,
+    count: usize,
+) -> Option<TextRange> {
+    let last_line = content_buffer.get_filled_items() - 1;
+    let blank = |line: usize| line_chars(content_buffer, line).into_iter().all(is_blank);
+    let run_end = |line: usize| {
+        let mut end = line;
+        while end < last_line && blank(end + 1) == blank(line) {
+            end += 1;
+        }
+        end
+    };
+    let mut start = line;
+    while start > 0 && blank(start - 1) == blank(line) {
+        start -= 1;
+    }
+    let mut end = run_end(line);
+    let mut blank_after = true;
+    for n in 0..count {
+        if n > 0 {
+            if end == last_line {
+                break;
+            }
+            end = run_end(end + 1);
+        }
+        if !inner {
+            if end < last_line {
+                end = run_end(end + 1);
+            } else {
+                blank_after = false;
+            }
+        }
+    }
+    if !blank_after && !blank(line) {
+        while start > 0 && blank(start - 1) {
+            start -= 1;
+        }
+    }
+    Some(TextRange {
+        kind: RangeKind::Line,
+        start: (start, 0),
+        end: (end, 0),
+    })
+}
+
+/// i( and a( and the other brackets. the pair can be spread over many lines, and with the
+/// cursor on a bracket that bracket's pair is taken. i( of a block whose brackets end and start
+/// their lines takes the lines in between whole
+fn brackets(
+    content_buffer: &GapBuffer<GapBuffer<char>>,
+    cursor: (usize, usize),
+    open: char,
+    close: char,
+    inner: bool,
+    count: usize,
+) -> Option<TextRange> {
+    let mut open_at = match content_buffer.char_at(cursor) {
+        Some(c) if c == open => cursor,
+        _ => content_buffer.find_unmatched(cursor, open, close, false)?,
+    };
+    for _ in 1..count {
+        open_at = content_buffer.find_unmatched(open_at, open, close, false)?;
+    }
+    let close_at = content_buffer.find_unmatched(open_at, open, close, true)?;
+    if !inner {
+        return Some(TextRange {
+            kind: RangeKind::Char,
+            start: open_at,
+            end: close_at,
+        });
+    }
+
+    let start = content_buffer.next_position(open_at)?;
+    let end = content_buffer.prev_position(close_at)?;
+    if start == close_at {
+        return None;
+    }
+    let close_starts_line = line_chars(content_buffer, close_at.0)[..close_at.1]
+        .iter()
+        .all(|c| is_blank(*c));
+    if content_buffer.char_at(start) == Some('\n') && close_starts_line {
+        if close_at.0 - open_at.0 < 2 {
+            return None;
+        }
+        return Some(TextRange {
+            kind: RangeKind::Line,
+            start: (open_at.0 + 1, 0),
+            end: (close_at.0 - 1, 0),
+        });
+    }
+    Some(TextRange {
+        kind: RangeKind::Char,
+        start,
+        end,
+    })
+}
+
+/// i" and a" and the other quotes, which never go past the line. quotes pair up from the start
+/// of the line, and when the cursor isn't inside a pair the first pair after it is taken. a"
+/// takes in the white after the closing quote, or before the opening one
+fn quotes(
+    content_buffer: &GapBuffer<GapBuffer<char>>,
+    (line, col): (usize, usize),
+    quote: char,
+    inner: bool,
+) -> Option<TextRange> {
+    let chars = line_chars(content_buffer, line);
+    let found: Vec<usize> = (0..chars.len())
+        .filter(|i| chars[*i] == quote && (*i == 0 || chars[*i - 1] != '\\'))
+        .collect();
+    let pairs: Vec<(usize, usize)> = found
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .collect();
+    let (first, last) = pairs
+        .iter()
+        .find(|(first, last)| *first <= col && col <= *last)
+        .or_else(|| pairs.iter().find(|(first, _)| *first > col))
+        .copied()?;
+
+    if inner {
+        if last == first + 1 {
+            return None;
+        }
+        return Some(chars_between(line, first + 1, last - 1));
+    }
+    let (mut start, mut end) = (first, last);
+    while end + 1 < chars.len() && is_blank(chars[end + 1]) {
+        end += 1;
+    }
+    if end == last {
+        while start > 0 && is_blank(chars[start - 1]) {
+            start -= 1;
+        }
+    }
+    Some(chars_between(line, start, end))
+}
+
+/// it and at, the text between a tag such as <a href=".."> and its </a>, or with the tags
+/// themselves for at. tags that are never closed and ones like <br/> are skipped
+fn tag(
+    content_buffer: &GapBuffer<GapBuffer<char>>,
+    cursor: (usize, usize),
+    inner: bool,
+    count: usize,
+) -> Option<TextRange> {
+    // the whole buffer as one run of chars, with the position in the buffer of each
+    let mut chars = Vec::new();
+    let mut positions = Vec::new();
+    for (line, text) in content_buffer.get_lines().iter().enumerate() {
+        for (col, c) in text.chars().chain(['\n']).enumerate() {
+            chars.push(c);
+            positions.push((line, col));
+        }
+    }
+    let at = positions.iter().position(|pos| *pos >= cursor)?;
+
+    // (open start, open end, close start, close end) of every pair of tags
+    let mut pairs = Vec::new();
+    let mut open_tags: Vec<(String, usize, usize)> = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] != '<' {
+            i += 1;
+            continue;
+        }
+        let end = match chars[i..].iter().position(|c| *c == '>') {
+            Some(offset) => i + offset,
+            None => break,
+        };
+        let body = &chars[i + 1..end];
+        let closing = body.first() == Some(&'/');
+        let name: String = body
+            .iter()
+            .skip(usize::from(closing))
+            .take_while(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | ':' | '.'))
+            .collect();
+        if !name.is_empty() && closing {
+            if let Some(open) = open_tags.iter().rposition(|(open, _, _)| *open == name) {
+                let (_, open_start, open_end) = open_tags[open].clone();
+                open_tags.truncate(open);
+                pairs.push((open_start, open_end, i, end));
+            }
+        } else if !name.is_empty() && body.last() != Some(&'/') {
+            open_tags.push((name, i, end));
+        }
+        i = end + 1;
+    }
+
+    let mut around: Vec<_> = pairs
+        .into_iter()
+        .filter(|(open_start, _, _, close_end)| *open_start <= at && at <= *close_end)
+        .collect();
+    around.sort_by_key(|(open_start, ..)| std::cmp::Reverse(*open_start));
+    let (open_start, open_end, close_start, close_end) = *around.get(count - 1)?;
+    let (start, end) = match inner {
+        true if close_start == open_end + 1 => return None,
+        true => (open_end + 1, close_start - 1),
+        false => (open_start, close_end),
+    };
+    Some(TextRange {
+        kind: RangeKind::Char,
+        start: positions[start],
+        end: positions[end],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_of(
+        text: &str,
+        cursor: (usize, usize),
+        key: char,
+        inner: bool,
+        count: usize,
+    ) -> Vec<String> {
+        let content_buffer = GapBuffer::build_nested(text);
+        let object = TextObject::from_key(key).unwrap();
+        match select(&content_buffer, cursor, object, inner, count) {
+            Some(range) => crate::ops::get_text(&content_buffer, &range),
+            None => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn words() {
+        let text = "foo bar.baz  qux\n";
+        assert_eq!(text_of(text, (0, 5), 'w', true, 1), ["bar"]);
+        // punctuation after the word rather than white, so the white before it goes instead
+        assert_eq!(text_of(text, (0, 5), 'w', false, 1), [" bar"]);
+        assert_eq!(text_of(text, (0, 1), 'w', false, 1), ["foo "]);
+        assert_eq!(text_of(text, (0, 1), 'w', true, 3), ["foo bar"]);
+        assert_eq!(text_of(text, (0, 5), 'W', false, 1), ["bar.baz  "]);
+        // no white after the last word, so the white before it goes instead
+        assert_eq!(text_of(text, (0, 14), 'w', false, 1), ["  qux"]);
+    }
+
+    #[test]
+    fn brackets_and_quotes() {
+        let text = "f(a, (b)) {\n    x = \"say \\\"hi\\\"\";\n}\n";
+        assert_eq!(text_of(text, (0, 3), '(', true, 1), ["a, (b)"]);
+        assert_eq!(text_of(text, (0, 6), 'b', false, 1), ["(b)"]);
+        assert_eq!(text_of(text, (0, 6), ')', false, 2), ["(a, (b))"]);
+        assert_eq!(
+            text_of(text, (1, 4), '{', true, 1),
+            ["    x = \"say \\\"hi\\\"\";"]
+        );
+        assert_eq!(text_of(text, (1, 4), '"', true, 1), ["say \\\"hi\\\""]);
+        assert_eq!(text_of(text, (0, 0), '[', true, 1), Vec::<String>::new());
+    }
+
+    #[test]
+    fn sentences_paragraphs_and_tags() {
+        let text = "One two.  Three four.\n\nnext\npara\n";
+        assert_eq!(text_of(text, (0, 12), 's', true, 1), ["Three four."]);
+        assert_eq!(text_of(text, (0, 2), 's', false, 1), ["One two.  "]);
+        assert_eq!(text_of(text, (2, 0), 'p', true, 1), ["next", "para"]);
+        assert_eq!(
+            text_of(text, (0, 0), 'p', false, 1),
+            ["One two.  Three four.", ""]
+        );
+
+        let html = "<div><p>hi <b>there</b></p>\n</div>\n";
+        assert_eq!(text_of(html, (0, 15), 't', true, 1), ["there"]);
+        assert_eq!(
+            text_of(html, (0, 15), 't', false, 2),
+            ["<p>hi <b>there</b></p>"]
+        );
+        assert_eq!(
+            text_of(html, (0, 2), 't', true, 1),
+            ["<p>hi <b>there</b></p>", ""]
+        );
+    }
+}
@@ -1,16 +1,25 @@
+use crate::config::{Cursor, Options};
+use crate::ops::TextRange;
+use crate::search;
+use crate::window::Rect;
 use crate::{EditorMode, EditorState};
-use std::io::{self, Write};
-use std::str::Lines;
-use terminol::cursor;
-use terminol::{Colors, Cursor};
+use gap_buffer::GapBuffer;
+use terminol::{char_width, Cell, Colors, CursorShape, Screen, Style};
 
 const INFO_BAR_ROW_OFFSET: u32 = 1;
 const CURSOR_LOCATION_COL_OFFSET: u32 = 15;
 const EDITOR_MODE_COL_OFFSET: u32 = 10;
+const MODIFIED_COL: usize = 2;
+/// rows at the bottom of the terminal that belong to the information bar and the command row
+const RESERVED_ROWS: usize = 2;
+/// the columns from one tab stop to the next
+const TABSTOP: usize = 8;
 
 struct InformationBar {
     length: u32,
     row: u32,
+    /// the first column of the bar, which is 1 unless the window is to the right of another
+    col: u32,
     command_row: u32,
     cursor_location_col: u32,
     editor_mode_col: u32,
@@ -21,137 +30,848 @@ impl InformationBar {
         InformationBar {
             length: term_attr.ws_col as u32,
             row: (term_attr.ws_row as u32) - INFO_BAR_ROW_OFFSET,
+            col: 1,
             command_row: (term_attr.ws_row as u32),
             cursor_location_col: (term_attr.ws_col as u32) - CURSOR_LOCATION_COL_OFFSET,
             editor_mode_col: EDITOR_MODE_COL_OFFSET,
         }
     }
+    /// the status line on the last row of the window taking up `rect`, which is the whole
+    /// information bar when there is only the one window
+    fn window(term_attr: &libc::winsize, rect: &Rect) -> Self {
+        let col = rect.col as u32 + 1;
+        InformationBar {
+            length: rect.width as u32,
+            row: (rect.row + rect.height) as u32,
+            col,
+            cursor_location_col: col
+                + (rect.width as u32).saturating_sub(CURSOR_LOCATION_COL_OFFSET),
+            ..InformationBar::new(term_attr)
+        }
+    }
 }
 
-pub fn update_tui(editor_state: &mut EditorState) {
-    let window_inf = InformationBar::new(&terminol::get_terminal_size());
-    let mode = editor_state.editor_mode.value();
+/// a window as it is drawn, the buffer it shows and where on the screen it goes. `cursor` is
+/// 1-indexed like the one in EditorState
+pub struct Pane<'a> {
+    pub content_buffer: &'a GapBuffer<GapBuffer<char>>,
+    pub viewport: &'a mut Viewport,
+    pub rect: Rect,
+    pub cursor: Cursor,
+    pub modified: bool,
+    /// the name of the file, shown on the status line once the screen is split
+    pub name: Option<&'a str>,
+}
 
-    match editor_state.editor_mode {
-        EditorMode::Command => {
-            if editor_state.previous_mode == EditorMode::Command {
-            } else {
-                draw_command_field(&window_inf);
-            }
+/// what the tabline shows for a tab page, the buffer in the window being edited on it
+pub struct Tab {
+    pub name: String,
+    pub modified: bool,
+    /// whether it is the tab page being edited
+    pub current: bool,
+}
+
+/// the part of the file that is shown in the terminal. `top` is the 0-indexed line drawn on the
+/// first row, `height` is the number of rows left over once the information bar and command row
+/// have been reserved. `left` is the first column shown when lines aren't wrapped
+#[derive(Clone, Copy, Debug)]
+pub struct Viewport {
+    pub top: usize,
+    pub left: usize,
+    pub height: usize,
+    pub width: usize,
+}
+
+impl Viewport {
+    pub fn new(term_attr: &libc::winsize) -> Self {
+        let mut viewport = Viewport {
+            top: 0,
+            left: 0,
+            height: 0,
+            width: 0,
+        };
+        viewport.resize(term_attr);
+        viewport
+    }
+    pub fn resize(&mut self, term_attr: &libc::winsize) {
+        self.height = (term_attr.ws_row as usize)
+            .saturating_sub(RESERVED_ROWS)
+            .max(1);
+        self.width = term_attr.ws_col as usize;
+    }
+    /// the rows and columns of a window taking up `rect`, less its status line and its border
+    fn fit(&mut self, rect: &Rect, border: bool) {
+        self.height = rect.height.saturating_sub(1).max(1);
+        self.width = rect.width.saturating_sub(border as usize).max(1);
+    }
+    /// scrolls for the 0-indexed `line` and `col` to be on screen
+    fn follow(
+        &mut self,
+        content_buffer: &GapBuffer<GapBuffer<char>>,
+        options: &Options,
+        (line, col): (usize, usize),
+    ) {
+        if options.wrap {
+            let width = self.width;
+            self.left = 0;
+            self.scroll_to_wrapped(line, |line| {
+                screen_rows(content_buffer, line, width, options.linebreak)
+            });
+        } else {
+            let cells = content_buffer.get(line).map(line_cells).unwrap_or_default();
+            let col = shown_col(&cells, col);
+            let wide = cells
+                .get(col + 1)
+                .is_some_and(|cell| cell.c == Cell::CONTINUATION);
+            self.scroll_to(line);
+            // both halves of a wide char are brought on screen
+            self.side_scroll_to(col + wide as usize);
+            self.side_scroll_to(col);
         }
-        _ => {
-            if editor_state.previous_mode == EditorMode::Command {
-                cursor::restore_cursor_position();
-                editor_state.previous_mode = editor_state.editor_mode;
-            } else {
-                draw_info_tui(&window_inf);
-                draw_mode(&window_inf, &mode);
-                update_cursor(editor_state);
-            }
+    }
+    pub fn bottom(&self) -> usize {
+        self.top + self.height - 1
+    }
+    /// scrolls the least amount needed for `line` (0-indexed) to be on screen, which is what
+    /// makes j and k scroll once the cursor reaches an edge
+    pub fn scroll_to(&mut self, line: usize) {
+        if line < self.top {
+            self.top = line;
+        } else if line > self.bottom() {
+            self.top = line + 1 - self.height;
+        }
+    }
+    /// with wrap on a line can take up several rows, so scrolling down has to keep going until
+    /// every row from `top` to the end of `line` fits. `rows` gives the rows a line takes
+    pub fn scroll_to_wrapped(&mut self, line: usize, rows: impl Fn(usize) -> usize) {
+        if line < self.top {
+            self.top = line;
+        }
+        while self.top < line && (self.top..=line).map(&rows).sum::<usize>() > self.height {
+            self.top += 1;
+        }
+    }
+    /// with wrap off, scrolls sideways the least amount needed for the 0-indexed `col` to be
+    /// on screen
+    pub fn side_scroll_to(&mut self, col: usize) {
+        if col < self.left {
+            self.left = col;
+        } else if col >= self.left + self.width {
+            self.left = col + 1 - self.width;
+        }
+    }
+    /// zz, puts `line` in the middle of the screen
+    pub fn center_on(&mut self, line: usize) {
+        self.top = line.saturating_sub(self.height / 2);
+    }
+    /// zt, puts `line` on the first row
+    pub fn top_on(&mut self, line: usize) {
+        self.top = line;
+    }
+    /// zb, puts `line` on the last row
+    pub fn bottom_on(&mut self, line: usize) {
+        self.top = (line + 1).saturating_sub(self.height);
+    }
+}
+
+/// draws a frame with the tabline when there are `tabs` to pick from, every window in `panes`,
+/// `active` being the index of the one being edited, and then the command row. the terminal is
+/// only sent what changed since the frame before
+pub fn update_tui(
+    screen: &mut Screen,
+    editor_state: &mut EditorState,
+    tabs: &[Tab],
+    panes: &mut [Pane],
+    active: usize,
+) {
+    screen.begin_frame();
+    let term_attr = terminol::get_terminal_size();
+    let window_inf = InformationBar::new(&term_attr);
+    let mode = editor_state.mode_label();
+    let split = panes.len() > 1;
+
+    for pane in panes.iter_mut() {
+        // a window that doesn't reach the right edge of the screen has a border along it
+        let border = pane.rect.col + pane.rect.width < term_attr.ws_col as usize;
+        pane.viewport.fit(&pane.rect, border);
+        let cursor = (pane.cursor.line - 1, pane.cursor.col - 1);
+        pane.viewport
+            .follow(pane.content_buffer, &editor_state.options, cursor);
+    }
+
+    if tabs.len() > 1 {
+        draw_tabline(screen, &window_inf, tabs);
+    }
+    for (i, pane) in panes.iter().enumerate() {
+        // the match incsearch is showing stands out like a selection would, only the window
+        // being edited has either
+        let selection = match i == active {
+            true => editor_state.selection().or(editor_state.search.current),
+            false => None,
+        };
+        let matches = highlighted(editor_state, pane.content_buffer, pane.viewport);
+        draw_text_area(
+            screen,
+            pane.content_buffer,
+            pane.viewport,
+            &pane.rect,
+            &editor_state.options,
+            selection.as_ref(),
+            &matches,
+        );
+        if pane.viewport.width < pane.rect.width {
+            draw_border(screen, &pane.rect, pane.viewport.height);
         }
     }
-    io::stdout()
-        .flush()
-        .unwrap_or_else(|e| panic!("io error occurred during flush: {e}"));
+    if !editor_state.output.is_empty() {
+        draw_output(screen, &window_inf, &editor_state.output);
+        screen.flush();
+        return;
+    }
+    for (i, pane) in panes.iter().enumerate() {
+        let status = InformationBar::window(&term_attr, &pane.rect);
+        let name = pane.name.filter(|_| split);
+        draw_info_tui(
+            screen,
+            &status,
+            &pane.cursor,
+            pane.modified,
+            name,
+            i == active,
+        );
+    }
+    update_cursor(screen, editor_state);
+    // the cursor is left at the end of the command line or the pattern being typed
+    let prompt = match editor_state.editor_mode {
+        EditorMode::Command => Some(':'),
+        EditorMode::Search if editor_state.search.prompt_forward => Some('/'),
+        EditorMode::Search => Some('?'),
+        _ => None,
+    };
+    if let Some(prompt) = prompt {
+        draw_command_field(screen, &window_inf, prompt, &editor_state.command);
+        screen.flush();
+        return;
+    }
+    match &editor_state.message {
+        Some(message) => draw_message(screen, &window_inf, message),
+        None => draw_mode(screen, &window_inf, &mode),
+    }
+    // the editor owns the cursor position, the terminal is only told where to draw it
+    if let Some(pane) = panes.get(active) {
+        let (row, col) = cursor_screen_position(
+            pane.content_buffer,
+            pane.viewport,
+            &editor_state.options,
+            &pane.cursor,
+        );
+        screen.set_cursor(pane.rect.row + row - 1, pane.rect.col + col - 1);
+    }
+    screen.flush();
 }
 
-fn update_cursor(editor_state: &mut EditorState) {
+fn update_cursor(screen: &mut Screen, editor_state: &EditorState) {
     match editor_state.editor_mode {
-        EditorMode::Insert | EditorMode::Command => cursor::enable_bar_cursor(),
-        _ => cursor::enable_standard_cursor(),
+        EditorMode::Insert | EditorMode::Command | EditorMode::Search => {
+            screen.set_cursor_shape(CursorShape::Bar)
+        }
+        _ => screen.set_cursor_shape(CursorShape::Block),
     }
 }
 
-/// draws the tui information bar including green background and default cursor position
-/// (1,1)
-fn draw_info_tui(window_inf: &InformationBar) {
-    let cursor = Cursor::get_cursor_coords();
-    cursor::save_cursor_position();
-    cursor::move_cursor_to(window_inf.row.try_into().unwrap(), 1);
+/// draws the tui information bar of a window, red for the one being edited and grey for the
+/// others, with a [+] while there are unsaved changes and the cursor position held by the
+/// editor. once the screen is split the name of the file goes in front of the [+]
+fn draw_info_tui(
+    screen: &mut Screen,
+    window_inf: &InformationBar,
+    cursor: &Cursor,
+    modified: bool,
+    name: Option<&str>,
+    active: bool,
+) {
+    let row = window_inf.row as usize - 1;
+    let col = window_inf.col as usize - 1;
+    let length = window_inf.length as usize;
+    let color = match active {
+        true => Colors::Red as i32,
+        false => Colors::Grey as i32,
+    };
+    let style = Style::background(color);
+    screen.fill(row, col, length, style);
 
-    // editor_data.cursor.mode(cursor::modes::bold);
-    let color = Colors::Red as i32;
-    cursor::set_background(color);
+    // nothing on the bar may spill over into the window next to it
+    let mut put = |at: usize, text: &str| {
+        let text: String = text.chars().take(length.saturating_sub(at)).collect();
+        screen.put_str(row, col + at, &text, style);
+    };
+    let label = match (name, modified) {
+        (Some(name), true) => format!("{name} [+]"),
+        (Some(name), false) => String::from(name),
+        (None, true) => String::from("[+]"),
+        (None, false) => String::new(),
+    };
+    put(MODIFIED_COL - 1, &label);
+    let location_col = (window_inf.cursor_location_col - window_inf.col) as usize;
+    put(location_col, &format!("({},{})", cursor.line, cursor.col));
+}
 
-    let bar = std::iter::repeat(" ")
-        .take(window_inf.length as usize)
-        .collect::<String>();
+/// the top row, with a label for each tab page. the one being edited stands out in red
+fn draw_tabline(screen: &mut Screen, window_inf: &InformationBar, tabs: &[Tab]) {
+    let mut written = 0;
+    for tab in tabs {
+        let color = match tab.current {
+            true => Colors::Red as i32,
+            false => Colors::Grey as i32,
+        };
+        let modified = if tab.modified { " [+]" } else { "" };
+        let label = format!(" {}{modified} ", tab.name);
+        if written >= window_inf.length as usize {
+            break;
+        }
+        written += screen.put_str(0, written, &label, Style::background(color));
+    }
+}
 
-    write!(io::stdout(), "{}", bar).unwrap_or_else(|e| panic!("failed io operation: {e}"));
+/// the column between a window and the one to the right of it, down the rows of its text
+fn draw_border(screen: &mut Screen, rect: &Rect, rows: usize) {
+    for row in 0..rows {
+        screen.put(
+            rect.row + row,
+            rect.col + rect.width - 1,
+            '|',
+            Style::reverse(),
+        );
+    }
+}
 
-    draw_cursor_location(&window_inf, color, cursor.line, cursor.col);
+/// the command row while a command line or a search pattern is typed after `prompt`, which is
+/// one of : / or ?
+fn draw_command_field(
+    screen: &mut Screen,
+    window_inf: &InformationBar,
+    prompt: char,
+    command: &str,
+) {
+    let row = window_inf.command_row as usize - 1;
+    let written = screen.put_str(row, 0, &format!("{prompt}{command}"), Style::PLAIN);
+    screen.set_cursor(row, written);
+}
 
-    cursor::restore_cursor_position();
-    cursor::reset_modes();
+fn draw_mode(screen: &mut Screen, window_inf: &InformationBar, mode: &str) {
+    screen.put_str(
+        window_inf.command_row as usize - 1,
+        window_inf.editor_mode_col as usize - 1,
+        mode,
+        Style::PLAIN,
+    );
 }
 
-fn draw_cursor_location(window_inf: &InformationBar, color: i32, line: usize, col: usize) {
-    cursor::move_cursor_to(
-        window_inf.row.try_into().unwrap(),
-        window_inf.cursor_location_col.try_into().unwrap(),
+/// puts `message` in the command row
+fn draw_message(screen: &mut Screen, window_inf: &InformationBar, message: &str) {
+    screen.put_str(
+        window_inf.command_row as usize - 1,
+        0,
+        message,
+        Style::PLAIN,
     );
+}
+
+/// draws lines of output such as :undolist over the bottom of the screen, ending with the same
+/// prompt vim uses. the last row is left for the prompt and the cursor waits after it
+fn draw_output(screen: &mut Screen, window_inf: &InformationBar, output: &[String]) {
+    let rows = window_inf.command_row as usize;
+    let shown = output.len().min(rows - 1);
+    let first_row = rows - 1 - shown;
 
-    cursor::set_background(color);
+    for (i, line) in output[output.len() - shown..].iter().enumerate() {
+        screen.fill(first_row + i, 0, screen.cols(), Style::PLAIN);
+        screen.put_str(first_row + i, 0, line, Style::PLAIN);
+    }
+    let prompt = "Press ENTER or type command to continue";
+    let written = screen.put_str(rows - 1, 0, prompt, Style::PLAIN);
+    screen.set_cursor(rows - 1, written);
+}
 
-    write!(io::stdout(), "({},{})", line, col)
-        .unwrap_or_else(|e| panic!("failed io operation: {e}"));
+/// a column of a line on screen
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Column {
+    /// the index in the line of the char it shows
+    index: usize,
+    c: char,
+    /// a combining mark that goes over `c`
+    mark: Option<char>,
 }
 
-fn draw_command_field(window_inf: &InformationBar) {
-    draw_line(
-        window_inf.command_row.try_into().unwrap(),
-        window_inf.length.try_into().unwrap(),
-        Colors::Black as i32,
-    );
-    cursor::move_cursor_to(window_inf.command_row.try_into().unwrap(), 1);
+impl Column {
+    fn new(index: usize, c: char) -> Column {
+        Column {
+            index,
+            c,
+            mark: None,
+        }
+    }
+}
 
-    write!(io::stdout(), ":").unwrap_or_else(|e| panic!("failed io operation: {e}"));
+/// one row of the screen filled by (part of) a line of the file
+struct ScreenRow {
+    line: usize,
+    /// the column on screen of the line the row starts at
+    first_col: usize,
+    /// what the row shows, see line_cells
+    cells: Vec<Column>,
+    line_len: usize,
+    /// the columns on screen the whole line takes up
+    line_width: usize,
+    /// whether the line ends on this row, which is where a selected newline is shown
+    ends_line: bool,
 }
 
-fn draw_mode(window_inf: &InformationBar, mode: &str) {
-    cursor::save_cursor_position();
-    cursor::move_cursor_to(
-        window_inf.command_row.try_into().unwrap(),
-        window_inf.editor_mode_col.try_into().unwrap(),
-    );
+/// draws every row of the viewport from the content buffer, in the window taking up `rect`.
+/// rows past the end of the file are marked with a '~' like vim does. nothing is ever written
+/// below the viewport or to the right of it, so the status line and the windows around it are
+/// left alone.
+///
+/// with wrap on, a line longer than the screen carries on over as many rows as it needs. with
+/// wrap off it is cut at the edge of the screen and scrolled sideways by `viewport.left`.
+/// whatever `selection` covers is drawn in reverse video and the search `matches` in yellow
+fn draw_text_area(
+    screen: &mut Screen,
+    content_buffer: &GapBuffer<GapBuffer<char>>,
+    viewport: &Viewport,
+    rect: &Rect,
+    options: &Options,
+    selection: Option<&TextRange>,
+    matches: &[TextRange],
+) {
+    let mut rows: Vec<Option<ScreenRow>> = Vec::with_capacity(viewport.height);
+    let mut line = viewport.top;
+    while rows.len() < viewport.height {
+        let (cells, line_len) = match content_buffer.get(line) {
+            Some(line_buf) => (line_cells(line_buf), line_text(line_buf).count()),
+            None => {
+                rows.push(None);
+                continue;
+            }
+        };
+        if options.wrap {
+            let starts = wrap_starts(&shown_text(&cells), viewport.width, options.linebreak);
+            for (i, start) in starts.iter().enumerate() {
+                let end = starts.get(i + 1).copied().unwrap_or(cells.len());
+                rows.push(Some(ScreenRow {
+                    line,
+                    first_col: *start,
+                    cells: cells[*start..end]
+                        .iter()
+                        .take(viewport.width)
+                        .copied()
+                        .collect(),
+                    line_len,
+                    line_width: cells.len(),
+                    ends_line: i + 1 == starts.len(),
+                }));
+            }
+        } else {
+            rows.push(Some(ScreenRow {
+                line,
+                first_col: viewport.left,
+                cells: cells
+                    .iter()
+                    .skip(viewport.left)
+                    .take(viewport.width)
+                    .copied()
+                    .collect(),
+                line_len,
+                line_width: cells.len(),
+                ends_line: true,
+            }));
+        }
+        line += 1;
+    }
+
+    for (row, screen_row) in rows.iter().take(viewport.height).enumerate() {
+        let origin = (rect.row + row, rect.col);
+        match screen_row {
+            Some(screen_row) => draw_row(
+                screen,
+                origin,
+                screen_row,
+                selection,
+                matches,
+                viewport.width,
+            ),
+            None => screen.put(origin.0, origin.1, '~', Style::PLAIN),
+        }
+    }
+}
+
+/// draws one row from `origin`, the 0-indexed row and column of the screen it starts at
+fn draw_row(
+    screen: &mut Screen,
+    (row, first): (usize, usize),
+    screen_row: &ScreenRow,
+    selection: Option<&TextRange>,
+    matches: &[TextRange],
+    width: usize,
+) {
+    let cols = |range: &TextRange| range.cols_on(screen_row.line, screen_row.line_len);
+    let selected = selection.and_then(cols);
+    let matched: Vec<(usize, usize)> = matches.iter().filter_map(cols).collect();
+    let is_selected = |col: usize| selected.is_some_and(|(from, to)| from <= col && col < to);
+    let is_matched = |col: usize| matched.iter().any(|(from, to)| *from <= col && col < *to);
+    let mut cells = screen_row.cells.clone();
+    // a selected newline shows up as a single reversed blank after the text of its line
+    let newline = screen_row.line_len;
+    let end = screen_row.line_width;
+    if screen_row.ends_line
+        && is_selected(newline)
+        && end >= screen_row.first_col
+        && end - screen_row.first_col < width
+    {
+        cells.push(Column::new(newline, ' '));
+    }
+    for (i, cell) in cells.iter().enumerate() {
+        let col = cell.index;
+        let style = if is_selected(col) {
+            Style::reverse()
+        } else if is_matched(col) && col < screen_row.line_len {
+            Style {
+                fg: Some(Colors::Black as i32),
+                bg: Some(Colors::Yellow as i32),
+                ..Style::PLAIN
+            }
+        } else {
+            Style::PLAIN
+        };
+        let wide = cells
+            .get(i + 1)
+            .is_some_and(|next| next.c == Cell::CONTINUATION);
+        let c = match cell.c {
+            // the left half is what draws a wide char, a right half without it is a blank
+            Cell::CONTINUATION if i > 0 => continue,
+            Cell::CONTINUATION => ' ',
+            // and a wide char cut off by the edge of the window is a blank too
+            _ if wide && i + 1 >= width => ' ',
+            c => c,
+        };
+        screen.put(row, first + i, c, style);
+        if let Some(mark) = cell.mark.filter(|_| c == cell.c) {
+            screen.put(row, first + i + 1, mark, style);
+        }
+    }
+}
 
-    write!(io::stdout(), "{}", mode).unwrap_or_else(|e| panic!("failed io operation: {e}"));
-    cursor::restore_cursor_position();
+/// the matches on screen to highlight. while a pattern is typed with incsearch they are those of
+/// the pattern so far, otherwise those of the last search, unless :nohlsearch put them away
+fn highlighted(
+    editor_state: &EditorState,
+    content_buffer: &GapBuffer<GapBuffer<char>>,
+    viewport: &Viewport,
+) -> Vec<TextRange> {
+    let options = &editor_state.options;
+    let search = &editor_state.search;
+    if !options.hlsearch {
+        return Vec::new();
+    }
+    let text = match editor_state.editor_mode {
+        EditorMode::Search if options.incsearch => search::typed_pattern(editor_state),
+        EditorMode::Search => None,
+        _ if search.highlight => search.pattern.as_deref(),
+        _ => None,
+    };
+    let regex = text
+        .filter(|text| !text.is_empty())
+        .map(|text| search::compile(text, options));
+    match regex {
+        Some(Ok(regex)) => search::matches_between(
+            content_buffer,
+            &regex,
+            viewport.top,
+            viewport.top + viewport.height,
+        ),
+        _ => Vec::new(),
+    }
 }
 
-fn draw_line(line_num: usize, length: usize, color: i32) {
-    cursor::save_cursor_position();
-    cursor::move_cursor_to(line_num, 1);
+fn line_text(line_buf: &GapBuffer<char>) -> impl Iterator<Item = &char> {
+    line_buf.iter().take_while(|c| **c != '\n')
+}
 
-    cursor::set_background(color);
+/// the columns a line takes up on screen, each a single cell of it. a tab is blanks up to the
+/// next tab stop and any other control char is shown the way it is typed, like ^A. a wide char
+/// is followed by a Cell::CONTINUATION for its right half, and a combining mark goes over the
+/// char before it instead of taking a column, only the first one is shown
+fn line_cells(line_buf: &GapBuffer<char>) -> Vec<Column> {
+    let mut cells: Vec<Column> = Vec::new();
+    for (i, c) in line_text(line_buf).enumerate() {
+        match *c {
+            '\t' => {
+                let width = TABSTOP - cells.len() % TABSTOP;
+                cells.extend(std::iter::repeat_n(Column::new(i, ' '), width));
+            }
+            c if c.is_ascii_control() => {
+                cells.push(Column::new(i, '^'));
+                cells.push(Column::new(i, (c as u8 ^ 0x40) as char));
+            }
+            c if c.is_control() => {
+                let code = format!("<{:x}>", c as u32);
+                cells.extend(code.chars().map(|shown| Column::new(i, shown)));
+            }
+            c => match char_width(c) {
+                0 => {
+                    let last = cells.iter().rposition(|cell| cell.c != Cell::CONTINUATION);
+                    match last {
+                        Some(last) => cells[last].mark = cells[last].mark.or(Some(c)),
+                        // with nothing before it to go over, a mark gets a blank of its own
+                        None => cells.push(Column {
+                            mark: Some(c),
+                            ..Column::new(i, ' ')
+                        }),
+                    }
+                }
+                2 => {
+                    cells.push(Column::new(i, c));
+                    cells.push(Column::new(i, Cell::CONTINUATION));
+                }
+                _ => cells.push(Column::new(i, c)),
+            },
+        }
+    }
+    cells
+}
 
-    let bar = std::iter::repeat(" ").take(length).collect::<String>();
+fn shown_text(cells: &[Column]) -> Vec<char> {
+    cells.iter().map(|cell| cell.c).collect()
+}
 
-    write!(io::stdout(), "{}", bar).unwrap_or_else(|e| panic!("io error{e}"));
+/// the column on screen the 0-indexed `col` of a line starts at, given its `cells`. a col past
+/// the end of the line is just after it
+fn shown_col(cells: &[Column], col: usize) -> usize {
+    cells
+        .iter()
+        .position(|cell| cell.index >= col)
+        .unwrap_or(cells.len())
+}
 
-    cursor::restore_cursor_position();
+/// the index of the first column on each screen row a line is wrapped over. a line always gets at
+/// least one row, even when it is empty. with linebreak the break goes after the last blank
+/// that fits, a blank landing just past the edge is left hanging off it. a word longer than a
+/// whole row still gets broken at the edge of the screen. a wide char that would be broken in
+/// two starts the next row instead
+fn wrap_starts(chars: &[char], width: usize, linebreak: bool) -> Vec<usize> {
+    let width = width.max(1);
+    let mut starts = vec![0];
+    let mut start = 0;
+    while chars.len() - start > width {
+        let blank = chars[start + 1..=start + width]
+            .iter()
+            .rposition(|c| *c == ' ')
+            .map(|i| start + 1 + i + 1);
+        let next = match blank {
+            Some(after_blank) if linebreak => after_blank.min(chars.len()),
+            _ => start + width,
+        };
+        start = match chars.get(next) {
+            Some(&Cell::CONTINUATION) if next - 1 > start => next - 1,
+            _ => next,
+        };
+        starts.push(start);
+    }
+    starts
 }
 
-pub fn update_line(line: String) {
-    cursor::save_cursor_position();
-    write!(io::stdout(), "{}", line).unwrap_or_else(|e| panic!("failed io operation: {e}"));
-    cursor::restore_cursor_position();
+/// how many screen rows the 0-indexed `line` takes up with wrap on
+fn screen_rows(
+    content_buffer: &GapBuffer<GapBuffer<char>>,
+    line: usize,
+    width: usize,
+    linebreak: bool,
+) -> usize {
+    match content_buffer.get(line) {
+        Some(line_buf) => wrap_starts(&shown_text(&line_cells(line_buf)), width, linebreak).len(),
+        None => 1,
+    }
 }
-pub fn update_below(start: usize, end: usize, mut content: Lines) {
-    cursor::save_cursor_position();
-    for i in start..end {
-        cursor::move_cursor_to(i + 1, 1);
-        let line = content.next().unwrap();
-        write!(io::stdout(), "{}", line).unwrap_or_else(|e| panic!("failed io operation: {e}"));
+
+/// the 1-indexed row and column on screen the cursor is drawn at, once wrapping or sideways
+/// scrolling is taken into account
+fn cursor_screen_position(
+    content_buffer: &GapBuffer<GapBuffer<char>>,
+    viewport: &Viewport,
+    options: &Options,
+    cursor: &Cursor,
+) -> (usize, usize) {
+    let line = cursor.line - 1;
+    let cells = content_buffer.get(line).map(line_cells).unwrap_or_default();
+    let col = shown_col(&cells, cursor.col - 1);
+    if !options.wrap {
+        return (line - viewport.top + 1, col - viewport.left + 1);
     }
-    cursor::restore_cursor_position();
+    let rows_above: usize = (viewport.top..line)
+        .map(|line| screen_rows(content_buffer, line, viewport.width, options.linebreak))
+        .sum();
+    let starts = wrap_starts(&shown_text(&cells), viewport.width, options.linebreak);
+    let row = starts.iter().rposition(|start| *start <= col).unwrap_or(0);
+    let screen_col = (col - starts[row] + 1).min(viewport.width);
+    (rows_above + row + 1, screen_col)
 }
 
-pub fn write_existing_file(file_contents: String) {
-    let mut line_num = 1;
-    for line in file_contents.lines() {
-        cursor::move_cursor_to(line_num, 1);
-        write!(io::stdout(), "{}", line).unwrap_or_else(|e| panic!("failed io operation: {e}"));
-        line_num += 1
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn starts(text: &str, width: usize, linebreak: bool) -> Vec<usize> {
+        let chars: Vec<char> = text.chars().collect();
+        wrap_starts(&chars, width, linebreak)
+    }
+
+    #[test]
+    fn wrap_at_the_edge_of_the_screen() {
+        assert_eq!(starts("", 4, false), [0]);
+        assert_eq!(starts("abcd", 4, false), [0]);
+        assert_eq!(starts("abcdefghij", 4, false), [0, 4, 8]);
+    }
+
+    #[test]
+    fn linebreak_breaks_after_blanks() {
+        assert_eq!(starts("one two three", 8, true), [0, 8]);
+        assert_eq!(starts("ab cdefgh ij", 6, true), [0, 3, 10]);
+        // a word longer than a row still has to be split somewhere
+        assert_eq!(starts("abcdefghij", 4, true), [0, 4, 8]);
+    }
+
+    #[test]
+    fn text_area_in_a_window() {
+        let content_buffer = GapBuffer::build_nested("abc\nde\n");
+        let viewport = Viewport {
+            top: 0,
+            left: 0,
+            height: 3,
+            width: 2,
+        };
+        let rect = Rect {
+            row: 1,
+            col: 2,
+            height: 4,
+            width: 3,
+        };
+        let mut screen = Screen::new(5, 6);
+        let options = Options::new();
+        draw_text_area(
+            &mut screen,
+            &content_buffer,
+            &viewport,
+            &rect,
+            &options,
+            None,
+            &[],
+        );
+        draw_border(&mut screen, &rect, viewport.height);
+        let row = |row: usize| -> String {
+            (0..6)
+                .map(|col| screen.get(row, col).map_or('?', |cell| cell.c))
+                .collect()
+        };
+        // the long line wraps within the window, which ends in its border
+        assert_eq!(row(0), "      ");
+        assert_eq!(row(1), "  ab| ");
+        assert_eq!(row(2), "  c | ");
+        assert_eq!(row(3), "  de| ");
+        assert_eq!(
+            screen.get(1, 4).map(|cell| cell.style),
+            Some(Style::reverse())
+        );
+    }
+
+    #[test]
+    fn tabs_and_control_chars_on_screen() {
+        let content_buffer = GapBuffer::build_nested("a\tb\tc\n\u{1}x\n");
+        let viewport = Viewport {
+            top: 0,
+            left: 0,
+            height: 3,
+            width: 12,
+        };
+        let rect = Rect {
+            row: 0,
+            col: 0,
+            height: 4,
+            width: 12,
+        };
+        let mut screen = Screen::new(4, 12);
+        let options = Options::new();
+        draw_text_area(
+            &mut screen,
+            &content_buffer,
+            &viewport,
+            &rect,
+            &options,
+            None,
+            &[],
+        );
+        let row = |row: usize| -> String {
+            (0..12)
+                .map(|col| screen.get(row, col).map_or('?', |cell| cell.c))
+                .collect()
+        };
+        // the tab after b runs to the next tab stop, past the edge of the window, so c wraps
+        assert_eq!(row(0), "a       b   ");
+        assert_eq!(row(1), "    c       ");
+        assert_eq!(row(2), "^Ax         ");
+
+        // the cursor is drawn where the char starts on screen
+        let cursor = |line, col| {
+            cursor_screen_position(&content_buffer, &viewport, &options, &Cursor { line, col })
+        };
+        assert_eq!(cursor(1, 3), (1, 9));
+        assert_eq!(cursor(1, 5), (2, 5));
+        assert_eq!(cursor(2, 2), (3, 3));
+    }
+
+    #[test]
+    fn wide_chars_and_combining_marks_on_screen() {
+        let content_buffer = GapBuffer::build_nested("ab漢字e\u{301}x\n");
+        let viewport = Viewport {
+            top: 0,
+            left: 0,
+            height: 3,
+            width: 5,
+        };
+        let rect = Rect {
+            row: 0,
+            col: 0,
+            height: 4,
+            width: 5,
+        };
+        let mut screen = Screen::new(4, 5);
+        let options = Options::new();
+        draw_text_area(
+            &mut screen,
+            &content_buffer,
+            &viewport,
+            &rect,
+            &options,
+            None,
+            &[],
+        );
+        let row = |row: usize| -> String {
+            (0..5)
+                .filter_map(|col| screen.get(row, col))
+                .filter(|cell| cell.c != Cell::CONTINUATION)
+                .flat_map(|cell| [Some(cell.c), cell.mark])
+                .flatten()
+                .collect()
+        };
+        // 字 would be split by the edge of the window, so it goes to the next row whole
+        assert_eq!(row(0), "ab漢 ");
+        assert_eq!(row(1), "字e\u{301}x ");
+
+        let cursor = |line, col| {
+            cursor_screen_position(&content_buffer, &viewport, &options, &Cursor { line, col })
+        };
+        assert_eq!(cursor(1, 4), (2, 1));
+        assert_eq!(cursor(1, 5), (2, 3));
+        // the mark takes no column of its own, x comes straight after the e
+        assert_eq!(cursor(1, 7), (2, 4));
     }
 }
@@ -0,0 +1,503 @@
+use gap_buffer::GapBuffer;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// first line of every undo file, bumped whenever the format changes
+const UNDO_FILE_HEADER: &str = "jbvim undo 1";
+
+/// a run of lines that one change replaced. undoing puts `before` back where `after` is,
+/// redoing does the opposite
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hunk {
+    pub start: usize,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+impl Hunk {
+    /// finds the lines that differ between two versions of a buffer by trimming the lines both
+    /// have in common at the start and at the end. None when nothing changed
+    fn diff(before: &[String], after: &[String]) -> Option<Hunk> {
+        let prefix = before
+            .iter()
+            .zip(after.iter())
+            .take_while(|(b, a)| b == a)
+            .count();
+        let max_suffix = before.len().min(after.len()) - prefix;
+        let suffix = before
+            .iter()
+            .rev()
+            .zip(after.iter().rev())
+            .take(max_suffix)
+            .take_while(|(b, a)| b == a)
+            .count();
+
+        if prefix == before.len() && prefix == after.len() {
+            return None;
+        }
+        Some(Hunk {
+            start: prefix,
+            before: before[prefix..before.len() - suffix].to_vec(),
+            after: after[prefix..after.len() - suffix].to_vec(),
+        })
+    }
+}
+
+/// one state of the buffer in the tree. the node at index 0 is the root, the file as it was
+/// before any change, and every other node's index doubles as its change number
+#[derive(Clone, Debug)]
+pub struct UndoNode {
+    pub parent: usize,
+    /// the child redo moves into, which is the one that was most recently made or visited
+    pub cur_child: Option<usize>,
+    pub hunk: Option<Hunk>,
+    /// where the cursor was when the change began, restored when it is undone or redone
+    pub cursor: (usize, usize),
+    /// seconds since the unix epoch when the change was made
+    pub time: u64,
+}
+
+/// lines and cursor captured when a change begins, so the change can be diffed once it ends
+struct PendingChange {
+    lines: Vec<String>,
+    cursor: (usize, usize),
+    changedtick: u64,
+}
+
+pub struct UndoTree {
+    pub nodes: Vec<UndoNode>,
+    pub current: usize,
+    pending: Option<PendingChange>,
+    /// how many of :global and :normal are running their commands, whose commits are ignored
+    /// so that all of it becomes one change
+    held: usize,
+    /// the line U restores and the text it restores it to
+    pub line_undo: Option<(usize, String)>,
+}
+
+impl Default for UndoTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UndoTree {
+    pub fn new() -> UndoTree {
+        UndoTree {
+            nodes: vec![UndoNode {
+                parent: 0,
+                cur_child: None,
+                hunk: None,
+                cursor: (1, 1),
+                time: now(),
+            }],
+            current: 0,
+            pending: None,
+            held: 0,
+            line_undo: None,
+        }
+    }
+    /// remembers the buffer as it is right now. every edit until the matching commit becomes a
+    /// single change, which is how a whole insert mode session is undone with one u. calling
+    /// this while a change is already open does nothing
+    pub fn begin(&mut self, content_buffer: &GapBuffer<GapBuffer<char>>) {
+        if self.pending.is_none() {
+            self.pending = Some(PendingChange {
+                lines: content_buffer.get_lines(),
+                cursor: content_buffer.cursor_position(),
+                changedtick: content_buffer.changedtick(),
+            });
+        }
+    }
+    /// keeps the change open through the commits made until release, which the commit after it
+    /// then closes
+    pub fn hold(&mut self) {
+        self.held += 1;
+    }
+    pub fn release(&mut self) {
+        self.held = self.held.saturating_sub(1);
+    }
+    pub fn is_open(&self) -> bool {
+        self.pending.is_some()
+    }
+    /// closes the change opened by begin and, if the buffer really changed, adds it to the tree
+    /// as a new child of the current state. returns whether a change was recorded
+    pub fn commit(&mut self, content_buffer: &GapBuffer<GapBuffer<char>>) -> bool {
+        if self.held > 0 {
+            return false;
+        }
+        let pending = match self.pending.take() {
+            Some(pending) => pending,
+            None => return false,
+        };
+        // nothing was edited, so there is no need to copy the lines to find that out
+        if content_buffer.changedtick() == pending.changedtick {
+            return false;
+        }
+        let hunk = match Hunk::diff(&pending.lines, &content_buffer.get_lines()) {
+            Some(hunk) => hunk,
+            None => return false,
+        };
+        self.track_line_undo(&hunk);
+
+        let node = self.nodes.len();
+        self.nodes.push(UndoNode {
+            parent: self.current,
+            cur_child: None,
+            hunk: Some(hunk),
+            cursor: pending.cursor,
+            time: now(),
+        });
+        self.nodes[self.current].cur_child = Some(node);
+        self.current = node;
+        true
+    }
+    /// keeps hold of the original text of the line being changed for U. a change to another
+    /// line, or to more than one line, starts over
+    fn track_line_undo(&mut self, hunk: &Hunk) {
+        if hunk.before.len() != 1 || hunk.after.len() != 1 {
+            self.line_undo = None;
+            return;
+        }
+        match &self.line_undo {
+            Some((line, _)) if *line == hunk.start => (),
+            _ => self.line_undo = Some((hunk.start, hunk.before[0].clone())),
+        }
+    }
+    /// u, steps back to the parent state. returns the cursor to restore, None at the root
+    pub fn undo(
+        &mut self,
+        content_buffer: &mut GapBuffer<GapBuffer<char>>,
+    ) -> Option<(usize, usize)> {
+        if self.current == 0 {
+            return None;
+        }
+        let node = &self.nodes[self.current];
+        if let Some(hunk) = &node.hunk {
+            content_buffer.replace_lines(hunk.start, hunk.after.len(), &hunk.before);
+        }
+        let cursor = node.cursor;
+        let parent = node.parent;
+        self.nodes[parent].cur_child = Some(self.current);
+        self.current = parent;
+        self.line_undo = None;
+        Some(cursor)
+    }
+    /// <C-r>, steps forward into the child that was made or visited last
+    pub fn redo(
+        &mut self,
+        content_buffer: &mut GapBuffer<GapBuffer<char>>,
+    ) -> Option<(usize, usize)> {
+        let child = self.nodes[self.current].cur_child?;
+        self.enter_child(child, content_buffer);
+        self.line_undo = None;
+        Some(self.nodes[child].cursor)
+    }
+    fn enter_child(&mut self, child: usize, content_buffer: &mut GapBuffer<GapBuffer<char>>) {
+        if let Some(hunk) = &self.nodes[child].hunk {
+            content_buffer.replace_lines(hunk.start, hunk.before.len(), &hunk.after);
+        }
+        self.nodes[self.current].cur_child = Some(child);
+        self.current = child;
+    }
+    /// g- and g+, moves to the state with the change number `target`, walking up to the common
+    /// ancestor and back down, so this can cross into other branches of the tree
+    pub fn goto_change(
+        &mut self,
+        target: usize,
+        content_buffer: &mut GapBuffer<GapBuffer<char>>,
+    ) -> Option<(usize, usize)> {
+        if target >= self.nodes.len() || target == self.current {
+            return None;
+        }
+        let mut path = vec![target];
+        while *path.last().unwrap() != 0 {
+            let parent = self.nodes[*path.last().unwrap()].parent;
+            path.push(parent);
+        }
+        let mut cursor = None;
+        while !path.contains(&self.current) {
+            cursor = self.undo(content_buffer);
+        }
+        let from = path.iter().position(|node| *node == self.current).unwrap();
+        for node in path[..from].iter().rev() {
+            self.enter_child(*node, content_buffer);
+            cursor = Some(self.nodes[*node].cursor);
+        }
+        self.line_undo = None;
+        cursor
+    }
+    /// the change number of the state the buffer is in
+    pub fn seq_cur(&self) -> usize {
+        self.current
+    }
+    /// the leaves of the tree as (change number, changes from the root, seconds since the
+    /// change) for :undolist
+    pub fn leaves(&self) -> Vec<(usize, usize, u64)> {
+        let now = now();
+        (1..self.nodes.len())
+            .filter(|node| self.nodes[*node].cur_child.is_none())
+            .map(|node| {
+                let mut depth = 0;
+                let mut walk = node;
+                while walk != 0 {
+                    depth += 1;
+                    walk = self.nodes[walk].parent;
+                }
+                (node, depth, now.saturating_sub(self.nodes[node].time))
+            })
+            .collect()
+    }
+}
+
+/// where the undo file for `file_name` lives. like vim's undodir every file gets its own undo
+/// file in one directory, named after the absolute path of the file with '/' turned into '%'
+pub fn undo_file_path(file_name: &str) -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_STATE_HOME") {
+        Some(state) => PathBuf::from(state),
+        None => PathBuf::from(env::var_os("HOME")?).join(".local/state"),
+    };
+    let path = fs::canonicalize(file_name).ok()?;
+    let name = path.to_string_lossy().replace('/', "%");
+    Some(dir.join("jbvim/undo").join(name))
+}
+
+/// FNV-1a hash of the file contents. the undo file records the hash of the text it was written
+/// for, so one left behind by a version of the file that was changed elsewhere is not applied
+pub fn content_hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+impl UndoTree {
+    /// writes the whole tree to `path`. a header line, the content hash and the current change
+    /// are followed by one line per node, each followed by the lines of its hunk. buffer lines
+    /// never hold a newline, so they are written as they are
+    pub fn write_file(&self, path: &Path, hash: u64) -> io::Result<()> {
+        let mut out = format!(
+            "{UNDO_FILE_HEADER}\nhash {hash:016x}\ncurrent {}\n",
+            self.current
+        );
+        for node in &self.nodes {
+            let cur_child = match node.cur_child {
+                Some(child) => child.to_string(),
+                None => String::from("-"),
+            };
+            let (start, before, after) = match &node.hunk {
+                Some(hunk) => (hunk.start.to_string(), &hunk.before[..], &hunk.after[..]),
+                None => (String::from("-"), &[][..], &[][..]),
+            };
+            out.push_str(&format!(
+                "node {} {} {} {} {} {} {} {}\n",
+                node.parent,
+                cur_child,
+                node.cursor.0,
+                node.cursor.1,
+                node.time,
+                start,
+                before.len(),
+                after.len()
+            ));
+            for line in before.iter().chain(after) {
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, out)
+    }
+    /// reads back a tree written by write_file. Ok(None) when there is no undo file, an error
+    /// message when there is one but it can't be used for a file whose contents hash to `hash`
+    pub fn read_file(path: &Path, hash: u64) -> Result<Option<UndoTree>, &'static str> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(_) => return Err("E822: Cannot open undo file for reading"),
+        };
+        let mut lines = contents.lines();
+        if lines.next() != Some(UNDO_FILE_HEADER) {
+            return Err("E823: Not an undo file");
+        }
+        let corrupted = "E825: Corrupted undo file";
+        let stored_hash = lines
+            .next()
+            .and_then(|line| line.strip_prefix("hash "))
+            .and_then(|hash| u64::from_str_radix(hash, 16).ok())
+            .ok_or(corrupted)?;
+        if stored_hash != hash {
+            return Err("File contents changed, cannot use undo info");
+        }
+        let current = lines
+            .next()
+            .and_then(|line| line.strip_prefix("current "))
+            .and_then(|current| current.parse().ok())
+            .ok_or(corrupted)?;
+
+        let mut nodes = Vec::new();
+        while let Some(line) = lines.next() {
+            let fields: Vec<&str> = match line.strip_prefix("node ") {
+                Some(fields) => fields.split(' ').collect(),
+                None => return Err(corrupted),
+            };
+            if fields.len() != 8 {
+                return Err(corrupted);
+            }
+            let number = |field: &str| field.parse::<usize>().map_err(|_| corrupted);
+            let optional = |field: &str| match field {
+                "-" => Ok(None),
+                field => number(field).map(Some),
+            };
+            let mut take_lines = |count: usize| -> Result<Vec<String>, &'static str> {
+                (0..count)
+                    .map(|_| lines.next().map(String::from).ok_or(corrupted))
+                    .collect()
+            };
+            let before = take_lines(number(fields[6])?)?;
+            let after = take_lines(number(fields[7])?)?;
+            let hunk = optional(fields[5])?.map(|start| Hunk {
+                start,
+                before,
+                after,
+            });
+            nodes.push(UndoNode {
+                parent: number(fields[0])?,
+                cur_child: optional(fields[1])?,
+                hunk,
+                cursor: (number(fields[2])?, number(fields[3])?),
+                time: fields[4].parse().map_err(|_| corrupted)?,
+            });
+        }
+
+        // every node but the root has to come after its parent, otherwise walking up the tree
+        // could loop forever
+        let links_ok = nodes.iter().enumerate().all(|(i, node)| {
+            (i == 0 || node.parent < i) && node.cur_child.is_none_or(|child| child < nodes.len())
+        });
+        if nodes.is_empty() || current >= nodes.len() || !links_ok {
+            return Err(corrupted);
+        }
+        Ok(Some(UndoTree {
+            nodes,
+            current,
+            pending: None,
+            held: 0,
+            line_undo: None,
+        }))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(s: &str) -> GapBuffer<GapBuffer<char>> {
+        GapBuffer::build_nested(s)
+    }
+
+    fn edit_line(content_buffer: &mut GapBuffer<GapBuffer<char>>, line: usize, text: &str) {
+        content_buffer.replace_lines(line, 1, &[String::from(text)]);
+        content_buffer.set_cursor_position((1, 1));
+    }
+
+    #[test]
+    fn undo_and_redo_a_change() {
+        let mut content_buffer = buffer("one\ntwo\nthree\n");
+        let mut tree = UndoTree::new();
+
+        tree.begin(&content_buffer);
+        edit_line(&mut content_buffer, 1, "TWO");
+        assert!(tree.commit(&content_buffer));
+
+        tree.undo(&mut content_buffer);
+        assert_eq!(content_buffer.get_lines(), ["one", "two", "three"]);
+        assert!(tree.undo(&mut content_buffer).is_none());
+
+        tree.redo(&mut content_buffer);
+        assert_eq!(content_buffer.get_lines(), ["one", "TWO", "three"]);
+    }
+
+    #[test]
+    fn nothing_recorded_without_an_edit() {
+        let mut content_buffer = buffer("one\ntwo\n");
+        let mut tree = UndoTree::new();
+
+        tree.begin(&content_buffer);
+        content_buffer.set_cursor_position((2, 2));
+        assert!(!tree.commit(&content_buffer));
+
+        // edits that put the text back the way it was are no change either
+        tree.begin(&content_buffer);
+        edit_line(&mut content_buffer, 0, "ONE");
+        edit_line(&mut content_buffer, 0, "one");
+        assert!(!tree.commit(&content_buffer));
+        assert_eq!(tree.nodes.len(), 1);
+    }
+
+    #[test]
+    fn time_travel_between_branches() {
+        let mut content_buffer = buffer("a\n");
+        let mut tree = UndoTree::new();
+
+        tree.begin(&content_buffer);
+        edit_line(&mut content_buffer, 0, "b");
+        tree.commit(&content_buffer);
+        tree.undo(&mut content_buffer);
+
+        // a new change after an undo starts a second branch from the root
+        tree.begin(&content_buffer);
+        edit_line(&mut content_buffer, 0, "c");
+        tree.commit(&content_buffer);
+        assert_eq!(tree.leaves().len(), 2);
+
+        // g- from change 2 lands on change 1, which is on the other branch
+        tree.goto_change(1, &mut content_buffer);
+        assert_eq!(content_buffer.get_lines(), ["b"]);
+        tree.goto_change(2, &mut content_buffer);
+        assert_eq!(content_buffer.get_lines(), ["c"]);
+    }
+
+    #[test]
+    fn undo_file_round_trip() {
+        let path = env::temp_dir().join(format!("jbvim-undo-test-{}", std::process::id()));
+        let mut content_buffer = buffer("one\ntwo\n");
+        let mut tree = UndoTree::new();
+
+        tree.begin(&content_buffer);
+        edit_line(&mut content_buffer, 1, "TWO");
+        tree.commit(&content_buffer);
+        let hash = content_hash(&content_buffer.get_content());
+        tree.write_file(&path, hash).unwrap();
+
+        // a file that changed since the undo file was written is left alone
+        assert!(UndoTree::read_file(&path, hash + 1).is_err());
+
+        let mut read = UndoTree::read_file(&path, hash).unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+        read.undo(&mut content_buffer);
+        assert_eq!(content_buffer.get_lines(), ["one", "two"]);
+    }
+
+    #[test]
+    fn diff_keeps_only_changed_lines() {
+        let before: Vec<String> = ["a", "b", "c"].iter().map(|s| s.to_string()).collect();
+        let after: Vec<String> = ["a", "x", "y", "c"].iter().map(|s| s.to_string()).collect();
+        let hunk = Hunk::diff(&before, &after).unwrap();
+
+        assert_eq!(hunk.start, 1);
+        assert_eq!(hunk.before, ["b"]);
+        assert_eq!(hunk.after, ["x", "y"]);
+    }
+}
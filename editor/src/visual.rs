@@ -0,0 +1,215 @@
+use crate::config::{BlockInsert, EditorMode, VisualSelection};
+use crate::operator;
+use crate::ops::{self, Operator, RangeKind};
+use crate::textobject::{self, TextObject};
+use crate::{normal_mode_handler, place_cursor, two_key_handler, EditorConfig};
+use gap_buffer::GapBuffer;
+use terminol::{Key, KeyCode, Modifiers};
+
+/// v, V and <C-v>, starts a selection of the given shape at the cursor
+pub fn enter(editor_config: &mut EditorConfig, kind: RangeKind) {
+    let editor_state = &mut editor_config.editor_state;
+    editor_state.visual_kind = kind;
+    editor_state.visual_anchor = editor_config.gap_buffer.cursor_position();
+    editor_state.update_editor_mode(EditorMode::Visual);
+}
+
+/// gv, selects again whatever was selected last
+pub fn reselect(editor_config: &mut EditorConfig) {
+    let editor_state = &mut editor_config.editor_state;
+    let last_visual = match editor_state.last_visual {
+        Some(last_visual) => last_visual,
+        None => return,
+    };
+    editor_state.visual_kind = last_visual.kind;
+    editor_state.visual_anchor = last_visual.anchor;
+    editor_config
+        .gap_buffer
+        .set_cursor_position(last_visual.cursor);
+    editor_state.update_editor_mode(EditorMode::Visual);
+}
+
+/// handles keys while something is selected. the cursor moves the same way as in normal mode
+/// and drags one end of the selection along, the anchor stays where the selection was started
+pub fn visual_mode_handler(key: &Key, editor_config: &mut EditorConfig) {
+    if let Some(first) = editor_config.editor_state.pending_key {
+        if let (KeyCode::Char(c), 'i' | 'a') = (key.code, first) {
+            select_object(editor_config, first == 'i', c);
+        }
+        // only the motions, the scrolling and the register names of the two key commands make
+        // sense on a selection
+        let g_motion = matches!(key.code, KeyCode::Char('g' | 'e' | 'E'));
+        if matches!(first, 'z' | 'f' | 'F' | 't' | 'T' | '"') || (first == 'g' && g_motion) {
+            two_key_handler(first, key, editor_config);
+        }
+        editor_config.editor_state.pending_key = None;
+        return;
+    }
+    let kind = editor_config.editor_state.visual_kind;
+    // counts, motions and scrolling all work the same as in normal mode
+    let moves = match (key.code, key.modifiers) {
+        (KeyCode::Char(c), Modifiers::NONE) => {
+            c.is_ascii_digit() || "hjkl$wWbBeE;,%(){}HMLGgzfFtT/?nN*#".contains(c)
+        }
+        (KeyCode::Char('d' | 'u' | 'f' | 'b'), Modifiers::CTRL) => true,
+        (code, _) => matches!(
+            code,
+            KeyCode::Up
+                | KeyCode::Down
+                | KeyCode::Left
+                | KeyCode::Right
+                | KeyCode::Home
+                | KeyCode::End
+                | KeyCode::PageUp
+                | KeyCode::PageDown
+        ),
+    };
+    match (key.code, key.modifiers) {
+        (KeyCode::Esc, _) | (KeyCode::Char('c'), Modifiers::CTRL) => leave(editor_config),
+        (KeyCode::Char('v'), Modifiers::NONE) => switch_kind(editor_config, RangeKind::Char),
+        (KeyCode::Char('V'), Modifiers::NONE) => switch_kind(editor_config, RangeKind::Line),
+        (KeyCode::Char('v'), Modifiers::CTRL) => switch_kind(editor_config, RangeKind::Block),
+        (KeyCode::Char('o'), Modifiers::NONE) => {
+            let editor_state = &mut editor_config.editor_state;
+            let cursor = editor_config.gap_buffer.cursor_position();
+            editor_config
+                .gap_buffer
+                .set_cursor_position(editor_state.visual_anchor);
+            editor_state.visual_anchor = cursor;
+        }
+        (KeyCode::Char('d' | 'x'), Modifiers::NONE) | (KeyCode::Delete, _) => {
+            operate(editor_config, Operator::Delete)
+        }
+        (KeyCode::Char('y'), Modifiers::NONE) => operate(editor_config, Operator::Yank),
+        (KeyCode::Char('c' | 's'), Modifiers::NONE) => operate(editor_config, Operator::Change),
+        (KeyCode::Char('>'), Modifiers::NONE) => operate(editor_config, Operator::ShiftRight),
+        (KeyCode::Char('<'), Modifiers::NONE) => operate(editor_config, Operator::ShiftLeft),
+        (KeyCode::Char('~'), Modifiers::NONE) => operate(editor_config, Operator::ToggleCase),
+        (KeyCode::Char('u'), Modifiers::NONE) => operate(editor_config, Operator::Lowercase),
+        (KeyCode::Char('U'), Modifiers::NONE) => operate(editor_config, Operator::Uppercase),
+        (KeyCode::Char('J'), Modifiers::NONE) => operate(editor_config, Operator::Join),
+        (KeyCode::Char(c @ ('i' | 'a' | '"')), Modifiers::NONE) => {
+            editor_config.editor_state.pending_key = Some(c);
+        }
+        (KeyCode::Char(':'), Modifiers::NONE) => {
+            leave(editor_config);
+            let editor_state = &mut editor_config.editor_state;
+            editor_state.command = String::from("'<,'>");
+            editor_state.update_editor_mode(EditorMode::Command);
+        }
+        _ if moves => {
+            normal_mode_handler(key, editor_config);
+            // a block may reach past the end of a short line, the others stop at its last char
+            if kind != RangeKind::Block {
+                let (line, col) = editor_config.gap_buffer.cursor_position();
+                place_cursor(&mut editor_config.gap_buffer, (line - 1, col - 1));
+            }
+        }
+        _ => (),
+    }
+}
+
+/// viw, vip and the rest, selects the text object named by `key` in place of the selection. the
+/// selection becomes linewise for the objects that are whole lines and characterwise otherwise
+fn select_object(editor_config: &mut EditorConfig, inner: bool, key: char) {
+    let editor_state = &mut editor_config.editor_state;
+    let count = editor_state.pending.count.take().unwrap_or(1);
+    let (line, col) = editor_config.gap_buffer.cursor_position();
+    let range = TextObject::from_key(key).and_then(|object| {
+        textobject::select(
+            &editor_config.gap_buffer,
+            (line - 1, col - 1),
+            object,
+            inner,
+            count,
+        )
+    });
+    let range = match range {
+        Some(range) => range,
+        None => return,
+    };
+    editor_state.visual_kind = match range.kind {
+        RangeKind::Line => RangeKind::Line,
+        _ => RangeKind::Char,
+    };
+    editor_state.visual_anchor = (range.start.0 + 1, range.start.1 + 1);
+    editor_config
+        .gap_buffer
+        .set_cursor_position((range.end.0 + 1, range.end.1 + 1));
+}
+
+/// pressing the key of the shape already selected ends visual mode, any other switches to it
+fn switch_kind(editor_config: &mut EditorConfig, kind: RangeKind) {
+    if editor_config.editor_state.visual_kind == kind {
+        leave(editor_config);
+    } else {
+        editor_config.editor_state.visual_kind = kind;
+    }
+}
+
+/// back to normal mode, remembering the selection for gv and in the '< and '> marks
+fn leave(editor_config: &mut EditorConfig) {
+    let editor_state = &mut editor_config.editor_state;
+    let cursor = editor_config.gap_buffer.cursor_position();
+    if let Some(selection) = editor_state.selection() {
+        let (start, end) = (selection.start, selection.end);
+        editor_state.marks.insert('<', (start.0 + 1, start.1 + 1));
+        editor_state.marks.insert('>', (end.0 + 1, end.1 + 1));
+    }
+    editor_state.last_visual = Some(VisualSelection {
+        kind: editor_state.visual_kind,
+        anchor: editor_state.visual_anchor,
+        cursor,
+    });
+    editor_state.update_editor_mode(EditorMode::Normal);
+}
+
+fn operate(editor_config: &mut EditorConfig, operator: Operator) {
+    let selection = match editor_config.editor_state.selection() {
+        Some(selection) => selection,
+        None => return,
+    };
+    leave(editor_config);
+    operator::apply(editor_config, operator, selection);
+}
+
+/// copies what was typed on the first line of a changed block to the rest of its lines. nothing
+/// is copied when the typing split or joined lines, since the block no longer lines up
+pub fn finish_block_insert(content_buffer: &mut GapBuffer<GapBuffer<char>>, block: BlockInsert) {
+    if content_buffer.get_filled_items() != block.line_count {
+        return;
+    }
+    let chars = ops::line_chars(content_buffer, block.first);
+    let typed = match chars.len().checked_sub(block.len_before) {
+        Some(typed) if typed > 0 && block.col + typed <= chars.len() => typed,
+        _ => return,
+    };
+    let text: String = chars[block.col..block.col + typed].iter().collect();
+    for line in block.first + 1..=block.last {
+        ops::insert_at(content_buffer, line, block.col, &text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::TextRange;
+
+    #[test]
+    fn block_insert_is_repeated_on_every_line() {
+        let mut content_buffer = GapBuffer::build_nested("abcd\nefgh\nij\n");
+        let range = TextRange::between(RangeKind::Block, (0, 1), (2, 2));
+        ops::delete(&mut content_buffer, &range);
+        let block = BlockInsert {
+            first: 0,
+            last: 2,
+            col: 1,
+            len_before: 2,
+            line_count: 3,
+        };
+        ops::insert_at(&mut content_buffer, 0, 1, "XY");
+
+        finish_block_insert(&mut content_buffer, block);
+        assert_eq!(content_buffer.get_lines(), ["aXYd", "eXYh", "iXY"]);
+    }
+}
@@ -0,0 +1,594 @@
+use crate::buffer::{self, Buffer};
+use crate::config::FileData;
+use crate::tui::Viewport;
+use crate::{ops, place_cursor, EditorConfig};
+use gap_buffer::GapBuffer;
+use terminol::{Key, KeyCode, Modifiers};
+
+/// the fewest rows or columns a window is ever left with, one for its text and one for its
+/// status line or border
+const MIN_SIZE: usize = 2;
+
+/// a part of the screen, 0-indexed from the top left corner
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub row: usize,
+    pub col: usize,
+    pub height: usize,
+    pub width: usize,
+}
+
+/// a view onto a buffer. the window being edited keeps its cursor in the gap buffer and its
+/// viewport in EditorConfig like before there were windows, the others keep them here
+pub struct Window {
+    pub id: usize,
+    /// the number of the buffer shown
+    pub buffer: usize,
+    /// 0-indexed (line, col)
+    pub cursor: (usize, usize),
+    pub viewport: Viewport,
+}
+
+/// how the screen is split up between the windows
+#[derive(Clone, Debug, PartialEq)]
+enum Frame {
+    Window(usize),
+    /// frames side by side when `vertical`, otherwise one above the other. each comes with the
+    /// columns or rows it takes up, its status line or border included
+    Split {
+        vertical: bool,
+        frames: Vec<(Frame, usize)>,
+    },
+}
+
+impl Frame {
+    /// the child indices that lead down to window `id`
+    fn path(&self, id: usize) -> Option<Vec<usize>> {
+        match self {
+            Frame::Window(window) => (*window == id).then(Vec::new),
+            Frame::Split { frames, .. } => frames.iter().enumerate().find_map(|(i, (frame, _))| {
+                frame.path(id).map(|mut path| {
+                    path.insert(0, i);
+                    path
+                })
+            }),
+        }
+    }
+    fn at(&mut self, path: &[usize]) -> &mut Frame {
+        match (path.split_first(), self) {
+            (Some((i, rest)), Frame::Split { frames, .. }) => frames[*i].0.at(rest),
+            (_, frame) => frame,
+        }
+    }
+    fn first_window(&self) -> usize {
+        match self {
+            Frame::Window(id) => *id,
+            Frame::Split { frames, .. } => frames[0].0.first_window(),
+        }
+    }
+
+    /// puts window `new` above `id`, or to the left of it when `vertical`, taking half of the
+    /// `size` rows or columns `id` has
+    fn split(&mut self, id: usize, new: usize, vertical: bool, size: usize) {
+        let Some(path) = self.path(id) else {
+            return;
+        };
+        let half = size / 2;
+        if let Some((last, parent)) = path.split_last() {
+            if let Frame::Split {
+                vertical: along,
+                frames,
+            } = self.at(parent)
+            {
+                if *along == vertical {
+                    frames[*last].1 -= half;
+                    frames.insert(*last, (Frame::Window(new), half));
+                    return;
+                }
+            }
+        }
+        *self.at(&path) = Frame::Split {
+            vertical,
+            frames: vec![(Frame::Window(new), half), (Frame::Window(id), size - half)],
+        };
+    }
+
+    /// takes window `id` out, the frame next to it gets its space. returns the first window of
+    /// that frame, None for the last window there is
+    fn close(&mut self, id: usize) -> Option<usize> {
+        let path = self.path(id)?;
+        let (last, parent) = path.split_last()?;
+        let parent = self.at(parent);
+        let Frame::Split { frames, .. } = parent else {
+            return None;
+        };
+        let (_, size) = frames.remove(*last);
+        let next = (*last).min(frames.len() - 1);
+        frames[next].1 += size;
+        let window = frames[next].0.first_window();
+        if frames.len() == 1 {
+            *parent = frames.remove(0).0;
+        }
+        Some(window)
+    }
+
+    /// gives every frame of each split the same size, out of `rows` and `cols`
+    fn equalize(&mut self, rows: usize, cols: usize) {
+        let Frame::Split { vertical, frames } = self else {
+            return;
+        };
+        let total = if *vertical { cols } else { rows };
+        let count = frames.len();
+        for (i, (frame, size)) in frames.iter_mut().enumerate() {
+            *size = match i + 1 == count {
+                true => total - total / count * (count - 1),
+                false => total / count,
+            };
+            match vertical {
+                true => frame.equalize(rows, *size),
+                false => frame.equalize(*size, cols),
+            }
+        }
+    }
+
+    /// makes the frame holding window `id` `delta` rows taller, or columns wider when
+    /// `vertical`, at the cost of the one after it or else the one before it
+    fn resize(&mut self, id: usize, vertical: bool, delta: isize) {
+        let Some(mut path) = self.path(id) else {
+            return;
+        };
+        while let Some(last) = path.pop() {
+            let Frame::Split {
+                vertical: along,
+                frames,
+            } = self.at(&path)
+            else {
+                continue;
+            };
+            if *along != vertical || frames.len() < 2 {
+                continue;
+            }
+            let other = if last + 1 < frames.len() {
+                last + 1
+            } else {
+                last - 1
+            };
+            let total = frames[last].1 + frames[other].1;
+            let size = frames[last].1.saturating_add_signed(delta);
+            let size = size.clamp(MIN_SIZE, total.saturating_sub(MIN_SIZE).max(MIN_SIZE));
+            frames[last].1 = size;
+            frames[other].1 = total - size;
+            return;
+        }
+    }
+
+    /// works out the part of `rect` each window gets. sizes that no longer add up, like after
+    /// the terminal was resized, are made to fit by the last frames of a split
+    fn layout(&mut self, rect: Rect, rects: &mut Vec<(usize, Rect)>) {
+        let (vertical, frames) = match self {
+            Frame::Window(id) => return rects.push((*id, rect)),
+            Frame::Split { vertical, frames } => (*vertical, frames),
+        };
+        let total = if vertical { rect.width } else { rect.height };
+        let mut sizes: Vec<usize> = frames.iter().map(|(_, size)| *size).collect();
+        fit(&mut sizes, total);
+        let mut start = 0;
+        for ((frame, size), fitted) in frames.iter_mut().zip(sizes) {
+            *size = fitted;
+            let part = match vertical {
+                true => Rect {
+                    col: rect.col + start,
+                    width: fitted,
+                    ..rect
+                },
+                false => Rect {
+                    row: rect.row + start,
+                    height: fitted,
+                    ..rect
+                },
+            };
+            frame.layout(part, rects);
+            start += fitted;
+        }
+    }
+}
+
+/// makes `sizes` add up to `total`. the last one takes what is left over, and what doesn't fit
+/// comes off the last ones first without taking any below MIN_SIZE
+fn fit(sizes: &mut [usize], total: usize) {
+    let sum: usize = sizes.iter().sum();
+    if sum <= total {
+        if let Some(last) = sizes.last_mut() {
+            *last += total - sum;
+        }
+        return;
+    }
+    let mut excess = sum - total;
+    for size in sizes.iter_mut().rev() {
+        let taken = excess.min(size.saturating_sub(MIN_SIZE));
+        *size -= taken;
+        excess -= taken;
+    }
+}
+
+/// the window on the `direction` side of `from`, one of h j k l. of the windows along that side
+/// the one across from `at`, the row or column the cursor is on, is taken
+fn neighbor(rects: &[(usize, Rect)], from: Rect, direction: char, at: usize) -> Option<usize> {
+    let across = |rect: &Rect| match direction {
+        'h' | 'l' => (rect.row, rect.row + rect.height),
+        _ => (rect.col, rect.col + rect.width),
+    };
+    let touching = |rect: &Rect| match direction {
+        'h' => rect.col + rect.width == from.col,
+        'l' => rect.col == from.col + from.width,
+        'k' => rect.row + rect.height == from.row,
+        _ => rect.row == from.row + from.height,
+    };
+    let (from_start, from_end) = across(&from);
+    let sides: Vec<(usize, (usize, usize))> = rects
+        .iter()
+        .filter(|(_, rect)| touching(rect))
+        .map(|(id, rect)| (*id, across(rect)))
+        .filter(|(_, (start, end))| *start < from_end && from_start < *end)
+        .collect();
+    sides
+        .iter()
+        .find(|(_, (start, end))| *start <= at && at < *end)
+        .or(sides.first())
+        .map(|(id, _)| *id)
+}
+
+/// the windows on the screen and the frames they are laid out in
+pub struct Windows {
+    pub list: Vec<Window>,
+    frame: Frame,
+    /// the id of the window being edited
+    pub active: usize,
+    next: usize,
+}
+
+impl Windows {
+    /// a single window on buffer `buffer`
+    pub fn new(buffer: usize, viewport: Viewport) -> Windows {
+        Windows {
+            list: vec![Window {
+                id: 1,
+                buffer,
+                cursor: (0, 0),
+                viewport,
+            }],
+            frame: Frame::Window(1),
+            active: 1,
+            next: 2,
+        }
+    }
+    /// where each window goes in `screen`, in the order ^W w goes through them
+    pub fn layout(&mut self, screen: Rect) -> Vec<(usize, Rect)> {
+        let mut rects = Vec::new();
+        self.frame.layout(screen, &mut rects);
+        rects
+    }
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Window> {
+        self.list.iter_mut().find(|window| window.id == id)
+    }
+    /// whether buffer `number` is shown in a window other than the one being edited
+    pub fn shows_elsewhere(&self, number: usize) -> bool {
+        self.list
+            .iter()
+            .any(|window| window.id != self.active && window.buffer == number)
+    }
+    /// the buffer in the window being edited, as it was when the window was last left
+    pub fn active_buffer(&self) -> Option<usize> {
+        let window = self.list.iter().find(|window| window.id == self.active)?;
+        Some(window.buffer)
+    }
+    /// closes the windows on buffer `number`, all but the one being edited when `keep_active`.
+    /// false when not a single window would be left
+    pub fn close_showing(&mut self, number: usize, keep_active: bool) -> bool {
+        let shown: Vec<usize> = self
+            .list
+            .iter()
+            .filter(|window| window.buffer == number)
+            .filter(|window| !keep_active || window.id != self.active)
+            .map(|window| window.id)
+            .collect();
+        for id in shown {
+            let Some(next) = self.frame.close(id) else {
+                return false;
+            };
+            self.list.retain(|window| window.id != id);
+            if id == self.active {
+                self.active = next;
+            }
+        }
+        true
+    }
+}
+
+/// the part of the terminal the windows share, which is all of it but the command row and the
+/// tabline when there is one
+fn screen(editor_config: &EditorConfig) -> Rect {
+    let term_attr = terminol::get_terminal_size();
+    let tabline = editor_config.tabs.shown() as usize;
+    Rect {
+        row: tabline,
+        col: 0,
+        height: (term_attr.ws_row as usize)
+            .saturating_sub(1 + tabline)
+            .max(MIN_SIZE),
+        width: (term_attr.ws_col as usize).max(1),
+    }
+}
+
+/// where each window goes on the screen right now
+pub fn layout(editor_config: &mut EditorConfig) -> Vec<(usize, Rect)> {
+    let screen = screen(editor_config);
+    editor_config.windows.layout(screen)
+}
+
+/// writes the cursor, viewport and buffer of the window being edited back into it
+pub fn save_active(editor_config: &mut EditorConfig) {
+    let (line, col) = editor_config.gap_buffer.cursor_position();
+    let viewport = editor_config.viewport;
+    let buffer = editor_config.buffers.current;
+    let windows = &mut editor_config.windows;
+    if let Some(window) = windows.get_mut(windows.active) {
+        window.cursor = (line - 1, col - 1);
+        window.viewport = viewport;
+        window.buffer = buffer;
+    }
+}
+
+/// makes window `id` the one being edited, with its buffer, cursor and viewport
+pub fn enter(editor_config: &mut EditorConfig, id: usize) {
+    let windows = &mut editor_config.windows;
+    let Some(window) = windows.get_mut(id) else {
+        return;
+    };
+    let (buffer, (line, col), viewport) = (window.buffer, window.cursor, window.viewport);
+    windows.active = id;
+    buffer::enter(editor_config, buffer);
+    // the buffer may have been edited from another window since
+    let cursor = clamp(&editor_config.gap_buffer, (line, col));
+    place_cursor(&mut editor_config.gap_buffer, cursor);
+    editor_config.viewport = viewport;
+}
+
+/// keeps the 0-indexed `cursor` of a window inside the text, which may have been shortened from
+/// another window on the same buffer
+pub fn clamp(
+    content_buffer: &GapBuffer<GapBuffer<char>>,
+    (line, col): (usize, usize),
+) -> (usize, usize) {
+    let line = line.min(content_buffer.get_filled_items() - 1);
+    let len = ops::line_chars(content_buffer, line)
+        .iter()
+        .filter(|c| **c != '\n')
+        .count();
+    (line, col.min(len.saturating_sub(1)))
+}
+
+/// moves to window `id` from the one being edited
+fn go_to(editor_config: &mut EditorConfig, id: usize) {
+    if id != editor_config.windows.active {
+        save_active(editor_config);
+        enter(editor_config, id);
+    }
+}
+
+/// :split and :vsplit, splits the window being edited in two. the new window above it or to
+/// the left of it shows the same buffer, or `file_name` when one is given, or a new empty
+/// buffer for :new and :vnew
+pub fn split(
+    editor_config: &mut EditorConfig,
+    vertical: bool,
+    file_name: Option<String>,
+    empty: bool,
+) {
+    let active = editor_config.windows.active;
+    let rects = layout(editor_config);
+    let Some((_, rect)) = rects.iter().find(|(id, _)| *id == active) else {
+        return;
+    };
+    let size = if vertical { rect.width } else { rect.height };
+    if size < MIN_SIZE * 2 {
+        editor_config.editor_state.message = Some(String::from("E36: Not enough room"));
+        return;
+    }
+    save_active(editor_config);
+    let windows = &mut editor_config.windows;
+    let id = windows.next;
+    windows.next += 1;
+    let Some(window) = windows.get_mut(active) else {
+        return;
+    };
+    let window = Window {
+        id,
+        buffer: window.buffer,
+        cursor: window.cursor,
+        viewport: window.viewport,
+    };
+    windows.list.push(window);
+    windows.frame.split(active, id, vertical, size);
+    windows.active = id;
+
+    if empty {
+        new_buffer(editor_config);
+    } else if file_name.is_some() && file_name != editor_config.file_data.file_name {
+        buffer::edit(editor_config, file_name, false);
+    }
+}
+
+/// edits a new empty buffer in the window being edited
+pub fn new_buffer(editor_config: &mut EditorConfig) {
+    let editor_state = &mut editor_config.editor_state;
+    match Buffer::load(FileData::scratch(), editor_state) {
+        Ok(buffer) => {
+            let number = editor_config.buffers.add(buffer);
+            buffer::enter(editor_config, number);
+        }
+        Err(e) => editor_state.message = Some(format!("E484: Can't open file: {e}")),
+    }
+}
+
+/// ^W c and :close, closes the window being edited. the window that gets its space is edited
+/// next. the last window can't be closed this way, only quit
+pub fn close(editor_config: &mut EditorConfig) {
+    let windows = &mut editor_config.windows;
+    let active = windows.active;
+    let Some(next) = windows.frame.close(active) else {
+        editor_config.editor_state.message = Some(String::from("E444: Cannot close last window"));
+        return;
+    };
+    windows.list.retain(|window| window.id != active);
+    enter(editor_config, next);
+}
+
+/// ^W o and :only, closes every window but the one being edited
+pub fn only(editor_config: &mut EditorConfig) {
+    let windows = &mut editor_config.windows;
+    let active = windows.active;
+    windows.list.retain(|window| window.id == active);
+    windows.frame = Frame::Window(active);
+}
+
+/// the second key of a ^W window command, with the count typed before ^W
+pub fn ctrl_w(editor_config: &mut EditorConfig, key: &Key) {
+    let count = editor_config.editor_state.pending.total_count();
+    // ^W ^J and the like are the same as ^W j
+    let c = match (key.code, key.modifiers) {
+        (KeyCode::Char(c), Modifiers::NONE | Modifiers::SHIFT | Modifiers::CTRL) => c,
+        _ => return,
+    };
+    let active = editor_config.windows.active;
+    let rects = layout(editor_config);
+    let Some(index) = rects.iter().position(|(id, _)| *id == active) else {
+        return;
+    };
+    match c {
+        'h' | 'j' | 'k' | 'l' => {
+            let rect = rects[index].1;
+            let (line, col) = editor_config.gap_buffer.cursor_position();
+            let viewport = &editor_config.viewport;
+            let at = match c {
+                'h' | 'l' => rect.row + (line - 1).saturating_sub(viewport.top),
+                _ => rect.col + (col - 1).saturating_sub(viewport.left),
+            };
+            if let Some(id) = neighbor(&rects, rect, c, at) {
+                go_to(editor_config, id);
+            }
+        }
+        'w' | 'W' => {
+            let index = match (c, count) {
+                (_, Some(count)) => (count - 1).min(rects.len() - 1),
+                ('w', None) => (index + 1) % rects.len(),
+                _ => (index + rects.len() - 1) % rects.len(),
+            };
+            go_to(editor_config, rects[index].0);
+        }
+        'c' | 'q' => close(editor_config),
+        'o' => only(editor_config),
+        's' | 'S' => split(editor_config, false, None, false),
+        'v' => split(editor_config, true, None, false),
+        'n' => split(editor_config, false, None, true),
+        '=' => {
+            let screen = screen(editor_config);
+            editor_config
+                .windows
+                .frame
+                .equalize(screen.height, screen.width);
+        }
+        '+' | '-' | '<' | '>' => {
+            let delta = count.unwrap_or(1) as isize;
+            let delta = if matches!(c, '+' | '>') {
+                delta
+            } else {
+                -delta
+            };
+            let vertical = matches!(c, '<' | '>');
+            editor_config.windows.frame.resize(active, vertical, delta);
+        }
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(row: usize, col: usize, height: usize, width: usize) -> Rect {
+        Rect {
+            row,
+            col,
+            height,
+            width,
+        }
+    }
+
+    fn laid_out(frame: &mut Frame) -> Vec<(usize, Rect)> {
+        let mut rects = Vec::new();
+        frame.layout(rect(0, 0, 20, 80), &mut rects);
+        rects
+    }
+
+    #[test]
+    fn split_and_close() {
+        let mut frame = Frame::Window(1);
+        frame.split(1, 2, false, 20);
+        assert_eq!(
+            laid_out(&mut frame),
+            [(2, rect(0, 0, 10, 80)), (1, rect(10, 0, 10, 80))]
+        );
+        // a vertical split of the lower window nests, another horizontal one doesn't
+        frame.split(1, 3, true, 80);
+        frame.split(2, 4, false, 10);
+        assert_eq!(
+            laid_out(&mut frame),
+            [
+                (4, rect(0, 0, 5, 80)),
+                (2, rect(5, 0, 5, 80)),
+                (3, rect(10, 0, 10, 40)),
+                (1, rect(10, 40, 10, 40)),
+            ]
+        );
+        assert_eq!(frame.close(3), Some(1));
+        assert_eq!(frame.close(4), Some(2));
+        assert_eq!(
+            laid_out(&mut frame),
+            [(2, rect(0, 0, 10, 80)), (1, rect(10, 0, 10, 80))]
+        );
+        assert_eq!(frame.close(2), Some(1));
+        assert_eq!(frame, Frame::Window(1));
+        assert_eq!(frame.close(1), None);
+    }
+
+    #[test]
+    fn resize_and_equalize() {
+        let mut frame = Frame::Window(1);
+        frame.split(1, 2, false, 20);
+        frame.resize(2, false, 3);
+        assert_eq!(laid_out(&mut frame)[0].1.height, 13);
+        // the window is never left without a row for its text
+        frame.resize(1, false, 30);
+        assert_eq!(laid_out(&mut frame)[0].1.height, MIN_SIZE);
+        // there is nothing to make wider in a horizontal split
+        frame.resize(1, true, 5);
+        assert_eq!(laid_out(&mut frame)[1].1.width, 80);
+        frame.equalize(20, 80);
+        assert_eq!(laid_out(&mut frame)[0].1.height, 10);
+    }
+
+    #[test]
+    fn windows_next_to_each_other() {
+        let rects = [
+            (1, rect(0, 0, 10, 40)),
+            (2, rect(10, 0, 10, 40)),
+            (3, rect(0, 40, 20, 40)),
+        ];
+        assert_eq!(neighbor(&rects, rects[0].1, 'j', 5), Some(2));
+        assert_eq!(neighbor(&rects, rects[0].1, 'l', 3), Some(3));
+        assert_eq!(neighbor(&rects, rects[0].1, 'k', 5), None);
+        assert_eq!(neighbor(&rects, rects[2].1, 'h', 15), Some(2));
+        assert_eq!(neighbor(&rects, rects[2].1, 'h', 2), Some(1));
+    }
+}
@@ -0,0 +1,67 @@
+use std::io::{self, Write};
+
+/// the selections a terminal can be asked to hold text for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Selection {
+    /// the clipboard that ctrl-c and ctrl-v use
+    Clipboard,
+    /// the primary selection, which X puts back with the middle mouse button
+    Primary,
+}
+
+/// hands `text` to the terminal through the OSC 52 escape, which most terminals put on the
+/// system clipboard. this works over ssh too, since the escape travels with the rest of the output
+pub fn set(selection: Selection, text: &str) {
+    write!(io::stdout(), "{}", osc52(selection, text))
+        .unwrap_or_else(|e| panic!("std io error, {e}"));
+    io::stdout()
+        .flush()
+        .unwrap_or_else(|e| panic!("std io error, {e}"));
+}
+
+/// the OSC 52 escape that sets `selection` to `text`, which goes in base64
+pub fn osc52(selection: Selection, text: &str) -> String {
+    let target = match selection {
+        Selection::Clipboard => 'c',
+        Selection::Primary => 'p',
+    };
+    format!("\x1b]52;{target};{}\x07", base64(text.as_bytes()))
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let mut group = [0u8; 3];
+        group[..chunk.len()].copy_from_slice(chunk);
+        let bits = u32::from_be_bytes([0, group[0], group[1], group[2]]);
+        // 4 chars of 6 bits each, with = for the ones past the end of the input
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (bits >> (18 - 6 * i)) & 0x3f;
+                encoded.push(char::from(ALPHABET[index as usize]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn osc52_escape() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"h"), "aA==");
+        assert_eq!(base64(b"hi"), "aGk=");
+        assert_eq!(base64(b"hello"), "aGVsbG8=");
+        assert_eq!(
+            osc52(Selection::Clipboard, "hello\n"),
+            "\x1b]52;c;aGVsbG8K\x07"
+        );
+        assert_eq!(osc52(Selection::Primary, "hi"), "\x1b]52;p;aGk=\x07");
+    }
+}
@@ -1,53 +1,7 @@
 pub mod color;
 pub use color::Colors;
-use std::io::{self, BufRead, Write};
+use std::io::{self, Write};
 
-#[derive(Debug)]
-pub struct Cursor {
-    pub line: usize,
-    pub col: usize,
-}
-
-impl Cursor {
-    fn new(line: usize, col: usize) -> Cursor {
-        Cursor { line, col }
-    }
-    /// constructs new instance of cursor with current and curosor row and col
-    /// using ascii escape character "\x1b[6n". stdout is flushed and stdin response
-    /// from terminal is read into cursor instance.
-    pub fn get_cursor_coords() -> Cursor {
-        write!(io::stdout(), "\x1b[6n")
-            .unwrap_or_else(|e| panic!("io error occurred during write: {e}"));
-
-        io::stdout()
-            .flush()
-            .unwrap_or_else(|e| panic!("io error occurred during flush: {e}"));
-
-        let mut v: Vec<u8> = Vec::with_capacity(30);
-        let mut reader = io::stdin().lock();
-        let _ = reader.read_until(b'R', &mut v);
-
-        let mut iterator = v.into_iter();
-        // take iterator by reference so that we can use the
-        // remaining elements in the iterator once we find the line
-        let line: u32 = iterator
-            .by_ref()
-            .map(|c| c as char)
-            .take_while(|c| *c != ';')
-            .filter(|i| i.is_numeric())
-            .map(|c| c.to_digit(10).expect("not a number!"))
-            .fold(0, |acc, elem| acc * 10 + elem);
-
-        let col: u32 = iterator
-            .by_ref()
-            .map(|c| c as char)
-            .filter(|i| i.is_numeric())
-            .map(|c| c.to_digit(10).expect("not a number!"))
-            .fold(0, |acc, elem| acc * 10 + elem);
-
-        Cursor::new(line as usize, col as usize)
-    }
-}
 pub fn enable_bar_cursor() {
     write!(io::stdout(), "\x1b[6 q",).unwrap_or_else(|e| panic!("io error{e}"))
 }
@@ -75,10 +29,6 @@ pub fn move_cursor_to(line: usize, column: usize) {
 pub fn move_home() {
     write!(io::stdout(), "\x1b[H").unwrap_or_else(|e| panic!("io error{e}"))
 }
-pub fn return_newline() {
-    let cursor = Cursor::get_cursor_coords();
-    move_cursor_to(&cursor.line + 1, cursor.col)
-}
 pub fn save_cursor_position() {
     write!(io::stdout(), "\x1b[s").unwrap_or_else(|e| panic!("io error{e}"))
 }
@@ -102,6 +52,12 @@ pub fn set_background(color: i32) {
 pub fn delete_end_of_line() {
     write!(io::stdout(), "\x1b[0K").unwrap_or_else(|e| panic!("io error{e}"));
 }
+pub fn enable_reverse_video() {
+    write!(io::stdout(), "\x1b[7m").unwrap_or_else(|e| panic!("io error{e}"));
+}
+pub fn disable_reverse_video() {
+    write!(io::stdout(), "\x1b[27m").unwrap_or_else(|e| panic!("io error{e}"));
+}
 pub fn reset_modes() {
     write!(io::stdout(), "\x1b[0m").unwrap_or_else(|e| panic!("io error{e}"));
 }
@@ -0,0 +1,618 @@
+use std::io::{self, Read};
+use std::ops::BitOr;
+
+/// how long to wait for the rest of an escape sequence before deciding that a lone Esc byte was
+/// the Esc key itself
+pub const ESCAPE_TIMEOUT_MS: i32 = 50;
+
+const ESC: u8 = 27;
+
+/// the modifier keys held down with a key, as a small bit set. the bit values match the ones
+/// xterm encodes in the parameter of a CSI sequence (minus one)
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+pub struct Modifiers(u8);
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers(0);
+    pub const SHIFT: Modifiers = Modifiers(1);
+    pub const ALT: Modifiers = Modifiers(2);
+    pub const CTRL: Modifiers = Modifiers(4);
+
+    pub fn contains(&self, other: Modifiers) -> bool {
+        self.0 & other.0 == other.0
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+    /// builds modifiers from the xterm modifier parameter, e.g. the 5 in "\x1b[1;5A" is ctrl
+    fn from_xterm_param(param: u32) -> Modifiers {
+        Modifiers((param.saturating_sub(1) & 0b111) as u8)
+    }
+}
+
+impl BitOr for Modifiers {
+    type Output = Modifiers;
+
+    fn bitor(self, rhs: Modifiers) -> Modifiers {
+        Modifiers(self.0 | rhs.0)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum KeyCode {
+    Char(char),
+    Enter,
+    Tab,
+    BackTab,
+    Backspace,
+    Esc,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    F(u8),
+    /// a complete sequence that was recognised as one but does not map to any key we know
+    Unknown,
+}
+
+/// a decoded key press. control keys are reported as the letter they were typed with plus the
+/// CTRL modifier, so <C-d> is `Key { code: KeyCode::Char('d'), modifiers: Modifiers::CTRL }`
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: Modifiers,
+}
+
+impl Key {
+    pub fn new(code: KeyCode, modifiers: Modifiers) -> Key {
+        Key { code, modifiers }
+    }
+    pub fn plain(code: KeyCode) -> Key {
+        Key::new(code, Modifiers::NONE)
+    }
+    pub fn ctrl(c: char) -> Key {
+        Key::new(KeyCode::Char(c), Modifiers::CTRL)
+    }
+    fn with(mut self, modifiers: Modifiers) -> Key {
+        self.modifiers = self.modifiers | modifiers;
+        self
+    }
+}
+
+/// decodes the first key in `bytes`, returning it along with the number of bytes it used.
+///
+/// None is returned when `bytes` holds only the start of a key, e.g. a lone Esc, "\x1b[" or the
+/// first byte of a multi-byte utf-8 char, so the caller can wait for more input. once that wait
+/// has timed out the caller passes `timed_out` as true and whatever has arrived is decoded as is,
+/// which is how a bare Esc is told apart from the start of a sequence. a dos keyboard string is
+/// only decoded from bytes that came in together, as a terminal writes both of its bytes at once.
+pub fn parse_key(bytes: &[u8], timed_out: bool) -> Option<(Key, usize)> {
+    let first = *bytes.first()?;
+    match first {
+        ESC => parse_escape(bytes, timed_out),
+        // the dos scan code prefixes listed in ansi.md. a NUL that arrived on its own is
+        // <C-Space> straight away, the code of a keyboard string never comes after it
+        0 => match bytes.get(1).and_then(|code| scan_code(first, *code)) {
+            Some(key) => Some((key, 2)),
+            None => Some((Key::ctrl(' '), 1)),
+        },
+        224 => {
+            // 0xe0 also leads three byte utf-8 chars, whose second byte is always 0xa0 to 0xbf
+            let utf8_lead = matches!(bytes.get(1), Some(0xa0..=0xbf))
+                && match bytes.get(2) {
+                    Some(b) => is_utf8_continuation(*b),
+                    None => !timed_out,
+                };
+            match bytes.get(1) {
+                Some(code) if !utf8_lead => match scan_code(first, *code) {
+                    Some(key) => Some((key, 2)),
+                    None => parse_utf8(bytes, timed_out),
+                },
+                _ => parse_utf8(bytes, timed_out),
+            }
+        }
+        _ => parse_single(bytes, timed_out),
+    }
+}
+
+/// decodes everything in `bytes`, as needed when replaying a recorded sequence of keys where no
+/// more input is going to arrive
+pub fn parse_keys(mut bytes: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    loop {
+        // none of this came from a dos terminal, so a NUL is always <C-Space>
+        let (key, used) = match bytes {
+            [0, ..] => (Key::ctrl(' '), 1),
+            _ => match parse_key(bytes, true) {
+                Some(parsed) => parsed,
+                None => break,
+            },
+        };
+        keys.push(key);
+        bytes = &bytes[used..];
+    }
+    keys
+}
+
+/// stands in for the ESC that starts the sequence of a special key in text written by
+/// encode_keys, like vim's K_SPECIAL. a real ESC in that text is always the Esc key itself
+const KEY_SPECIAL: char = '\u{80}';
+
+/// writes keys as text, which is how a recorded macro is kept in a register. a key is written
+/// as the bytes the terminal sends for it, except that the sequences starting with ESC start
+/// with KEY_SPECIAL instead, so <Esc>O can't be mistaken for <A-O> when it is read back
+pub fn encode_keys(keys: &[Key]) -> String {
+    let mut text = String::new();
+    for key in keys {
+        let alt = key.modifiers.contains(Modifiers::ALT);
+        let modifiers = Modifiers(key.modifiers.0 & !Modifiers::ALT.0);
+        let plain = match (key.code, modifiers) {
+            (KeyCode::Char(c @ 'a'..='z'), Modifiers::CTRL) => char::from(c as u8 - b'a' + 1),
+            (KeyCode::Char(c @ '\\'..='_'), Modifiers::CTRL) => char::from(c as u8 - b'\\' + 28),
+            (KeyCode::Char(' '), Modifiers::CTRL) => '\0',
+            (KeyCode::Char(c), Modifiers::NONE | Modifiers::SHIFT) => c,
+            (KeyCode::Enter, Modifiers::NONE) => '\r',
+            (KeyCode::Tab, Modifiers::NONE) => '\t',
+            (KeyCode::Backspace, Modifiers::NONE) => '\x7f',
+            (KeyCode::Esc, _) => '\x1b',
+            (code, modifiers) => {
+                if let Some(sequence) = special_sequence(code, modifiers) {
+                    text.push(KEY_SPECIAL);
+                    text.push_str(&sequence);
+                }
+                continue;
+            }
+        };
+        if alt {
+            text.push(KEY_SPECIAL);
+        }
+        text.push(plain);
+    }
+    text
+}
+
+/// the part after ESC of the sequence xterm sends for a key that has no byte of its own
+fn special_sequence(code: KeyCode, modifiers: Modifiers) -> Option<String> {
+    let (number, last) = match code {
+        KeyCode::Up => (1, 'A'),
+        KeyCode::Down => (1, 'B'),
+        KeyCode::Right => (1, 'C'),
+        KeyCode::Left => (1, 'D'),
+        KeyCode::Home => (1, 'H'),
+        KeyCode::End => (1, 'F'),
+        KeyCode::BackTab => return Some(String::from("[Z")),
+        KeyCode::Insert => (2, '~'),
+        KeyCode::Delete => (3, '~'),
+        KeyCode::PageUp => (5, '~'),
+        KeyCode::PageDown => (6, '~'),
+        KeyCode::F(n @ 1..=4) => (1, char::from(b'P' + n - 1)),
+        KeyCode::F(n @ 5) => (n as u32 + 10, '~'),
+        KeyCode::F(n @ 6..=10) => (n as u32 + 11, '~'),
+        KeyCode::F(n @ 11..=12) => (n as u32 + 12, '~'),
+        // control chars without a letter of their own, like <C-Enter>, have no sequence
+        _ => return None,
+    };
+    Some(match (modifiers, number) {
+        (Modifiers::NONE, 1) => format!("[{last}"),
+        (Modifiers::NONE, number) => format!("[{number}{last}"),
+        (modifiers, number) => format!("[{number};{}{last}", modifiers.0 + 1),
+    })
+}
+
+/// reads back the keys in text written by encode_keys
+pub fn decode_keys(text: &str) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let (key, used) = match c {
+            '\x1b' => (Key::plain(KeyCode::Esc), 1),
+            // encode_keys writes <C-Space> as a NUL and never writes a dos keyboard string
+            '\0' => (Key::ctrl(' '), 1),
+            KEY_SPECIAL => {
+                let after = &rest[c.len_utf8()..];
+                let mut bytes = vec![ESC];
+                bytes.extend_from_slice(after.as_bytes());
+                match parse_key(&bytes, true) {
+                    Some((key, used)) => (key, c.len_utf8() + used - 1),
+                    None => break,
+                }
+            }
+            _ => match parse_key(rest.as_bytes(), true) {
+                Some(parsed) => parsed,
+                None => break,
+            },
+        };
+        keys.push(key);
+        rest = &rest[used..];
+    }
+    keys
+}
+
+/// decodes a key that does not start with Esc
+fn parse_single(bytes: &[u8], timed_out: bool) -> Option<(Key, usize)> {
+    let key = match bytes[0] {
+        9 => Key::plain(KeyCode::Tab),
+        13 => Key::plain(KeyCode::Enter),
+        127 => Key::plain(KeyCode::Backspace),
+        b @ 1..=26 => Key::ctrl((b'a' + b - 1) as char),
+        b @ 28..=31 => Key::ctrl((b'\\' + b - 28) as char),
+        b if b < 128 => Key::plain(KeyCode::Char(b as char)),
+        _ => return parse_utf8(bytes, timed_out),
+    };
+    Some((key, 1))
+}
+
+fn is_utf8_continuation(byte: u8) -> bool {
+    byte & 0b1100_0000 == 0b1000_0000
+}
+
+fn parse_utf8(bytes: &[u8], timed_out: bool) -> Option<(Key, usize)> {
+    let len = match bytes[0] {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => return Some((Key::plain(KeyCode::Unknown), 1)),
+    };
+    if bytes.len() < len {
+        let so_far_valid = bytes[1..].iter().all(|b| is_utf8_continuation(*b));
+        if so_far_valid && !timed_out {
+            return None;
+        }
+        return Some((Key::plain(KeyCode::Unknown), 1));
+    }
+    match std::str::from_utf8(&bytes[..len]) {
+        Ok(s) => {
+            let c = s.chars().next().unwrap_or_default();
+            Some((Key::plain(KeyCode::Char(c)), len))
+        }
+        Err(_) => Some((Key::plain(KeyCode::Unknown), 1)),
+    }
+}
+
+fn parse_escape(bytes: &[u8], timed_out: bool) -> Option<(Key, usize)> {
+    match bytes.get(1) {
+        None if timed_out => Some((Key::plain(KeyCode::Esc), 1)),
+        None => None,
+        Some(b'[') => match parse_csi(bytes) {
+            Some(parsed) => Some(parsed),
+            // "\x1b[" with nothing after it was <A-[>
+            None if timed_out && bytes.len() == 2 => {
+                Some((Key::new(KeyCode::Char('['), Modifiers::ALT), 2))
+            }
+            None if timed_out => Some((Key::plain(KeyCode::Unknown), bytes.len())),
+            None => None,
+        },
+        Some(b'O') => match parse_ss3(bytes) {
+            Some(parsed) => Some(parsed),
+            None if timed_out => Some((Key::new(KeyCode::Char('O'), Modifiers::ALT), 2)),
+            None => None,
+        },
+        // esc esc is what most terminals send for <A-Esc>
+        Some(&ESC) => Some((Key::new(KeyCode::Esc, Modifiers::ALT), 2)),
+        // anything else after esc is the key typed with alt held
+        Some(_) => {
+            let (key, used) = parse_key(&bytes[1..], timed_out)?;
+            Some((key.with(Modifiers::ALT), used + 1))
+        }
+    }
+}
+
+/// decodes "\x1b[" followed by parameters and a final byte, e.g. "\x1b[A", "\x1b[3~" or
+/// "\x1b[1;5C"
+fn parse_csi(bytes: &[u8]) -> Option<(Key, usize)> {
+    // the linux console sends f1 to f5 as "\x1b[[A" to "\x1b[[E"
+    if bytes.get(2) == Some(&b'[') {
+        let key = match bytes.get(3)? {
+            b @ b'A'..=b'E' => Key::plain(KeyCode::F(b - b'A' + 1)),
+            _ => Key::plain(KeyCode::Unknown),
+        };
+        return Some((key, 4));
+    }
+
+    let mut params: Vec<u32> = vec![0];
+    for (i, b) in bytes.iter().enumerate().skip(2) {
+        match b {
+            b'0'..=b'9' => {
+                let last = params.last_mut().unwrap();
+                *last = last.saturating_mul(10).saturating_add((b - b'0') as u32);
+            }
+            b';' => params.push(0),
+            0x40..=0x7e => {
+                let modifiers = Modifiers::from_xterm_param(params.get(1).copied().unwrap_or(1));
+                let code = match b {
+                    b'~' => tilde_code(params[0]),
+                    b'Z' => return Some((Key::plain(KeyCode::BackTab), i + 1)),
+                    _ => letter_code(*b),
+                };
+                return Some((Key::new(code, modifiers), i + 1));
+            }
+            // intermediate bytes and private markers, kept as part of the sequence
+            0x20..=0x3f => (),
+            _ => return Some((Key::plain(KeyCode::Unknown), i)),
+        }
+    }
+    None
+}
+
+/// decodes "\x1bO" followed by a final byte, which is how f1 to f4 and the arrows are sent in
+/// application cursor mode
+fn parse_ss3(bytes: &[u8]) -> Option<(Key, usize)> {
+    let mut modifiers = Modifiers::NONE;
+    let mut i = 2;
+    while let Some(b @ b'0'..=b'9') = bytes.get(i) {
+        modifiers = Modifiers::from_xterm_param((b - b'0') as u32);
+        i += 1;
+    }
+    let code = letter_code(*bytes.get(i)?);
+    Some((Key::new(code, modifiers), i + 1))
+}
+
+fn letter_code(b: u8) -> KeyCode {
+    match b {
+        b'A' => KeyCode::Up,
+        b'B' => KeyCode::Down,
+        b'C' => KeyCode::Right,
+        b'D' => KeyCode::Left,
+        b'H' => KeyCode::Home,
+        b'F' => KeyCode::End,
+        b'P' => KeyCode::F(1),
+        b'Q' => KeyCode::F(2),
+        b'R' => KeyCode::F(3),
+        b'S' => KeyCode::F(4),
+        b'M' => KeyCode::Enter,
+        _ => KeyCode::Unknown,
+    }
+}
+
+fn tilde_code(param: u32) -> KeyCode {
+    match param {
+        1 | 7 => KeyCode::Home,
+        2 => KeyCode::Insert,
+        3 => KeyCode::Delete,
+        4 | 8 => KeyCode::End,
+        5 => KeyCode::PageUp,
+        6 => KeyCode::PageDown,
+        11..=15 => KeyCode::F((param - 10) as u8),
+        17..=21 => KeyCode::F((param - 11) as u8),
+        23 | 24 => KeyCode::F((param - 12) as u8),
+        _ => KeyCode::Unknown,
+    }
+}
+
+/// the "0;code" and "224;code" keyboard strings from the table in ansi.md
+fn scan_code(prefix: u8, code: u8) -> Option<Key> {
+    use KeyCode::*;
+    let none = Modifiers::NONE;
+    let shift = Modifiers::SHIFT;
+    let ctrl = Modifiers::CTRL;
+    let alt = Modifiers::ALT;
+
+    let (code, modifiers) = match code {
+        3 if prefix == 0 => return Some(Key::ctrl('@')),
+        15 => (BackTab, none),
+        59..=68 => (F(code - 58), none),
+        84..=93 => (F(code - 83), shift),
+        94..=103 => (F(code - 93), ctrl),
+        104..=113 => (F(code - 103), alt),
+        133 => (F(11), none),
+        134 => (F(12), none),
+        135 => (F(11), shift),
+        136 => (F(12), shift),
+        137 => (F(11), ctrl),
+        138 => (F(12), ctrl),
+        139 => (F(11), alt),
+        140 => (F(12), alt),
+        71 => (Home, none),
+        72 => (Up, none),
+        73 => (PageUp, none),
+        75 => (Left, none),
+        77 => (Right, none),
+        79 => (End, none),
+        80 => (Down, none),
+        81 => (PageDown, none),
+        82 => (Insert, none),
+        83 => (Delete, none),
+        119 => (Home, ctrl),
+        141 => (Up, ctrl),
+        132 => (PageUp, ctrl),
+        115 => (Left, ctrl),
+        116 => (Right, ctrl),
+        117 => (End, ctrl),
+        145 => (Down, ctrl),
+        118 => (PageDown, ctrl),
+        146 => (Insert, ctrl),
+        147 => (Delete, ctrl),
+        148 => (Tab, ctrl),
+        151 => (Home, alt),
+        152 => (Up, alt),
+        153 => (PageUp, alt),
+        155 => (Left, alt),
+        157 => (Right, alt),
+        159 => (End, alt),
+        154 => (Down, alt),
+        161 => (PageDown, alt),
+        162 => (Insert, alt),
+        163 => (Delete, alt),
+        165 => (Tab, alt),
+        166 => (Enter, alt),
+        // alt with the letter keys, laid out in keyboard rows
+        16..=25 => (Char(b"qwertyuiop"[(code - 16) as usize] as char), alt),
+        30..=38 => (Char(b"asdfghjkl"[(code - 30) as usize] as char), alt),
+        44..=50 => (Char(b"zxcvbnm"[(code - 44) as usize] as char), alt),
+        120..=129 => (Char(b"1234567890"[(code - 120) as usize] as char), alt),
+        _ => return None,
+    };
+    Some(Key::new(code, modifiers))
+}
+
+/// reads keys from stdin, holding on to bytes that arrived after the key that was returned so
+/// that pasted text or fast typing is not lost
+pub struct KeyReader {
+    pending: Vec<u8>,
+}
+
+impl Default for KeyReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeyReader {
+    pub fn new() -> KeyReader {
+        KeyReader {
+            pending: Vec::with_capacity(32),
+        }
+    }
+    /// blocks until a whole key has been read. an incomplete sequence waits at most
+    /// ESCAPE_TIMEOUT_MS for its remaining bytes before being decoded as is. once stdin is at
+    /// its end, like when the terminal hung up, this fails with UnexpectedEof
+    pub fn read_key(&mut self) -> io::Result<Key> {
+        loop {
+            if let Some((key, used)) = parse_key(&self.pending, false) {
+                self.pending.drain(..used);
+                return Ok(key);
+            }
+            if !self.pending.is_empty() && !stdin_ready(ESCAPE_TIMEOUT_MS) {
+                if let Some((key, used)) = parse_key(&self.pending, true) {
+                    self.pending.drain(..used);
+                    return Ok(key);
+                }
+            }
+            let mut chunk = [0u8; 32];
+            let read = io::stdin().read(&mut chunk)?;
+            if read == 0 {
+                // nothing more is coming, what is left is decoded as is
+                if let Some((key, used)) = parse_key(&self.pending, true) {
+                    self.pending.drain(..used);
+                    return Ok(key);
+                }
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+            }
+            self.pending.extend_from_slice(&chunk[..read]);
+        }
+    }
+}
+
+/// waits up to `timeout_ms` for stdin to have something to read
+fn stdin_ready(timeout_ms: i32) -> bool {
+    let mut poll_fd = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    unsafe { libc::poll(&mut poll_fd, 1, timeout_ms) > 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(bytes: &[u8]) -> Key {
+        parse_key(bytes, true).unwrap().0
+    }
+
+    #[test]
+    fn plain_and_control_bytes() {
+        assert_eq!(key(b"j"), Key::plain(KeyCode::Char('j')));
+        assert_eq!(key(&[4]), Key::ctrl('d'));
+        assert_eq!(key(&[13]), Key::plain(KeyCode::Enter));
+        assert_eq!(key(&[127]), Key::plain(KeyCode::Backspace));
+    }
+
+    #[test]
+    fn utf8_waits_for_all_bytes() {
+        let bytes = "é".as_bytes();
+        assert_eq!(parse_key(&bytes[..1], false), None);
+        assert_eq!(
+            parse_key(bytes, false),
+            Some((Key::plain(KeyCode::Char('é')), 2))
+        );
+    }
+
+    #[test]
+    fn lone_escape_needs_timeout() {
+        assert_eq!(parse_key(&[ESC], false), None);
+        assert_eq!(key(&[ESC]), Key::plain(KeyCode::Esc));
+        assert_eq!(parse_key(b"\x1b[", false), None);
+    }
+
+    #[test]
+    fn csi_and_ss3_sequences() {
+        assert_eq!(key(b"\x1b[A"), Key::plain(KeyCode::Up));
+        assert_eq!(key(b"\x1bOD"), Key::plain(KeyCode::Left));
+        assert_eq!(key(b"\x1b[3~"), Key::plain(KeyCode::Delete));
+        assert_eq!(key(b"\x1b[6~"), Key::plain(KeyCode::PageDown));
+        assert_eq!(key(b"\x1b[24~"), Key::plain(KeyCode::F(12)));
+        assert_eq!(key(b"\x1bOP"), Key::plain(KeyCode::F(1)));
+        assert_eq!(key(b"\x1b[1;5C"), Key::new(KeyCode::Right, Modifiers::CTRL));
+        // the old summed-bytes check could not tell these apart from other input
+        assert_eq!(parse_key(b"\x1b[Bj", false).unwrap().1, 3);
+    }
+
+    #[test]
+    fn alt_prefix() {
+        assert_eq!(key(b"\x1bx"), Key::new(KeyCode::Char('x'), Modifiers::ALT));
+    }
+
+    #[test]
+    fn dos_keyboard_strings() {
+        assert_eq!(key(&[0, 59]), Key::plain(KeyCode::F(1)));
+        assert_eq!(key(&[0, 94]), Key::new(KeyCode::F(1), Modifiers::CTRL));
+        assert_eq!(key(&[224, 72]), Key::plain(KeyCode::Up));
+        assert_eq!(key(&[224, 163]), Key::new(KeyCode::Delete, Modifiers::ALT));
+    }
+
+    #[test]
+    fn nul_before_a_key_is_ctrl_space() {
+        let j = Key::plain(KeyCode::Char('j'));
+        // a NUL that came on its own doesn't wait for a code, which would have come with it
+        assert_eq!(parse_key(&[0], false), Some((Key::ctrl(' '), 1)));
+        // keys that never came from a terminal don't hold keyboard strings, so this isn't alt-F3
+        assert_eq!(parse_keys(&[0, b'j']), vec![Key::ctrl(' '), j]);
+        assert_eq!(
+            decode_keys(&encode_keys(&[Key::ctrl(' '), j])),
+            [Key::ctrl(' '), j]
+        );
+    }
+
+    #[test]
+    fn keys_as_text() {
+        let keys = vec![
+            Key::plain(KeyCode::Char('i')),
+            Key::plain(KeyCode::Char('é')),
+            Key::plain(KeyCode::Esc),
+            Key::plain(KeyCode::Char('O')),
+            Key::plain(KeyCode::Esc),
+            Key::plain(KeyCode::Char('[')),
+            Key::ctrl('r'),
+            Key::plain(KeyCode::Enter),
+            Key::new(KeyCode::Left, Modifiers::CTRL),
+            Key::new(KeyCode::Char('x'), Modifiers::ALT),
+            Key::plain(KeyCode::F(5)),
+            Key::plain(KeyCode::Delete),
+            Key::plain(KeyCode::F(2)),
+        ];
+        let text = encode_keys(&keys);
+        assert_eq!(&text[..7], "i\u{e9}\x1bO\x1b[");
+        assert_eq!(decode_keys(&text), keys);
+    }
+
+    #[test]
+    fn sequence_of_keys() {
+        let keys = parse_keys(b"ix\x1b");
+        assert_eq!(
+            keys,
+            vec![
+                Key::plain(KeyCode::Char('i')),
+                Key::plain(KeyCode::Char('x')),
+                Key::plain(KeyCode::Esc),
+            ]
+        );
+    }
+}
@@ -1,6 +1,10 @@
+pub mod clipboard;
 pub mod cursor;
-pub use cursor::{Colors, Cursor};
-use libc;
+pub mod input;
+pub mod screen;
+pub use cursor::Colors;
+pub use input::{decode_keys, encode_keys, Key, KeyCode, KeyReader, Modifiers};
+pub use screen::{char_width, Attributes, Cell, CursorShape, Screen, Style};
 use std::io::{self, Write};
 use termios::{
     tcsetattr, Termios, BRKINT, CS8, CSIZE, ECHO, ECHONL, ICANON, ICRNL, IEXTEN, IGNBRK, IGNCR,
@@ -47,7 +51,7 @@ pub fn enable_raw_mode() -> Termios {
     original_termios
 }
 pub fn disable_raw_mode(original_settings: &Termios) {
-    tcsetattr(libc::STDIN_FILENO, TCSANOW, &original_settings)
+    tcsetattr(libc::STDIN_FILENO, TCSANOW, original_settings)
         .unwrap_or_else(|e| panic!("std io error, {e}"))
 }
 
@@ -0,0 +1,438 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::ops::BitOr;
+
+/// how a cell is drawn besides its colors, as a small bit set
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+pub struct Attributes(u8);
+
+impl Attributes {
+    pub const NONE: Attributes = Attributes(0);
+    pub const BOLD: Attributes = Attributes(1);
+    pub const REVERSE: Attributes = Attributes(2);
+
+    pub fn contains(&self, other: Attributes) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Attributes {
+    type Output = Attributes;
+
+    fn bitor(self, rhs: Attributes) -> Attributes {
+        Attributes(self.0 | rhs.0)
+    }
+}
+
+/// the colors and attributes text is drawn with. None is the terminal's own color
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+pub struct Style {
+    pub fg: Option<i32>,
+    pub bg: Option<i32>,
+    pub attributes: Attributes,
+}
+
+impl Style {
+    pub const PLAIN: Style = Style {
+        fg: None,
+        bg: None,
+        attributes: Attributes::NONE,
+    };
+
+    pub fn background(color: i32) -> Style {
+        Style {
+            bg: Some(color),
+            ..Style::PLAIN
+        }
+    }
+    pub fn reverse() -> Style {
+        Style {
+            attributes: Attributes::REVERSE,
+            ..Style::PLAIN
+        }
+    }
+
+    /// the select graphic rendition sequence that sets this style from scratch
+    fn sgr(&self) -> String {
+        let mut sgr = String::from("\x1b[0");
+        if self.attributes.contains(Attributes::BOLD) {
+            sgr.push_str(";1");
+        }
+        if self.attributes.contains(Attributes::REVERSE) {
+            sgr.push_str(";7");
+        }
+        if let Some(fg) = self.fg {
+            let _ = write!(sgr, ";38;5;{fg}");
+        }
+        if let Some(bg) = self.bg {
+            let _ = write!(sgr, ";48;5;{bg}");
+        }
+        sgr.push('m');
+        sgr
+    }
+}
+
+/// one character on the screen and how it is drawn. a wide char takes up two cells, the second
+/// of which holds Cell::CONTINUATION
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct Cell {
+    pub c: char,
+    /// a combining mark drawn over `c`, like the accent of an e followed by U+0301
+    pub mark: Option<char>,
+    pub style: Style,
+}
+
+impl Cell {
+    const BLANK: Cell = Cell {
+        c: ' ',
+        mark: None,
+        style: Style::PLAIN,
+    };
+    /// what the right half of a wide char holds. the terminal draws it along with the left half,
+    /// so it is never sent
+    pub const CONTINUATION: char = '\0';
+
+    fn new(c: char, style: Style) -> Cell {
+        Cell {
+            c,
+            mark: None,
+            style,
+        }
+    }
+}
+
+/// the columns `c` takes up on a terminal. 2 for the wide chars of east asian scripts and for
+/// emoji, 0 for combining marks and the other chars that are drawn over the one before them,
+/// 1 for everything else
+pub fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x0300..=0x036F
+        | 0x0483..=0x0489
+        | 0x0591..=0x05BD
+        | 0x0610..=0x061A
+        | 0x064B..=0x065F
+        | 0x1AB0..=0x1AFF
+        | 0x1DC0..=0x1DFF
+        | 0x200B..=0x200F
+        | 0x20D0..=0x20FF
+        | 0xFE00..=0xFE0F
+        | 0xFE20..=0xFE2F
+        | 0xE0100..=0xE01EF => 0,
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F680..=0x1F6FF
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x2FFFD
+        | 0x30000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+pub enum CursorShape {
+    #[default]
+    Block,
+    Bar,
+}
+
+/// what is on the terminal and the next frame being drawn over it. a frame is drawn from
+/// scratch into the cells, and flushing it only sends the terminal what changed since the
+/// frame before
+#[derive(Debug)]
+pub struct Screen {
+    rows: usize,
+    cols: usize,
+    cells: Vec<Cell>,
+    /// the cells the terminal shows, None until the first frame has been flushed
+    shown: Option<Vec<Cell>>,
+    /// 0-indexed (row, col) the cursor is left at once the frame is flushed
+    cursor: (usize, usize),
+    cursor_shape: CursorShape,
+    shown_shape: Option<CursorShape>,
+}
+
+impl Screen {
+    pub fn new(rows: usize, cols: usize) -> Screen {
+        Screen {
+            rows,
+            cols,
+            cells: vec![Cell::BLANK; rows * cols],
+            shown: None,
+            cursor: (0, 0),
+            cursor_shape: CursorShape::Block,
+            shown_shape: None,
+        }
+    }
+    /// a screen the size of the terminal
+    pub fn from_terminal() -> Screen {
+        let term_attr = crate::get_terminal_size();
+        Screen::new(term_attr.ws_row as usize, term_attr.ws_col as usize)
+    }
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// starts a new frame, blank and the size of the terminal. when the terminal was resized
+    /// nothing on it can be trusted and the whole frame is sent
+    pub fn begin_frame(&mut self) {
+        let term_attr = crate::get_terminal_size();
+        self.resize(term_attr.ws_row as usize, term_attr.ws_col as usize);
+        self.cells.fill(Cell::BLANK);
+    }
+    pub fn resize(&mut self, rows: usize, cols: usize) {
+        if (rows, cols) != (self.rows, self.cols) {
+            *self = Screen {
+                cursor_shape: self.cursor_shape,
+                ..Screen::new(rows, cols)
+            };
+        }
+    }
+    /// makes the next flush send the whole frame, for when something else has drawn over it
+    pub fn invalidate(&mut self) {
+        self.shown = None;
+        self.shown_shape = None;
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> Option<&Cell> {
+        (col < self.cols)
+            .then(|| self.cells.get(row * self.cols + col))
+            .flatten()
+    }
+    /// puts `c` at the 0-indexed `row` and `col`, anything off the screen is dropped. a control
+    /// char, which would move the terminal's cursor, is put as a '?'. a wide char takes `col` and
+    /// the cell after it, or is put as a blank when that is off the screen. a combining mark is
+    /// drawn over the char before `col`, which keeps the first mark it is given
+    pub fn put(&mut self, row: usize, col: usize, c: char, style: Style) {
+        let c = if c.is_control() { '?' } else { c };
+        if row >= self.rows {
+            return;
+        }
+        match char_width(c) {
+            0 => {
+                let Some(mut col) = col.checked_sub(1).filter(|col| *col < self.cols) else {
+                    return;
+                };
+                if self.cells[row * self.cols + col].c == Cell::CONTINUATION && col > 0 {
+                    col -= 1;
+                }
+                let cell = &mut self.cells[row * self.cols + col];
+                cell.mark = cell.mark.or(Some(c));
+            }
+            _ if col >= self.cols => (),
+            2 if col + 1 >= self.cols => self.set(row, col, Cell::new(' ', style)),
+            2 => {
+                self.set(row, col, Cell::new(c, style));
+                self.set(row, col + 1, Cell::new(' ', style));
+                self.cells[row * self.cols + col + 1] = Cell::new(Cell::CONTINUATION, style);
+            }
+            _ => self.set(row, col, Cell::new(c, style)),
+        }
+    }
+    /// writes `cell` at `row` and `col`. a wide char it is written over half of is blanked, so no
+    /// half of one is left behind
+    fn set(&mut self, row: usize, col: usize, cell: Cell) {
+        let index = row * self.cols + col;
+        let old = self.cells[index];
+        if old.c == Cell::CONTINUATION && col > 0 {
+            self.cells[index - 1] = Cell::new(' ', self.cells[index - 1].style);
+        } else if col + 1 < self.cols && self.cells[index + 1].c == Cell::CONTINUATION {
+            self.cells[index + 1] = Cell::new(' ', self.cells[index + 1].style);
+        }
+        self.cells[index] = cell;
+    }
+    /// writes `text` from `row` and `col` on, cut off at the edge of the screen. returns the
+    /// columns it took up
+    pub fn put_str(&mut self, row: usize, col: usize, text: &str, style: Style) -> usize {
+        let mut written = 0;
+        for c in text.chars() {
+            let width = if c.is_control() { 1 } else { char_width(c) };
+            if col + written + width > self.cols {
+                break;
+            }
+            self.put(row, col + written, c, style);
+            written += width;
+        }
+        written
+    }
+    /// fills `width` cells of `row` from `col` on with blanks in `style`
+    pub fn fill(&mut self, row: usize, col: usize, width: usize, style: Style) {
+        for i in 0..width {
+            self.put(row, col + i, ' ', style);
+        }
+    }
+    /// where the cursor is left, 0-indexed
+    pub fn set_cursor(&mut self, row: usize, col: usize) {
+        self.cursor = (row, col);
+    }
+    pub fn set_cursor_shape(&mut self, shape: CursorShape) {
+        self.cursor_shape = shape;
+    }
+
+    /// the escape sequences that turn what the terminal shows into the frame. cells that didn't
+    /// change are skipped over, the cursor is only moved where a run of changes starts and the
+    /// style only set where it differs from the cell written before
+    pub fn diff(&self) -> String {
+        let mut out = String::new();
+        // where the terminal's cursor is after the last write, and the style it writes with
+        let mut at: Option<(usize, usize)> = None;
+        let mut pen: Option<Style> = None;
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                let index = row * self.cols + col;
+                let cell = self.cells[index];
+                if cell.c == Cell::CONTINUATION {
+                    // the terminal already moved past it when the left half was written
+                    if at == Some((row, col)) {
+                        at = Some((row, col + 1));
+                    }
+                    continue;
+                }
+                if self
+                    .shown
+                    .as_ref()
+                    .is_some_and(|shown| shown[index] == cell)
+                {
+                    continue;
+                }
+                if at != Some((row, col)) {
+                    let _ = write!(out, "\x1b[{};{}H", row + 1, col + 1);
+                }
+                if pen != Some(cell.style) {
+                    out.push_str(&cell.style.sgr());
+                    pen = Some(cell.style);
+                }
+                out.push(cell.c);
+                out.extend(cell.mark);
+                at = Some((row, col + 1));
+            }
+        }
+        if pen.is_some_and(|pen| pen != Style::PLAIN) {
+            out.push_str("\x1b[0m");
+        }
+        if self.shown_shape != Some(self.cursor_shape) {
+            out.push_str(match self.cursor_shape {
+                CursorShape::Block => "\x1b[0 q",
+                CursorShape::Bar => "\x1b[6 q",
+            });
+        }
+        let (row, col) = self.cursor;
+        if at != Some((row, col)) || out.is_empty() {
+            let _ = write!(out, "\x1b[{};{}H", row + 1, col + 1);
+        }
+        out
+    }
+
+    /// sends the frame to the terminal in a single write, which is then taken as showing it
+    pub fn flush(&mut self) {
+        let diff = self.diff();
+        let mut stdout = io::stdout().lock();
+        write!(stdout, "{diff}").unwrap_or_else(|e| panic!("failed io operation: {e}"));
+        stdout
+            .flush()
+            .unwrap_or_else(|e| panic!("io error occurred during flush: {e}"));
+        self.shown = Some(self.cells.clone());
+        self.shown_shape = Some(self.cursor_shape);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flushed(screen: &mut Screen) {
+        screen.shown = Some(screen.cells.clone());
+        screen.shown_shape = Some(screen.cursor_shape);
+    }
+
+    #[test]
+    fn first_frame_is_sent_whole() {
+        let mut screen = Screen::new(2, 3);
+        screen.put_str(0, 0, "abcd", Style::PLAIN);
+        // every row starts with a move rather than trusting the terminal to wrap
+        assert_eq!(
+            screen.diff(),
+            "\x1b[1;1H\x1b[0mabc\x1b[2;1H   \x1b[0 q\x1b[1;1H"
+        );
+        assert_eq!(screen.get(0, 3), None);
+    }
+
+    #[test]
+    fn control_chars_take_a_single_cell() {
+        let mut screen = Screen::new(1, 4);
+        screen.put_str(0, 0, "a\tb\x1b", Style::PLAIN);
+        assert_eq!(screen.diff(), "\x1b[1;1H\x1b[0ma?b?\x1b[0 q\x1b[1;1H");
+    }
+
+    #[test]
+    fn wide_chars_and_combining_marks() {
+        let mut screen = Screen::new(2, 5);
+        assert_eq!(screen.put_str(0, 0, "a漢e\u{301}b", Style::PLAIN), 5);
+        assert_eq!(
+            screen.get(0, 2).map(|cell| cell.c),
+            Some(Cell::CONTINUATION)
+        );
+        assert_eq!(screen.get(0, 3).and_then(|cell| cell.mark), Some('\u{301}'));
+        // a wide char that doesn't fit on the row isn't split over it
+        assert_eq!(screen.put_str(1, 3, "x字", Style::PLAIN), 1);
+        assert_eq!(
+            screen.diff(),
+            "\x1b[1;1H\x1b[0ma漢e\u{301}b\x1b[2;1H   x \x1b[0 q\x1b[1;1H"
+        );
+
+        // writing over half of a wide char blanks the other half
+        flushed(&mut screen);
+        screen.put(0, 2, 'z', Style::PLAIN);
+        screen.set_cursor(0, 3);
+        assert_eq!(screen.diff(), "\x1b[1;2H\x1b[0m z");
+    }
+
+    #[test]
+    fn only_changes_are_sent() {
+        let mut screen = Screen::new(2, 10);
+        screen.put_str(0, 0, "hello", Style::PLAIN);
+        flushed(&mut screen);
+        assert_eq!(screen.diff(), "\x1b[1;1H");
+
+        // a run of changes is written with a single cursor move
+        screen.put_str(0, 1, "EL", Style::PLAIN);
+        screen.put(1, 4, 'x', Style::PLAIN);
+        screen.set_cursor(1, 5);
+        assert_eq!(screen.diff(), "\x1b[1;2H\x1b[0mEL\x1b[2;5Hx");
+    }
+
+    #[test]
+    fn styles_are_set_where_they_change() {
+        let mut screen = Screen::new(1, 6);
+        flushed(&mut screen);
+        let red = Style::background(9);
+        screen.put_str(0, 0, "ab", red);
+        screen.put_str(0, 2, "cd", Style::reverse());
+        screen.set_cursor(0, 4);
+        screen.set_cursor_shape(CursorShape::Bar);
+        assert_eq!(
+            screen.diff(),
+            "\x1b[1;1H\x1b[0;48;5;9mab\x1b[0;7mcd\x1b[0m\x1b[6 q"
+        );
+    }
+
+    #[test]
+    fn resizing_sends_everything_again() {
+        let mut screen = Screen::new(1, 2);
+        flushed(&mut screen);
+        screen.resize(1, 3);
+        assert_eq!(screen.diff(), "\x1b[1;1H\x1b[0m   \x1b[0 q\x1b[1;1H");
+    }
+}